@@ -3,16 +3,29 @@ use std::{
     time::{Duration, Instant},
 };
 
-use crossterm::event::{self, Event, KeyCode, KeyEvent};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     DefaultTerminal, Frame,
     layout::{Constraint, Layout},
     style::{Color, Style},
-    widgets::{Block, Borders, Widget},
+    widgets::{Block, Borders, Paragraph, Widget},
 };
 use tui_logger::{TuiLoggerWidget, TuiWidgetState};
 
-use crate::engine::{AudioEngine, AudioEngineState};
+use crate::{
+    engine::{AudioEngine, AudioEngineState},
+    instrument_view::{InstrumentEditor, InstrumentView},
+    instruments::InstrumentKind,
+    metronome::Metronome,
+    mixer::DEFAULT_STEP_DIVISION,
+    piano_roll::{PianoRoll, PianoRollView},
+    sequencer::division_label,
+    step_grid::{StepGrid, StepGridView},
+    tempo::TapTempo,
+    tracker::{Tracker, TrackerView},
+};
+
+const BPM_NUDGE: f32 = 1.0;
 
 pub struct App {
     state: AppState,
@@ -20,6 +33,11 @@ pub struct App {
     current_window: AppWindow,
     last_update: Instant,
     debug_state: TuiWidgetState,
+    tap_tempo: TapTempo,
+    tracker: TrackerView,
+    piano_roll: PianoRollView,
+    step_grid: StepGridView,
+    instrument: InstrumentView,
 }
 
 #[derive(PartialEq, Default)]
@@ -35,6 +53,10 @@ enum AppWindow {
     #[default]
     Mixer,
     Sequencer,
+    Tracker,
+    PianoRoll,
+    Grid,
+    Instrument,
     Debug,
 }
 
@@ -47,6 +69,11 @@ impl App {
             audio_engine,
             last_update: Instant::now(),
             debug_state,
+            tap_tempo: TapTempo::new(),
+            tracker: TrackerView::new(),
+            piano_roll: PianoRollView::new(),
+            step_grid: StepGridView::new(),
+            instrument: InstrumentView::new(),
         })
     }
     /// runs the application's main loop until the user quits
@@ -54,7 +81,15 @@ impl App {
         {
             let mixer = self.audio_engine.get_mixer();
             if let Ok(mut mixer) = mixer.lock() {
-                mixer.add_track(0.3, "Kick".into(), 16, 4, self.get_sample_rate());
+                let length = mixer.default_pattern_length(DEFAULT_STEP_DIVISION);
+                mixer.add_track(
+                    0.3,
+                    "Kick".into(),
+                    length,
+                    DEFAULT_STEP_DIVISION,
+                    self.get_sample_rate(),
+                );
+                mixer.set_selected_instrument(InstrumentKind::Kick);
             }
         }
 
@@ -63,6 +98,10 @@ impl App {
         }
 
         while self.state == AppState::Running {
+            if let Ok(mut mixer) = self.audio_engine.get_mixer().lock() {
+                mixer.collect_takes();
+            }
+
             terminal.draw(|frame| {
                 self.draw(frame);
             })?;
@@ -102,10 +141,12 @@ impl App {
         let header = Block::default()
             .borders(Borders::ALL)
             .title(format!(
-                " Terminal DAW | {} | {} | {:.0} BPM ",
+                " Terminal DAW | {} | {} | {:.0} BPM | Bar {} | {} ",
                 self.current_window_title(),
                 self.playback_status(),
-                self.get_bpm()
+                self.get_bpm(),
+                self.get_bar() + 1,
+                self.metronome_status()
             ))
             .title_style(Style::default().fg(Color::Cyan));
 
@@ -117,13 +158,19 @@ impl App {
         match self.current_window {
             AppWindow::Mixer => self.render_mixer(frame, content),
             AppWindow::Sequencer => self.render_sequencer(frame, content),
+            AppWindow::Tracker => self.render_tracker(frame, content),
+            AppWindow::PianoRoll => self.render_piano_roll(frame, content),
+            AppWindow::Grid => self.render_step_grid(frame, content),
+            AppWindow::Instrument => self.render_instrument(frame, content),
             AppWindow::Debug => self.render_debug_window(frame, debug_state),
         }
 
-        // Footer with help
-        let footer = Block::default()
-            .borders(Borders::ALL)
-            .title(" [Space] Play/Stop | [Tab] Window | [↑↓] Volume | [Q] Quit ");
+        // Footer with global help on the border and window help inside
+        let footer = Paragraph::new(self.current_window_help()).block(
+            Block::default().borders(Borders::ALL).title(
+                " [Space] Play/Stop | [Tab] Window | [+-] BPM | [B] Tap | [K] Click | [N] Count-in | [Ctrl+Z/Y] Undo/Redo | [Q] Quit ",
+            ),
+        );
         frame.render_widget(footer, chunks[2]);
     }
     // --- Window rendering ---
@@ -143,16 +190,197 @@ impl App {
     }
 
     fn render_sequencer(&self, frame: &mut Frame, area: ratatui::prelude::Rect) {
-        let block = Block::default().title("Sequencer").borders(Borders::ALL);
-        let inner = block.inner(area);
-        frame.render_widget(block, area);
+        let mixer = self.audio_engine.get_mixer();
+        if let Ok(mixer_guard) = &mut mixer.lock() {
+            let clipboard = match mixer_guard.clipboard().len() {
+                0 => String::new(),
+                len => format!(" | Clipboard {}", len),
+            };
+            let Some(track) = mixer_guard.selected_track() else {
+                let block = Block::default().title("Sequencer").borders(Borders::ALL);
+                frame.render_widget(block, area);
+                return;
+            };
 
+            let sequencer = track.sequencer(); // You'll need a getter method
+            let seed = sequencer
+                .random_seed()
+                .map(|seed| format!(" | Seed {}", seed))
+                .unwrap_or_default();
+            let snap = if sequencer.quantise_entry() {
+                " | Snap"
+            } else {
+                ""
+            };
+            let recorder = track.recorder();
+            let piano = match track.piano() {
+                piano if piano.is_step_recording() => format!(" | Keys C{} Step", piano.octave()),
+                piano if piano.is_live_recording() => format!(
+                    " | Keys C{} Live {} {:.0}%",
+                    piano.octave(),
+                    recorder.mode(),
+                    recorder.strength() * 100.0
+                ),
+                piano if piano.is_enabled() => format!(" | Keys C{}", piano.octave()),
+                _ => String::new(),
+            };
+            let processors: String = track
+                .note_processors()
+                .iter()
+                .map(|processor| format!(" | {}", processor.describe()))
+                .collect();
+            let block = Block::default()
+                .title(format!(
+                    "Sequencer | {} | {}{} | {} | {} steps | {}{}{}{}{} ",
+                    track.get_name(),
+                    sequencer.key(),
+                    snap,
+                    sequencer.time_signature(),
+                    sequencer.pattern_len(),
+                    division_label(sequencer.step_division()),
+                    seed,
+                    clipboard,
+                    piano,
+                    processors
+                ))
+                .borders(Borders::ALL);
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            frame.render_widget(sequencer, inner);
+        } else {
+            let block = Block::default().title("Sequencer").borders(Borders::ALL);
+            frame.render_widget(block, area);
+        }
+    }
+
+    fn render_tracker(&self, frame: &mut Frame, area: ratatui::prelude::Rect) {
         let mixer = self.audio_engine.get_mixer();
         if let Ok(mixer_guard) = &mut mixer.lock()
             && let Some(track) = mixer_guard.selected_track()
         {
-            let sequencer = track.sequencer(); // You'll need a getter method
-            frame.render_widget(sequencer, inner);
+            let sequencer = track.sequencer();
+            let mode = if self.tracker.is_hex() { "Hex" } else { "Dec" };
+            let follow = if self.tracker.is_following() {
+                " | Follow"
+            } else {
+                ""
+            };
+            let editing = if self.tracker.is_editing() {
+                " | Edit"
+            } else {
+                ""
+            };
+            let block = Block::default()
+                .title(format!(
+                    "Tracker | {} | {} steps | {} | {}{}{} ",
+                    track.get_name(),
+                    sequencer.pattern_len(),
+                    division_label(sequencer.step_division()),
+                    mode,
+                    follow,
+                    editing
+                ))
+                .borders(Borders::ALL);
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            frame.render_widget(
+                Tracker {
+                    view: &self.tracker,
+                    sequencer,
+                },
+                inner,
+            );
+        } else {
+            let block = Block::default().title("Tracker").borders(Borders::ALL);
+            frame.render_widget(block, area);
+        }
+    }
+
+    fn render_piano_roll(&self, frame: &mut Frame, area: ratatui::prelude::Rect) {
+        let mixer = self.audio_engine.get_mixer();
+        if let Ok(mixer_guard) = &mut mixer.lock()
+            && let Some(track) = mixer_guard.selected_track()
+        {
+            let sequencer = track.sequencer();
+            let block = Block::default()
+                .title(format!(
+                    "Piano Roll | {} | {} | {} steps | {} | Length {} ",
+                    track.get_name(),
+                    self.piano_roll.pitch(),
+                    sequencer.pattern_len(),
+                    division_label(sequencer.step_division()),
+                    self.piano_roll.length()
+                ))
+                .borders(Borders::ALL);
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            frame.render_widget(
+                PianoRoll {
+                    view: &self.piano_roll,
+                    sequencer,
+                },
+                inner,
+            );
+        } else {
+            let block = Block::default().title("Piano Roll").borders(Borders::ALL);
+            frame.render_widget(block, area);
+        }
+    }
+
+    fn render_step_grid(&self, frame: &mut Frame, area: ratatui::prelude::Rect) {
+        let mixer = self.audio_engine.get_mixer();
+        if let Ok(mixer_guard) = mixer.lock() {
+            let block = Block::default()
+                .title(format!(
+                    "Grid | {} tracks | Step {} ",
+                    mixer_guard.ordered_tracks().count(),
+                    self.step_grid.column() + 1
+                ))
+                .borders(Borders::ALL);
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            frame.render_widget(
+                StepGrid {
+                    view: &self.step_grid,
+                    mixer: &mixer_guard,
+                },
+                inner,
+            );
+        } else {
+            let block = Block::default().title("Grid").borders(Borders::ALL);
+            frame.render_widget(block, area);
+        }
+    }
+
+    fn render_instrument(&self, frame: &mut Frame, area: ratatui::prelude::Rect) {
+        let mixer = self.audio_engine.get_mixer();
+        if let Ok(mixer_guard) = &mut mixer.lock()
+            && let Some(track) = mixer_guard.selected_track()
+        {
+            let name = track
+                .instrument()
+                .map(|instrument| instrument.get_name().to_string())
+                .unwrap_or_else(|| "No Instrument".to_string());
+            let block = Block::default()
+                .title(format!(
+                    "Instrument | {} | {} | {} ",
+                    track.get_name(),
+                    track.instrument_kind(),
+                    name
+                ))
+                .borders(Borders::ALL);
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+            frame.render_widget(
+                InstrumentEditor {
+                    view: &self.instrument,
+                    track,
+                },
+                inner,
+            );
+        } else {
+            let block = Block::default().title("Instrument").borders(Borders::ALL);
+            frame.render_widget(block, area);
         }
     }
 
@@ -170,7 +398,11 @@ impl App {
     fn next_window(&mut self) {
         self.current_window = match self.current_window {
             AppWindow::Mixer => AppWindow::Sequencer,
-            AppWindow::Sequencer => AppWindow::Mixer,
+            AppWindow::Sequencer => AppWindow::Tracker,
+            AppWindow::Tracker => AppWindow::PianoRoll,
+            AppWindow::PianoRoll => AppWindow::Grid,
+            AppWindow::Grid => AppWindow::Instrument,
+            AppWindow::Instrument => AppWindow::Mixer,
             AppWindow::Debug => AppWindow::Mixer,
         };
     }
@@ -178,27 +410,84 @@ impl App {
     //TODO: implement switching window tabs
     fn _previous_window(&mut self) {
         self.current_window = match self.current_window {
-            AppWindow::Mixer => AppWindow::Sequencer,
+            AppWindow::Mixer => AppWindow::Instrument,
             AppWindow::Sequencer => AppWindow::Mixer,
+            AppWindow::Tracker => AppWindow::Sequencer,
+            AppWindow::PianoRoll => AppWindow::Tracker,
+            AppWindow::Grid => AppWindow::PianoRoll,
+            AppWindow::Instrument => AppWindow::Grid,
             AppWindow::Debug => AppWindow::Mixer,
         };
     }
 
+    fn current_window_help(&self) -> &'static str {
+        match self.current_window {
+            AppWindow::Mixer => {
+                "[←→] Track | [↑↓] Volume | [T] Add | [R] Remove | [M] Meter | [S] Root | [Shift+S] Scale | [O] Oscillator quality | [E] Prompt | [C] Tempo change (bar bpm [ramp]) | [Shift+C] Remove tempo change"
+            }
+            AppWindow::Sequencer => {
+                "[←→] Step | [E] Prompt | [I] Note | [Shift+E] Euclid | [R] Random | [Shift+R] Random settings | [Shift+S] Key | [Shift+Q] Snap entry | [Shift+P] Quantise | [V/Shift+←→] Select | [Ctrl+C/X/V/D] Copy/Cut/Paste/Duplicate | [T/Shift+T] Semitone | [O/Shift+O] Octave | [,.] Rotate | [X] Transform | [A] Arp | [Shift+A] Arp settings | [P] Keyboard piano (zsx…/q2w…, ↑↓ Octave, Enter Off/Step/Live record, Del Rest, Esc Exit) | [L] Overdub/Replace | [Shift+L] Record quantise strength | [M] Meter | [[ ]] Length | [{ }] Division"
+            }
+            AppWindow::Tracker => {
+                "[↑↓] Row | [PgUp/PgDn] Page | [←→] Column | [Enter] Edit | [0-F] Value | [+-] Nudge | [R/P/W] Retrigger/Chance/Wait | [Del] Clear | [H] Hex/Dec | [F] Follow"
+            }
+            AppWindow::PianoRoll => {
+                "[←→] Step | [↑↓] Pitch | [PgUp/PgDn] Octave | [Enter] Add/Remove | [Del] Remove | [Shift+←→] Resize | [Alt+Arrows] Move | [[ ]] New note length"
+            }
+            AppWindow::Grid => "[←→] Step | [↑↓] Track | [Enter/X] Toggle step | [Del] Clear step",
+            AppWindow::Instrument => {
+                "[↑↓] Parameter | [←→] Adjust | [Shift+←→] Coarse | [PgUp/PgDn] Track | [I/Shift+I] Instrument | [E] Prompt | [L] Load file | [W] Save preset | [R] Load preset"
+            }
+            AppWindow::Debug => "",
+        }
+    }
+
     fn current_window_title(&self) -> &'static str {
         match self.current_window {
             AppWindow::Mixer => "Mixer",
             AppWindow::Sequencer => "Sequencer",
+            AppWindow::Tracker => "Tracker",
+            AppWindow::PianoRoll => "Piano Roll",
+            AppWindow::Grid => "Grid",
+            AppWindow::Instrument => "Instrument",
             AppWindow::Debug => "Debug logs",
         }
     }
 
     fn playback_status(&self) -> String {
+        let counting_in = self
+            .audio_engine
+            .get_mixer()
+            .lock()
+            .map(|m| m.is_counting_in())
+            .unwrap_or(false);
+
         match self.audio_engine.state() {
+            AudioEngineState::Playing if counting_in => "● Count-in".to_string(),
             AudioEngineState::Playing => "▶ Playing".to_string(),
             AudioEngineState::Stopped => "⏹ Stopped".to_string(),
         }
     }
 
+    fn metronome_status(&self) -> String {
+        let mixer = self.audio_engine.get_mixer();
+        let Ok(mixer) = mixer.lock() else {
+            return String::new();
+        };
+        let metronome = mixer.metronome();
+
+        let click = if metronome.is_enabled() {
+            format!("{} {:.0}%", metronome.sound(), metronome.level() * 100.0)
+        } else {
+            "Off".to_string()
+        };
+
+        match metronome.count_in_bars() {
+            0 => format!("Click {}", click),
+            bars => format!("Click {} | Count-in {}", click, bars),
+        }
+    }
+
     fn get_bpm(&self) -> f32 {
         self.audio_engine
             .get_mixer()
@@ -207,27 +496,125 @@ impl App {
             .unwrap_or(0.0)
     }
 
+    fn get_bar(&self) -> u32 {
+        self.audio_engine
+            .get_mixer()
+            .lock()
+            .map(|m| m.bar_position() as u32)
+            .unwrap_or(0)
+    }
+
+    fn nudge_bpm(&mut self, amount: f32) {
+        if let Ok(mut mixer) = self.audio_engine.get_mixer().lock() {
+            let bpm = mixer.bpm() + amount;
+            mixer.set_bpm(bpm);
+        }
+    }
+
+    fn tap_tempo(&mut self) {
+        if let Some(bpm) = self.tap_tempo.tap(Instant::now())
+            && let Ok(mut mixer) = self.audio_engine.get_mixer().lock()
+        {
+            mixer.set_bpm(bpm.round());
+        }
+    }
+
+    fn with_metronome(&mut self, action: impl FnOnce(&mut Metronome)) {
+        if let Ok(mut mixer) = self.audio_engine.get_mixer().lock() {
+            action(mixer.metronome_mut());
+        }
+    }
+
+    //True when the window uses the key itself, like a note on the keyboard piano
+    fn takes_key(&self, key_event: KeyEvent) -> bool {
+        match self.current_window {
+            AppWindow::Sequencer => self
+                .audio_engine
+                .get_mixer()
+                .lock()
+                .ok()
+                .and_then(|mut m| m.selected_track().map(|t| t.takes_key(key_event)))
+                .unwrap_or(false),
+            _ => self.is_typing(),
+        }
+    }
+
+    //True while a window is taking text input, global keys are ignored then
+    fn is_typing(&self) -> bool {
+        match self.current_window {
+            AppWindow::Sequencer => self
+                .audio_engine
+                .get_mixer()
+                .lock()
+                .ok()
+                .and_then(|mut m| m.selected_track().map(|t| t.is_typing()))
+                .unwrap_or(false),
+            AppWindow::Mixer => self
+                .audio_engine
+                .get_mixer()
+                .lock()
+                .is_ok_and(|m| m.is_editing()),
+            AppWindow::Tracker => self.tracker.is_editing(),
+            AppWindow::Instrument => self.instrument.is_editing(),
+            _ => false,
+        }
+    }
+
     fn handle_keys(&mut self, key_event: KeyEvent) {
+        //Undo and redo work in every window, but not while typing in a prompt
+        if key_event.modifiers.contains(KeyModifiers::CONTROL)
+            && matches!(key_event.code, KeyCode::Char('z') | KeyCode::Char('y'))
+            && !self.is_typing()
+        {
+            if let Ok(mut mixer) = self.audio_engine.get_mixer().lock() {
+                match key_event.code {
+                    KeyCode::Char('z') => mixer.undo(),
+                    _ => mixer.redo(),
+                }
+            }
+            return;
+        }
+
         if let Ok(mut mixer) = self.audio_engine.get_mixer().lock() {
             //Handle context
             match self.current_window {
                 AppWindow::Mixer => mixer.handle_keyboard_input(key_event),
-                AppWindow::Sequencer => {
-                    if let Some(track) = mixer.selected_track() {
-                        let sequencer = track.sequencer_mut();
-
-                        sequencer.handle_keyboard_input(key_event);
-                    }
-                }
-                AppWindow::Debug => {}
+                AppWindow::Sequencer => mixer.handle_sequencer_keyboard_input(key_event),
+                AppWindow::Tracker => mixer.edit_selected_pattern(|sequencer| {
+                    self.tracker.handle_keyboard_input(key_event, sequencer)
+                }),
+                AppWindow::PianoRoll => mixer.edit_selected_pattern(|sequencer| {
+                    self.piano_roll.handle_keyboard_input(key_event, sequencer)
+                }),
+                AppWindow::Grid => self.step_grid.handle_keyboard_input(key_event, &mut mixer),
+                AppWindow::Instrument | AppWindow::Debug => {}
             }
         }
 
-        //TODO: fix implementation so that it does not go to debug when typing in the note
+        //The instrument view locks the mixer itself, loading a file must not hold the lock
+        if matches!(self.current_window, AppWindow::Instrument) {
+            self.instrument
+                .handle_keyboard_input(key_event, &self.audio_engine.get_mixer());
+        }
+
+        //Control combinations belong to the window that handled them
+        if self.takes_key(key_event) || key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
         match key_event.code {
             KeyCode::Char('q') => self.state = AppState::Exiting,
             KeyCode::Tab => self.next_window(),
             KeyCode::Char('d') => self.current_window = AppWindow::Debug,
+            KeyCode::Char('+') | KeyCode::Char('=') => self.nudge_bpm(BPM_NUDGE),
+            KeyCode::Char('-') => self.nudge_bpm(-BPM_NUDGE),
+            KeyCode::Char('b') => self.tap_tempo(),
+            KeyCode::Char(' ') => self.audio_engine.toggle_playback(),
+            KeyCode::Char('k') => self.with_metronome(Metronome::toggle),
+            KeyCode::Char('K') => self.with_metronome(Metronome::next_sound),
+            KeyCode::Char('<') => self.with_metronome(Metronome::decrease_level),
+            KeyCode::Char('>') => self.with_metronome(Metronome::increase_level),
+            KeyCode::Char('n') => self.with_metronome(Metronome::cycle_count_in),
             _ => {}
         };
     }
@@ -0,0 +1,29 @@
+use crate::sequencer::NoteEvent;
+
+//Steps copied from a sequencer, shared by every track so patterns can be moved between them
+#[derive(Default)]
+pub struct Clipboard {
+    steps: Vec<Vec<NoteEvent>>,
+}
+
+impl Clipboard {
+    pub fn new() -> Self {
+        Clipboard { steps: Vec::new() }
+    }
+
+    pub fn set(&mut self, steps: Vec<Vec<NoteEvent>>) {
+        self.steps = steps;
+    }
+
+    pub fn steps(&self) -> &[Vec<NoteEvent>] {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
@@ -0,0 +1,612 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    filter::{FilterMode, StateVariableFilter},
+    generators::{Envelope, Instrument, Processor, WaveType},
+    noise::{Noise, NoiseColour},
+    notes::freq_to_midi,
+    oscillator::{Oscillator, OscillatorQuality},
+    parameter::Parameter,
+};
+
+//Drums are one shots, the sequencer note only triggers them and note off is ignored.
+//The envelopes decay to silence and the level is cubed so the tail falls off like a real drum
+fn decay_envelope(decay: f32, sample_rate: f32) -> Envelope {
+    Envelope::new(0.001, decay, 0.0, 0.01, sample_rate)
+}
+
+fn shaped_level(envelope: &mut Envelope) -> f32 {
+    envelope.process(1.0).powi(3)
+}
+
+fn decay_parameter(name: &'static str, seconds: f32) -> Parameter {
+    Parameter::new(name, seconds, 0.005, 5.0, 0.1)
+        .unit("s")
+        .logarithmic()
+}
+
+fn frequency_parameter(name: &'static str, frequency: f32, min: f32, max: f32) -> Parameter {
+    Parameter::new(name, frequency, min, max, 0.05)
+        .unit("Hz")
+        .logarithmic()
+}
+
+//Sine with a falling pitch, a noise click on the attack and soft clipping
+pub struct Kick {
+    sample_rate: f32,
+    phase: f32,
+    tune: f32,
+    //Semitones above the tune the pitch starts at
+    pitch_amount: f32,
+    pitch_decay: f32,
+    //Falls from 1 to 0 after a hit, scales the pitch amount
+    pitch_envelope: f32,
+    decay: f32,
+    click: f32,
+    click_level: f32,
+    drive: f32,
+    velocity: f32,
+    noise: Noise,
+    envelope: Envelope,
+}
+
+//Length of the click in seconds
+const CLICK_TIME: f32 = 0.003;
+
+impl Kick {
+    pub fn new(sample_rate: f32) -> Self {
+        Kick {
+            sample_rate,
+            phase: 0.0,
+            tune: 50.0,
+            pitch_amount: 24.0,
+            pitch_decay: 0.04,
+            pitch_envelope: 0.0,
+            decay: 0.5,
+            click: 0.3,
+            click_level: 0.0,
+            drive: 0.2,
+            velocity: 1.0,
+            noise: Noise::new(NoiseColour::White, 0),
+            envelope: decay_envelope(0.5, sample_rate),
+        }
+    }
+}
+
+impl Instrument for Kick {
+    fn get_name(&self) -> &str {
+        "Kick"
+    }
+
+    fn process(&mut self) -> f32 {
+        let frequency = self.tune * (self.pitch_amount * self.pitch_envelope / 12.0).exp2();
+        self.pitch_envelope *= (-1.0 / (self.pitch_decay * self.sample_rate)).exp();
+
+        let body = (self.phase * TAU).sin();
+        self.phase = (self.phase + frequency / self.sample_rate).rem_euclid(1.0);
+
+        let click = self.noise.process() * self.click_level * self.click;
+        self.click_level *= (-1.0 / (CLICK_TIME * self.sample_rate)).exp();
+
+        let sample = body * shaped_level(&mut self.envelope) + click;
+        let drive = 1.0 + self.drive * 4.0;
+        (sample * drive).tanh() / drive.tanh() * self.velocity
+    }
+
+    fn note_on(&mut self, _frequency: f32, velocity: f32) {
+        self.velocity = velocity;
+        self.phase = 0.0;
+        self.pitch_envelope = 1.0;
+        self.click_level = 1.0;
+        self.envelope.start();
+    }
+
+    fn note_off(&mut self) {}
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        self.phase
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            frequency_parameter("Tune", self.tune, 20.0, 200.0),
+            Parameter::new("Pitch Amount", self.pitch_amount, 0.0, 48.0, 1.0).unit("st"),
+            decay_parameter("Pitch Decay", self.pitch_decay),
+            decay_parameter("Decay", self.decay),
+            Parameter::new("Click", self.click, 0.0, 1.0, 0.01),
+            Parameter::new("Drive", self.drive, 0.0, 1.0, 0.01),
+        ]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.tune = value.clamp(20.0, 200.0),
+            1 => self.pitch_amount = value.clamp(0.0, 48.0),
+            2 => self.pitch_decay = value.clamp(0.005, 5.0),
+            3 => {
+                self.decay = value.clamp(0.005, 5.0);
+                self.envelope.set_parameter(1, self.decay);
+            }
+            4 => self.click = value.clamp(0.0, 1.0),
+            5 => self.drive = value.clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.phase = 0.0;
+        self.pitch_envelope = 0.0;
+        self.click_level = 0.0;
+        self.noise.set_seed(self.noise.seed());
+        self.envelope.reset();
+    }
+}
+
+//Two detuned sines for the shell and high-passed noise for the wires
+pub struct Snare {
+    sample_rate: f32,
+    phases: [f32; 2],
+    tune: f32,
+    tone_decay: f32,
+    noise_decay: f32,
+    //0 is all tone, 1 all noise
+    noise_mix: f32,
+    noise_tone: f32,
+    velocity: f32,
+    noise: Noise,
+    filter: StateVariableFilter,
+    tone_envelope: Envelope,
+    noise_envelope: Envelope,
+}
+
+//Second shell mode relative to the tune
+const SNARE_OVERTONE: f32 = 1.6;
+
+impl Snare {
+    pub fn new(sample_rate: f32) -> Self {
+        Snare {
+            sample_rate,
+            phases: [0.0; 2],
+            tune: 180.0,
+            tone_decay: 0.12,
+            noise_decay: 0.2,
+            noise_mix: 0.6,
+            noise_tone: 2500.0,
+            velocity: 1.0,
+            noise: Noise::new(NoiseColour::White, 0),
+            filter: StateVariableFilter::new(FilterMode::HighPass),
+            tone_envelope: decay_envelope(0.12, sample_rate),
+            noise_envelope: decay_envelope(0.2, sample_rate),
+        }
+    }
+}
+
+impl Instrument for Snare {
+    fn get_name(&self) -> &str {
+        "Snare"
+    }
+
+    fn process(&mut self) -> f32 {
+        let tone = (self.phases[0] * TAU).sin() * 0.6 + (self.phases[1] * TAU).sin() * 0.4;
+        self.phases[0] = (self.phases[0] + self.tune / self.sample_rate).rem_euclid(1.0);
+        self.phases[1] =
+            (self.phases[1] + self.tune * SNARE_OVERTONE / self.sample_rate).rem_euclid(1.0);
+
+        let noise = self.noise.process();
+        let noise = self
+            .filter
+            .process(noise, self.noise_tone, 0.0, self.sample_rate);
+
+        let tone = tone * shaped_level(&mut self.tone_envelope) * (1.0 - self.noise_mix);
+        let noise = noise * shaped_level(&mut self.noise_envelope) * self.noise_mix;
+        (tone + noise) * self.velocity
+    }
+
+    fn note_on(&mut self, _frequency: f32, velocity: f32) {
+        self.velocity = velocity;
+        self.phases = [0.0; 2];
+        self.tone_envelope.start();
+        self.noise_envelope.start();
+    }
+
+    fn note_off(&mut self) {}
+
+    //The noise rings longest by default
+    fn get_envelope(&self) -> &Envelope {
+        &self.noise_envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        self.phases[0]
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            frequency_parameter("Tune", self.tune, 60.0, 600.0),
+            decay_parameter("Tone Decay", self.tone_decay),
+            decay_parameter("Noise Decay", self.noise_decay),
+            Parameter::new("Noise Mix", self.noise_mix, 0.0, 1.0, 0.01),
+            frequency_parameter("Noise Tone", self.noise_tone, 200.0, 15000.0),
+        ]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.tune = value.clamp(60.0, 600.0),
+            1 => {
+                self.tone_decay = value.clamp(0.005, 5.0);
+                self.tone_envelope.set_parameter(1, self.tone_decay);
+            }
+            2 => {
+                self.noise_decay = value.clamp(0.005, 5.0);
+                self.noise_envelope.set_parameter(1, self.noise_decay);
+            }
+            3 => self.noise_mix = value.clamp(0.0, 1.0),
+            4 => self.noise_tone = value.clamp(200.0, 15000.0),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.phases = [0.0; 2];
+        self.noise.set_seed(self.noise.seed());
+        self.filter.reset();
+        self.tone_envelope.reset();
+        self.noise_envelope.reset();
+    }
+}
+
+//Square waves at inharmonic ratios, the classic analogue drum machine cymbal
+const HAT_FREQUENCIES: [f32; 6] = [205.3, 304.4, 369.6, 522.7, 540.0, 800.0];
+//Pitch class of the notes that play the open hat, A# like the open hat of the GM drum map
+const OPEN_HAT_PITCH_CLASS: u8 = 10;
+
+//Metallic hi-hat, A# notes play it open and every other note closed.
+//It is a single voice so a closed hit chokes a ringing open hat
+pub struct HiHat {
+    sample_rate: f32,
+    oscillators: Vec<Oscillator>,
+    tune: f32,
+    tone: f32,
+    closed_decay: f32,
+    open_decay: f32,
+    noise_level: f32,
+    velocity: f32,
+    noise: Noise,
+    band_pass: StateVariableFilter,
+    high_pass: StateVariableFilter,
+    envelope: Envelope,
+}
+
+//The high-pass sits this far below the tone
+const HAT_HIGH_PASS_RATIO: f32 = 0.7;
+//Most of the square wave energy is below the filters, make up for it
+const HAT_GAIN: f32 = 6.0;
+
+impl HiHat {
+    pub fn new(sample_rate: f32) -> Self {
+        HiHat {
+            sample_rate,
+            oscillators: HAT_FREQUENCIES
+                .iter()
+                .map(|_| Oscillator::new(WaveType::Square, OscillatorQuality::Standard))
+                .collect(),
+            tune: 0.0,
+            tone: 9000.0,
+            closed_decay: 0.06,
+            open_decay: 0.5,
+            noise_level: 0.2,
+            velocity: 1.0,
+            noise: Noise::new(NoiseColour::White, 0),
+            band_pass: StateVariableFilter::new(FilterMode::BandPass),
+            high_pass: StateVariableFilter::new(FilterMode::HighPass),
+            envelope: decay_envelope(0.06, sample_rate),
+        }
+    }
+}
+
+impl Instrument for HiHat {
+    fn get_name(&self) -> &str {
+        "Hi-Hat"
+    }
+
+    fn process(&mut self) -> f32 {
+        let ratio = (self.tune / 12.0).exp2();
+        let metal: f32 = self
+            .oscillators
+            .iter_mut()
+            .zip(HAT_FREQUENCIES)
+            .map(|(oscillator, frequency)| oscillator.process(frequency * ratio, self.sample_rate))
+            .sum::<f32>()
+            / HAT_FREQUENCIES.len() as f32;
+
+        let noise = self.noise.process();
+        let mix = metal * (1.0 - self.noise_level) + noise * self.noise_level;
+
+        let sample = self
+            .band_pass
+            .process(mix, self.tone, 0.3, self.sample_rate);
+        let sample = self.high_pass.process(
+            sample,
+            self.tone * HAT_HIGH_PASS_RATIO,
+            0.0,
+            self.sample_rate,
+        );
+
+        sample * shaped_level(&mut self.envelope) * HAT_GAIN * self.velocity
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        let open = freq_to_midi(frequency).round() as i32 % 12 == OPEN_HAT_PITCH_CLASS as i32;
+        let decay = if open {
+            self.open_decay
+        } else {
+            self.closed_decay
+        };
+
+        self.velocity = velocity;
+        self.envelope.set_parameter(1, decay);
+        self.envelope.start();
+    }
+
+    fn note_off(&mut self) {}
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        self.oscillators[0].phase()
+    }
+
+    fn set_quality(&mut self, quality: OscillatorQuality) {
+        for oscillator in self.oscillators.iter_mut() {
+            oscillator.set_quality(quality);
+        }
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("Tune", self.tune, -24.0, 24.0, 1.0).unit("st"),
+            frequency_parameter("Tone", self.tone, 2000.0, 16000.0),
+            decay_parameter("Closed Decay", self.closed_decay),
+            decay_parameter("Open Decay", self.open_decay),
+            Parameter::new("Noise", self.noise_level, 0.0, 1.0, 0.01),
+        ]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.tune = value.clamp(-24.0, 24.0),
+            1 => self.tone = value.clamp(2000.0, 16000.0),
+            2 => self.closed_decay = value.clamp(0.005, 5.0),
+            3 => self.open_decay = value.clamp(0.005, 5.0),
+            4 => self.noise_level = value.clamp(0.0, 1.0),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        for oscillator in self.oscillators.iter_mut() {
+            oscillator.reset();
+        }
+        self.noise.set_seed(self.noise.seed());
+        self.band_pass.reset();
+        self.high_pass.reset();
+        self.envelope.reset();
+    }
+}
+
+//Band-passed noise hit several times in quick succession, the last hit rings out as the tail
+pub struct Clap {
+    sample_rate: f32,
+    bursts: u8,
+    //Seconds between bursts
+    spread: f32,
+    tail_decay: f32,
+    tone: f32,
+    //Samples since the hit, None once the tail has started
+    elapsed: Option<usize>,
+    burst_level: f32,
+    velocity: f32,
+    noise: Noise,
+    filter: StateVariableFilter,
+    envelope: Envelope,
+}
+
+impl Clap {
+    pub fn new(sample_rate: f32) -> Self {
+        Clap {
+            sample_rate,
+            bursts: 3,
+            spread: 0.01,
+            tail_decay: 0.25,
+            tone: 1200.0,
+            elapsed: None,
+            burst_level: 0.0,
+            velocity: 1.0,
+            noise: Noise::new(NoiseColour::White, 0),
+            filter: StateVariableFilter::new(FilterMode::BandPass),
+            envelope: decay_envelope(0.25, sample_rate),
+        }
+    }
+
+    //Level of the bursts before the tail, each one restarts at full level and dies within the spread
+    fn burst(&mut self) -> f32 {
+        let Some(elapsed) = self.elapsed else {
+            return 0.0;
+        };
+
+        let spread = (self.spread * self.sample_rate).max(1.0) as usize;
+        if elapsed >= spread * (self.bursts as usize - 1) {
+            self.elapsed = None;
+            self.envelope.start();
+            return 0.0;
+        }
+
+        if elapsed % spread == 0 {
+            self.burst_level = 1.0;
+        }
+        self.elapsed = Some(elapsed + 1);
+
+        let level = self.burst_level;
+        self.burst_level *= (-3.0 / spread as f32).exp();
+        level
+    }
+}
+
+impl Instrument for Clap {
+    fn get_name(&self) -> &str {
+        "Clap"
+    }
+
+    fn process(&mut self) -> f32 {
+        let level = self.burst() + shaped_level(&mut self.envelope);
+        let noise = self.noise.process();
+        let sample = self.filter.process(noise, self.tone, 0.4, self.sample_rate);
+
+        //The band-pass takes a lot of energy out of the noise
+        sample * level * 2.0 * self.velocity
+    }
+
+    fn note_on(&mut self, _frequency: f32, velocity: f32) {
+        self.velocity = velocity;
+        self.elapsed = Some(0);
+        //A single burst goes straight to the tail
+        if self.bursts <= 1 {
+            self.elapsed = None;
+            self.envelope.start();
+        }
+    }
+
+    fn note_off(&mut self) {}
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        0.0
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("Bursts", self.bursts as f32, 1.0, 8.0, 1.0),
+            Parameter::new("Spread", self.spread, 0.002, 0.05, 0.1)
+                .unit("s")
+                .logarithmic(),
+            decay_parameter("Tail Decay", self.tail_decay),
+            frequency_parameter("Tone", self.tone, 300.0, 8000.0),
+        ]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.bursts = value.round().clamp(1.0, 8.0) as u8,
+            1 => self.spread = value.clamp(0.002, 0.05),
+            2 => {
+                self.tail_decay = value.clamp(0.005, 5.0);
+                self.envelope.set_parameter(1, self.tail_decay);
+            }
+            3 => self.tone = value.clamp(300.0, 8000.0),
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        self.elapsed = None;
+        self.burst_level = 0.0;
+        self.noise.set_seed(self.noise.seed());
+        self.filter.reset();
+        self.envelope.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::midi_to_freq;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn render(instrument: &mut impl Instrument, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE) as usize)
+            .map(|_| instrument.process())
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[test]
+    fn closed_hat_chokes_the_open_hat() {
+        let open = midi_to_freq(70.0);
+        let closed = midi_to_freq(72.0);
+
+        let mut ringing = HiHat::new(SAMPLE_RATE);
+        ringing.note_on(open, 1.0);
+        render(&mut ringing, 0.05);
+        let tail = render(&mut ringing, 0.2);
+
+        let mut choked = HiHat::new(SAMPLE_RATE);
+        choked.note_on(open, 1.0);
+        render(&mut choked, 0.05);
+        choked.note_on(closed, 1.0);
+        let choked_tail = render(&mut choked, 0.2);
+
+        //The open hat is still ringing where the closed hit has died away
+        assert!(peak(&tail[4800..]) > 0.01);
+        assert_eq!(peak(&choked_tail[4800..]), 0.0);
+    }
+
+    #[test]
+    fn clap_plays_its_bursts() {
+        for bursts in 1..=8 {
+            let mut clap = Clap::new(SAMPLE_RATE);
+            clap.set_parameter(0, bursts as f32);
+            clap.note_on(440.0, 1.0);
+
+            //Every burst but the last restarts at full level, the last one is the tail
+            let mut hits = 0;
+            while clap.elapsed.is_some() {
+                if clap.burst() == 1.0 {
+                    hits += 1;
+                }
+            }
+            assert_eq!(hits + 1, bursts);
+            assert!(clap.envelope.is_active());
+        }
+    }
+
+    #[test]
+    fn kick_pitch_falls_to_the_tune() {
+        let mut kick = Kick::new(SAMPLE_RATE);
+        kick.note_on(440.0, 1.0);
+
+        //Average frequency over blocks of 5 ms, single samples are too short to measure
+        let block = 240;
+        let frequencies: Vec<f32> = (0..100)
+            .map(|_| {
+                let mut cycles = 0.0;
+                for _ in 0..block {
+                    let phase = kick.get_phase();
+                    kick.process();
+                    cycles += (kick.get_phase() - phase).rem_euclid(1.0);
+                }
+                cycles * SAMPLE_RATE / block as f32
+            })
+            .collect();
+
+        //Starts near two octaves up, falls through the first 200 ms and settles on the tune
+        assert!(frequencies[0] > 180.0);
+        assert!(frequencies[..40].windows(2).all(|pair| pair[1] < pair[0]));
+        assert!((frequencies.last().unwrap() - 50.0).abs() < 0.5);
+    }
+}
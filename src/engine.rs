@@ -46,6 +46,13 @@ impl AudioEngine {
     pub fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         //Set the state
         *self.state.lock().unwrap() = AudioEngineState::Playing;
+        self.mixer.lock().unwrap().begin_playback();
+
+        //The stream keeps running while stopped so notes can be auditioned
+        if self.stream.is_some() {
+            return Ok(());
+        }
+
         let mixer = Arc::clone(&self.mixer);
         let state = Arc::clone(&self.state);
         let chanels = self.channels as usize;
@@ -59,19 +66,16 @@ impl AudioEngine {
         let stream = self.device.build_output_stream(
             &config,
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                if *state.lock().unwrap() == AudioEngineState::Stopped {
-                    for sample in data.iter_mut() {
-                        *sample = 0.0;
-                    }
-
-                    return;
-                }
-
                 let num_frames = data.len() / chanels;
+                let is_playing = *state.lock().unwrap() == AudioEngineState::Playing;
 
                 let mix = {
                     let mut mixer_gaurd = mixer.lock().unwrap();
-                    mixer_gaurd.process_block(num_frames)
+                    if is_playing {
+                        mixer_gaurd.process_block(num_frames)
+                    } else {
+                        mixer_gaurd.process_idle(num_frames)
+                    }
                 };
 
                 for (frame_idx, frame) in data.chunks_mut(chanels).enumerate() {
@@ -92,9 +96,10 @@ impl AudioEngine {
         Ok(())
     }
 
+    //Pauses the transport, the stream stays open for auditioning
     pub fn stop(&mut self) {
         *self.state.lock().unwrap() = AudioEngineState::Stopped;
-        self.stream = None;
+        self.mixer.lock().unwrap().release_all();
     }
 
     pub fn toggle_playback(&mut self) {
@@ -123,5 +128,6 @@ impl AudioEngine {
 impl Drop for AudioEngine {
     fn drop(&mut self) {
         self.stop();
+        self.stream = None;
     }
 }
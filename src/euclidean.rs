@@ -0,0 +1,153 @@
+use std::{fmt, str::FromStr};
+
+use crate::notes::Note;
+
+//E(k, n) rhythm with the note it is played with, parsed from the sequencer prompt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EuclideanRhythm {
+    pub hits: usize,
+    pub steps: usize,
+    pub rotation: usize,
+    pub note: Note,
+    pub velocity: f32,
+}
+
+impl EuclideanRhythm {
+    //Hits spread as evenly as possible over the steps, rotated to the right
+    pub fn pattern(&self) -> Vec<bool> {
+        euclidean(self.hits, self.steps, self.rotation)
+    }
+}
+
+//Bresenham style distribution, gives the same patterns as Bjorklund's algorithm
+pub fn euclidean(hits: usize, steps: usize, rotation: usize) -> Vec<bool> {
+    if steps == 0 {
+        return Vec::new();
+    }
+
+    let hits = hits.min(steps);
+    let mut pattern: Vec<bool> = (0..steps).map(|i| (i * hits) % steps < hits).collect();
+    pattern.rotate_right(rotation % steps);
+    pattern
+}
+
+#[derive(Debug)]
+pub struct ParseEuclideanError;
+
+impl fmt::Display for ParseEuclideanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid euclidean rhythm string")
+    }
+}
+
+impl FromStr for EuclideanRhythm {
+    type Err = ParseEuclideanError;
+
+    //Parses "k n [rotation] [note] [velocity]", e.g. "3 8 2 C2 0.8"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+
+        let mut number = |default: Option<usize>| match parts.next() {
+            Some(part) => part.parse::<usize>().map_err(|_| ParseEuclideanError),
+            None => default.ok_or(ParseEuclideanError),
+        };
+
+        let hits = number(None)?;
+        let steps = number(None)?;
+        let rotation = number(Some(0))?;
+
+        let note = match parts.next() {
+            Some(part) => part.parse::<Note>().map_err(|_| ParseEuclideanError)?,
+            None => Note::C4,
+        };
+
+        let velocity = match parts.next() {
+            Some(part) => part.parse::<f32>().map_err(|_| ParseEuclideanError)?,
+            None => 1.0,
+        };
+
+        if steps == 0 || hits > steps {
+            return Err(ParseEuclideanError);
+        }
+
+        Ok(EuclideanRhythm {
+            hits,
+            steps,
+            rotation,
+            note,
+            velocity: velocity.clamp(0.0, 1.0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Reads "x..x..x." as a pattern
+    fn pattern(text: &str) -> Vec<bool> {
+        text.chars().map(|c| c == 'x').collect()
+    }
+
+    #[test]
+    fn tresillo() {
+        assert_eq!(euclidean(3, 8, 0), pattern("x..x..x."));
+    }
+
+    #[test]
+    fn five_in_eight_rotated() {
+        assert_eq!(euclidean(5, 8, 0), pattern("x.x.xx.x"));
+        assert_eq!(euclidean(5, 8, 1), pattern("xx.x.xx."));
+        //Rotation wraps around the pattern length
+        assert_eq!(euclidean(5, 8, 9), euclidean(5, 8, 1));
+    }
+
+    #[test]
+    fn hits_fill_every_step() {
+        assert_eq!(euclidean(8, 8, 0), vec![true; 8]);
+        assert_eq!(euclidean(12, 8, 3), vec![true; 8]);
+        assert_eq!(euclidean(0, 8, 0), vec![false; 8]);
+        assert!(euclidean(3, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn parses_full_and_default_fields() {
+        let rhythm: EuclideanRhythm = "3 8 2 C2 0.8".parse().unwrap();
+        assert_eq!(
+            rhythm,
+            EuclideanRhythm {
+                hits: 3,
+                steps: 8,
+                rotation: 2,
+                note: Note::C2,
+                velocity: 0.8,
+            }
+        );
+
+        let rhythm: EuclideanRhythm = "5 8".parse().unwrap();
+        assert_eq!(
+            (rhythm.rotation, rhythm.note, rhythm.velocity),
+            (0, Note::C4, 1.0)
+        );
+        assert_eq!(
+            "8 8".parse::<EuclideanRhythm>().unwrap().pattern(),
+            vec![true; 8]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        for input in [
+            "",
+            "3",
+            "3 0",
+            "9 8",
+            "a 8",
+            "3 8 x",
+            "3 8 0 H9",
+            "3 8 0 C4 loud",
+        ] {
+            assert!(input.parse::<EuclideanRhythm>().is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::f32::consts::PI;
+
+//In place radix-2 FFT, the length must be a power of two.
+//The inverse is not scaled, divide by the length to get the input back
+pub fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+    assert_eq!(n, im.len());
+    assert!(n.is_power_of_two());
+
+    //Bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * PI / len as f32;
+
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                //Computed per butterfly instead of by recurrence to keep large sizes accurate
+                let (w_im, w_re) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + len / 2;
+
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+//Magnitude of every bin from DC to Nyquist of a real signal
+pub fn magnitude_spectrum(signal: &[f32]) -> Vec<f32> {
+    let mut re = signal.to_vec();
+    let mut im = vec![0.0; signal.len()];
+    fft(&mut re, &mut im, false);
+
+    re.iter()
+        .zip(&im)
+        .take(signal.len() / 2 + 1)
+        .map(|(re, im)| (re * re + im * im).sqrt())
+        .collect()
+}
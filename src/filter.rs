@@ -0,0 +1,118 @@
+use std::f32::consts::PI;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    LowPass,
+    HighPass,
+    BandPass,
+}
+
+pub const FILTER_MODES: [FilterMode; 3] = [
+    FilterMode::LowPass,
+    FilterMode::HighPass,
+    FilterMode::BandPass,
+];
+pub const FILTER_MODE_NAMES: [&str; 3] = ["Low-pass", "High-pass", "Band-pass"];
+
+//Highest cutoff as a share of the sample rate, the filter turns unstable at Nyquist
+const MAX_CUTOFF: f32 = 0.49;
+//Damping left at full resonance, keeps the filter just short of self oscillation
+const MIN_DAMPING: f32 = 0.02;
+
+//Trapezoidal state variable filter, stays stable while the cutoff moves every sample
+pub struct StateVariableFilter {
+    mode: FilterMode,
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl StateVariableFilter {
+    pub fn new(mode: FilterMode) -> Self {
+        StateVariableFilter {
+            mode,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        }
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: FilterMode) {
+        self.mode = mode;
+    }
+
+    pub fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+
+    //Resonance runs from 0 to 1
+    pub fn process(&mut self, input: f32, cutoff: f32, resonance: f32, sample_rate: f32) -> f32 {
+        let cutoff = cutoff.clamp(1.0, sample_rate * MAX_CUTOFF);
+        let g = (PI * cutoff / sample_rate).tan();
+        let k = 2.0 - (2.0 - MIN_DAMPING) * resonance.clamp(0.0, 1.0);
+
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+
+        let v3 = input - self.ic2eq;
+        let v1 = a1 * self.ic1eq + a2 * v3;
+        let v2 = self.ic2eq + a2 * self.ic1eq + a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        match self.mode {
+            FilterMode::LowPass => v2,
+            FilterMode::HighPass => input - k * v1 - v2,
+            FilterMode::BandPass => v1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const CUTOFF: f32 = 1000.0;
+
+    //Level of a sine through the filter once it has settled
+    fn level(mode: FilterMode, frequency: f32) -> f32 {
+        let mut filter = StateVariableFilter::new(mode);
+        let output: Vec<f32> = (0..9600)
+            .map(|n| {
+                let input = (2.0 * PI * frequency * n as f32 / SAMPLE_RATE).sin();
+                filter.process(input, CUTOFF, 0.0, SAMPLE_RATE)
+            })
+            .collect();
+        output[4800..]
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    #[test]
+    fn low_pass_cuts_above_the_cutoff() {
+        let low = level(FilterMode::LowPass, CUTOFF / 8.0);
+        let high = level(FilterMode::LowPass, CUTOFF * 8.0);
+        assert!(low > 0.9);
+        assert!(high < 0.05);
+    }
+
+    #[test]
+    fn high_pass_cuts_below_the_cutoff() {
+        let low = level(FilterMode::HighPass, CUTOFF / 8.0);
+        let high = level(FilterMode::HighPass, CUTOFF * 8.0);
+        assert!(low < 0.05);
+        assert!(high > 0.9);
+    }
+
+    #[test]
+    fn band_pass_cuts_both_sides_of_the_cutoff() {
+        let centre = level(FilterMode::BandPass, CUTOFF);
+        assert!(level(FilterMode::BandPass, CUTOFF / 16.0) < centre * 0.2);
+        assert!(level(FilterMode::BandPass, CUTOFF * 16.0) < centre * 0.2);
+    }
+}
@@ -0,0 +1,388 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    generators::{ENVELOPE_PARAMETERS, Envelope, Instrument, Processor},
+    parameter::Parameter,
+};
+
+pub const OPERATORS: usize = 4;
+//Phase shift in radians of a modulator at full level
+const MODULATION_INDEX: f32 = 4.0;
+//Phase shift in radians fed back into the last operator at full feedback
+const FEEDBACK_INDEX: f32 = 1.5;
+//Mode, ratio, fixed frequency and level, followed by the envelope
+const OPERATOR_PARAMETERS: usize = 4 + ENVELOPE_PARAMETERS;
+//Algorithm and feedback come before the operators
+const GLOBAL_PARAMETERS: usize = 2;
+
+const MODE_NAMES: [&str; 2] = ["Ratio", "Fixed"];
+const OPERATOR_NAMES: [[&str; OPERATOR_PARAMETERS]; OPERATORS] = [
+    [
+        "Op 1 Mode",
+        "Op 1 Ratio",
+        "Op 1 Fixed",
+        "Op 1 Level",
+        "Op 1 Attack",
+        "Op 1 Decay",
+        "Op 1 Sustain",
+        "Op 1 Release",
+    ],
+    [
+        "Op 2 Mode",
+        "Op 2 Ratio",
+        "Op 2 Fixed",
+        "Op 2 Level",
+        "Op 2 Attack",
+        "Op 2 Decay",
+        "Op 2 Sustain",
+        "Op 2 Release",
+    ],
+    [
+        "Op 3 Mode",
+        "Op 3 Ratio",
+        "Op 3 Fixed",
+        "Op 3 Level",
+        "Op 3 Attack",
+        "Op 3 Decay",
+        "Op 3 Sustain",
+        "Op 3 Release",
+    ],
+    [
+        "Op 4 Mode",
+        "Op 4 Ratio",
+        "Op 4 Fixed",
+        "Op 4 Level",
+        "Op 4 Attack",
+        "Op 4 Decay",
+        "Op 4 Sustain",
+        "Op 4 Release",
+    ],
+];
+
+//How the operators are connected, operator 4 is at the top and takes the feedback
+struct Algorithm {
+    //Operators modulating each operator, always higher than the operator itself
+    modulators: [&'static [usize]; OPERATORS],
+    //Operators heard at the output, operator 1 is always one of them
+    carriers: &'static [usize],
+}
+
+const ALGORITHMS: [Algorithm; 8] = [
+    Algorithm {
+        modulators: [&[1], &[2], &[3], &[]],
+        carriers: &[0],
+    },
+    Algorithm {
+        modulators: [&[1], &[2, 3], &[], &[]],
+        carriers: &[0],
+    },
+    Algorithm {
+        modulators: [&[1, 3], &[2], &[], &[]],
+        carriers: &[0],
+    },
+    Algorithm {
+        modulators: [&[1, 2], &[], &[3], &[]],
+        carriers: &[0],
+    },
+    Algorithm {
+        modulators: [&[1], &[], &[3], &[]],
+        carriers: &[0, 2],
+    },
+    Algorithm {
+        modulators: [&[3], &[3], &[3], &[]],
+        carriers: &[0, 1, 2],
+    },
+    Algorithm {
+        modulators: [&[], &[], &[3], &[]],
+        carriers: &[0, 1, 2],
+    },
+    Algorithm {
+        modulators: [&[], &[], &[], &[]],
+        carriers: &[0, 1, 2, 3],
+    },
+];
+const ALGORITHM_NAMES: [&str; 8] = [
+    "4>3>2>1",
+    "3+4>2>1",
+    "3>2>1 4>1",
+    "4>3>1 2>1",
+    "2>1 4>3",
+    "4>1+2+3",
+    "4>3 2 1",
+    "1 2 3 4",
+];
+
+//A sine oscillator with its own level and envelope
+struct Operator {
+    phase: f32,
+    fixed: bool,
+    ratio: f32,
+    fixed_frequency: f32,
+    level: f32,
+    envelope: Envelope,
+    output: f32,
+}
+
+impl Operator {
+    fn new(ratio: f32, level: f32, envelope: Envelope) -> Self {
+        Operator {
+            phase: 0.0,
+            fixed: false,
+            ratio,
+            fixed_frequency: 440.0,
+            level,
+            envelope,
+            output: 0.0,
+        }
+    }
+
+    //Modulation is a phase shift in radians
+    fn process(&mut self, frequency: f32, modulation: f32, sample_rate: f32) -> f32 {
+        let frequency = if self.fixed {
+            self.fixed_frequency
+        } else {
+            frequency * self.ratio
+        };
+
+        let sample = (self.phase * TAU + modulation).sin();
+        self.phase = (self.phase + frequency / sample_rate).rem_euclid(1.0);
+        self.output = self.envelope.process(sample) * self.level;
+        self.output
+    }
+
+    fn parameters(&self, names: &[&'static str; OPERATOR_PARAMETERS]) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::choice(names[0], self.fixed as usize, &MODE_NAMES),
+            Parameter::new(names[1], self.ratio, 0.125, 32.0, 0.01),
+            Parameter::new(names[2], self.fixed_frequency, 1.0, 20000.0, 0.05)
+                .unit("Hz")
+                .logarithmic(),
+            Parameter::new(names[3], self.level, 0.0, 1.0, 0.01),
+        ];
+        parameters.extend(
+            self.envelope
+                .parameters()
+                .into_iter()
+                .zip(&names[4..])
+                .map(|(parameter, name)| Parameter { name, ..parameter }),
+        );
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.fixed = value >= 0.5,
+            1 => self.ratio = value.clamp(0.125, 32.0),
+            2 => self.fixed_frequency = value.clamp(1.0, 20000.0),
+            3 => self.level = value.clamp(0.0, 1.0),
+            index => self.envelope.set_parameter(index - 4, value),
+        }
+    }
+}
+
+//Four operator FM synth, the algorithm decides which operators modulate which
+pub struct FmSynth {
+    sample_rate: f32,
+    operators: [Operator; OPERATORS],
+    algorithm: usize,
+    feedback: f32,
+    //Last two outputs of operator 4, averaged so feedback does not turn to noise
+    feedback_history: [f32; 2],
+    frequency: f32,
+    velocity: f32,
+}
+
+impl FmSynth {
+    pub fn new(sample_rate: f32) -> Self {
+        let envelope = |attack, decay, sustain, release| {
+            Envelope::new(attack, decay, sustain, release, sample_rate)
+        };
+
+        //Two stacks, a soft tone on 2>1 and a bright attack on 4>3
+        FmSynth {
+            sample_rate,
+            operators: [
+                Operator::new(1.0, 1.0, envelope(0.002, 1.5, 0.4, 0.3)),
+                Operator::new(1.0, 0.4, envelope(0.002, 0.8, 0.2, 0.3)),
+                Operator::new(2.0, 0.3, envelope(0.002, 0.6, 0.0, 0.2)),
+                Operator::new(14.0, 0.15, envelope(0.001, 0.2, 0.0, 0.1)),
+            ],
+            algorithm: 4,
+            feedback: 0.0,
+            feedback_history: [0.0; 2],
+            frequency: 440.0,
+            velocity: 1.0,
+        }
+    }
+}
+
+impl Instrument for FmSynth {
+    fn get_name(&self) -> &str {
+        "FM"
+    }
+
+    fn process(&mut self) -> f32 {
+        let algorithm = &ALGORITHMS[self.algorithm];
+
+        //Modulators always come after the operators they modulate, so run from the top down
+        for index in (0..OPERATORS).rev() {
+            let mut modulation: f32 = algorithm.modulators[index]
+                .iter()
+                .map(|modulator| self.operators[*modulator].output)
+                .sum::<f32>()
+                * MODULATION_INDEX;
+
+            if index == OPERATORS - 1 {
+                let [previous, last] = self.feedback_history;
+                modulation += (previous + last) * 0.5 * self.feedback * FEEDBACK_INDEX;
+            }
+
+            let output =
+                self.operators[index].process(self.frequency, modulation, self.sample_rate);
+
+            if index == OPERATORS - 1 {
+                self.feedback_history = [self.feedback_history[1], output];
+            }
+        }
+
+        let mix: f32 = algorithm
+            .carriers
+            .iter()
+            .map(|carrier| self.operators[*carrier].output)
+            .sum();
+
+        mix / algorithm.carriers.len() as f32 * self.velocity
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        self.frequency = frequency;
+        self.velocity = velocity;
+        for operator in self.operators.iter_mut() {
+            operator.envelope.start();
+        }
+    }
+
+    fn note_off(&mut self) {
+        for operator in self.operators.iter_mut() {
+            operator.envelope.stop();
+        }
+    }
+
+    //Operator 1 is a carrier in every algorithm
+    fn get_envelope(&self) -> &Envelope {
+        &self.operators[0].envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        self.operators[0].phase
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::choice("Algorithm", self.algorithm, &ALGORITHM_NAMES),
+            Parameter::new("Feedback", self.feedback, 0.0, 1.0, 0.01),
+        ];
+        for (operator, names) in self.operators.iter().zip(&OPERATOR_NAMES) {
+            parameters.extend(operator.parameters(names));
+        }
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.algorithm = (value.round().max(0.0) as usize).min(ALGORITHMS.len() - 1),
+            1 => self.feedback = value.clamp(0.0, 1.0),
+            index => {
+                let index = index - GLOBAL_PARAMETERS;
+                if let Some(operator) = self.operators.get_mut(index / OPERATOR_PARAMETERS) {
+                    operator.set_parameter(index % OPERATOR_PARAMETERS, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+    const ALL_CARRIERS: usize = 7;
+
+    //Every operator at full level and full sustain, so only the attack shapes the sound
+    fn synth(algorithm: usize, feedback: f32) -> FmSynth {
+        let mut synth = FmSynth::new(SAMPLE_RATE);
+        synth.set_parameter(0, algorithm as f32);
+        synth.set_parameter(1, feedback);
+        for operator in 0..OPERATORS {
+            let base = GLOBAL_PARAMETERS + operator * OPERATOR_PARAMETERS;
+            synth.set_parameter(base + 3, 1.0);
+            synth.set_parameter(base + 4, 0.0);
+            synth.set_parameter(base + 6, 1.0);
+        }
+        synth
+    }
+
+    fn render(synth: &mut FmSynth, frequency: f32, len: usize) -> Vec<f32> {
+        synth.note_on(frequency, 1.0);
+        (0..len).map(|_| synth.process()).collect()
+    }
+
+    #[test]
+    fn all_carriers_sum_their_sines() {
+        let mut synth = synth(ALL_CARRIERS, 0.0);
+        let ratios = [1.0, 1.0, 2.0, 14.0];
+        let output = render(&mut synth, 110.0, 4800);
+
+        //Past the attack every operator is a plain sine at its ratio
+        for (n, sample) in output.iter().enumerate().skip(100) {
+            let expected: f32 = ratios
+                .iter()
+                .map(|ratio| (TAU * 110.0 * ratio * n as f32 / SAMPLE_RATE).sin())
+                .sum::<f32>()
+                / OPERATORS as f32;
+            assert!((sample - expected).abs() < 1e-3, "sample {n}");
+        }
+    }
+
+    #[test]
+    fn fixed_operators_ignore_the_note() {
+        let fixed = || {
+            let mut synth = synth(0, 0.0);
+            for operator in 0..OPERATORS {
+                synth.set_parameter(GLOBAL_PARAMETERS + operator * OPERATOR_PARAMETERS, 1.0);
+            }
+            synth
+        };
+
+        let low = render(&mut fixed(), 110.0, 2400);
+        let high = render(&mut fixed(), 880.0, 2400);
+        assert_eq!(low, high);
+        assert_ne!(render(&mut synth(0, 0.0), 110.0, 2400), low);
+    }
+
+    #[test]
+    fn feedback_only_changes_operator_four() {
+        let mut plain = synth(ALL_CARRIERS, 0.0);
+        let mut fed_back = synth(ALL_CARRIERS, 0.8);
+        plain.note_on(220.0, 1.0);
+        fed_back.note_on(220.0, 1.0);
+
+        let mut difference = 0.0f32;
+        for _ in 0..2400 {
+            plain.process();
+            fed_back.process();
+            for operator in 0..OPERATORS - 1 {
+                assert_eq!(
+                    plain.operators[operator].output,
+                    fed_back.operators[operator].output
+                );
+            }
+            difference = difference.max(
+                (plain.operators[OPERATORS - 1].output - fed_back.operators[OPERATORS - 1].output)
+                    .abs(),
+            );
+        }
+        assert!(difference > 0.1);
+    }
+}
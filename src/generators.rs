@@ -1,5 +1,10 @@
 use std::{collections::HashMap, fmt::format};
+use std::{error::Error, path::Path};
 
+use crate::{
+    oscillator::{Oscillator, OscillatorQuality},
+    parameter::Parameter,
+};
 use ratatui::{
     Frame,
     style::Style,
@@ -10,10 +15,28 @@ use ratatui::{
 pub trait Instrument: Send {
     fn get_name(&self) -> &str;
     fn process(&mut self) -> f32;
-    fn note_on(&mut self, frequency: f32);
+    //Velocity runs from 0 to 1 and scales the output level
+    fn note_on(&mut self, frequency: f32, velocity: f32);
     fn note_off(&mut self);
     fn get_envelope(&self) -> &Envelope;
     fn get_phase(&self) -> f32;
+    //Instruments without oscillators ignore this
+    fn set_quality(&mut self, _quality: OscillatorQuality) {}
+    //Values shown in the instrument window, set_parameter takes the same index
+    fn parameters(&self) -> Vec<Parameter> {
+        Vec::new()
+    }
+    fn set_parameter(&mut self, _index: usize, _value: f32) {}
+    //Instruments that play audio files load them here
+    fn load_file(&mut self, _path: &Path) -> Result<(), Box<dyn Error>> {
+        Err("this instrument does not load files".into())
+    }
+    //File the instrument last loaded, presets save it so they can load it again
+    fn loaded_file(&self) -> Option<&Path> {
+        None
+    }
+    //Back to how a new instrument starts, called on rewind so renders from the top repeat exactly
+    fn reset(&mut self) {}
 }
 
 pub trait Processor {
@@ -23,6 +46,7 @@ pub trait Processor {
     fn process(&mut self, input: Self::Input) -> Self::Output;
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WaveType {
     Sine,
     Square,
@@ -30,13 +54,19 @@ pub enum WaveType {
     Saw,
 }
 
-//TODO: we should be abale to select any instrument type put it in a list and select the
-//appropriate instrument
+pub const WAVE_TYPES: [WaveType; 4] = [
+    WaveType::Sine,
+    WaveType::Square,
+    WaveType::Triangle,
+    WaveType::Saw,
+];
+pub const WAVE_NAMES: [&str; 4] = ["Sine", "Square", "Triangle", "Saw"];
+
 pub struct PrimitiveWave {
-    wave_type: WaveType,
+    oscillator: Oscillator,
     sample_rate: f32,
-    phase: f32,
     frequency: f32,
+    velocity: f32,
     envelope: Envelope,
 }
 
@@ -54,8 +84,14 @@ pub struct Envelope {
     phase: f32,
     state: EnvelopeState,
     current_level: f32,
+    sample_rate: f32,
 }
 
+//Attack, decay, sustain and release, listed after the parameters of an instrument
+pub const ENVELOPE_PARAMETERS: usize = 4;
+//Shortest attack, decay or release, avoids dividing by zero
+const MIN_ENVELOPE_TIME: f32 = 0.001;
+
 #[derive(PartialEq, Clone, Copy)]
 pub enum EnvelopeState {
     Attack,
@@ -75,6 +111,34 @@ impl Envelope {
             phase: 0.0,
             state: EnvelopeState::Idle,
             current_level: 0.0,
+            sample_rate,
+        }
+    }
+
+    //Times in seconds and sustain level, in the order of ENVELOPE_PARAMETERS
+    pub fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Self::time_parameter("Attack", self.attack / self.sample_rate),
+            Self::time_parameter("Decay", self.decay / self.sample_rate),
+            Parameter::new("Sustain", self.sustain, 0.0, 1.0, 0.01),
+            Self::time_parameter("Release", self.release / self.sample_rate),
+        ]
+    }
+
+    fn time_parameter(name: &'static str, seconds: f32) -> Parameter {
+        Parameter::new(name, seconds, MIN_ENVELOPE_TIME, 10.0, 0.1)
+            .unit("s")
+            .logarithmic()
+    }
+
+    pub fn set_parameter(&mut self, index: usize, value: f32) {
+        let samples = value.max(MIN_ENVELOPE_TIME) * self.sample_rate;
+        match index {
+            0 => self.attack = samples,
+            1 => self.decay = samples,
+            2 => self.sustain = value.clamp(0.0, 1.0),
+            3 => self.release = samples,
+            _ => {}
         }
     }
 
@@ -87,6 +151,13 @@ impl Envelope {
         self.state == EnvelopeState::Idle
     }
 
+    //Silent and idle, as if no note had played
+    pub fn reset(&mut self) {
+        self.state = EnvelopeState::Idle;
+        self.phase = 0.0;
+        self.current_level = 0.0;
+    }
+
     pub fn stop(&mut self) {
         self.state = EnvelopeState::Release;
         if self.state != EnvelopeState::Release && self.state != EnvelopeState::Idle {
@@ -160,22 +231,14 @@ impl Processor for Envelope {
 impl PrimitiveWave {
     pub fn new(frequency: f32, wave_type: WaveType, sample_rate: f32, envelope: Envelope) -> Self {
         PrimitiveWave {
-            wave_type,
+            oscillator: Oscillator::new(wave_type, OscillatorQuality::Standard),
             sample_rate,
-            phase: 0.0,
             frequency,
+            velocity: 1.0,
             envelope,
         }
     }
 
-    pub fn advance_phase(&mut self) {
-        self.phase += self.frequency / self.sample_rate;
-
-        if self.phase >= 1.0 {
-            self.phase -= 1.0
-        }
-    }
-
     pub fn get_ui(&self, frame: &mut Frame) {
         let block = Block::new()
             .border_style(Style::new().blue())
@@ -191,37 +254,14 @@ impl PrimitiveWave {
 
 impl Instrument for PrimitiveWave {
     fn process(&mut self) -> f32 {
-        let wave_result = match self.wave_type {
-            WaveType::Sine => (self.phase * 2.0 * std::f32::consts::PI).sin(),
-            WaveType::Square => {
-                if self.phase < 0.5 {
-                    1.0
-                } else {
-                    -1.0
-                }
-            }
-            WaveType::Triangle => {
-                if self.phase < 0.5 {
-                    // Rising: 0 -> 0.5 becomes -1 -> 1
-                    4.0 * self.phase - 1.0
-                } else {
-                    // Falling: 0.5 -> 1 becomes 1 -> -1
-                    -4.0 * self.phase + 3.0
-                }
-            }
-            WaveType::Saw => {
-                // Linear ramp from -1 to 1
-                2.0 * self.phase - 1.0
-            }
-        };
-
-        self.advance_phase();
+        let wave_result = self.oscillator.process(self.frequency, self.sample_rate); //Process also moves the phase
 
-        self.envelope.process(wave_result)
+        self.envelope.process(wave_result) * self.velocity
     }
 
-    fn note_on(&mut self, frequency: f32) {
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
         self.frequency = frequency;
+        self.velocity = velocity;
         self.envelope.start();
     }
 
@@ -234,7 +274,32 @@ impl Instrument for PrimitiveWave {
     }
 
     fn get_phase(&self) -> f32 {
-        self.phase
+        self.oscillator.phase()
+    }
+
+    fn set_quality(&mut self, quality: OscillatorQuality) {
+        self.oscillator.set_quality(quality);
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let wave = WAVE_TYPES
+            .iter()
+            .position(|wave_type| *wave_type == self.oscillator.wave_type())
+            .unwrap_or(0);
+
+        let mut parameters = vec![Parameter::choice("Wave", wave, &WAVE_NAMES)];
+        parameters.extend(self.envelope.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => {
+                let wave = (value.round().max(0.0) as usize).min(WAVE_TYPES.len() - 1);
+                self.oscillator.set_wave_type(WAVE_TYPES[wave]);
+            }
+            index => self.envelope.set_parameter(index - 1, value),
+        }
     }
 
     fn get_name(&self) -> &str {
@@ -0,0 +1,227 @@
+use std::{
+    error::Error,
+    f32::consts::PI,
+    path::{Path, PathBuf},
+};
+
+use rand::{RngExt, SeedableRng, rngs::SmallRng};
+
+use crate::{
+    generators::{Envelope, Instrument, Processor},
+    notes::Note,
+    parameter::Parameter,
+    sample::Sample,
+};
+
+//Grains past this many are dropped rather than allocating on the audio thread
+const MAX_GRAINS: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrainWindow {
+    Hann,
+    Triangle,
+    Trapezoid,
+    Gaussian,
+}
+
+pub const GRAIN_WINDOWS: [GrainWindow; 4] = [
+    GrainWindow::Hann,
+    GrainWindow::Triangle,
+    GrainWindow::Trapezoid,
+    GrainWindow::Gaussian,
+];
+
+pub const GRAIN_WINDOW_NAMES: [&str; 4] = ["Hann", "Triangle", "Trapezoid", "Gaussian"];
+
+impl GrainWindow {
+    //Gain at a point from 0 to 1 through the grain
+    pub fn gain(&self, t: f32) -> f32 {
+        match self {
+            GrainWindow::Hann => 0.5 - 0.5 * (2.0 * PI * t).cos(),
+            GrainWindow::Triangle => 1.0 - (2.0 * t - 1.0).abs(),
+            GrainWindow::Trapezoid => (4.0 * t.min(1.0 - t)).min(1.0),
+            GrainWindow::Gaussian => (-0.5 * ((t - 0.5) / 0.15).powi(2)).exp(),
+        }
+    }
+}
+
+struct Grain {
+    position: f64,
+    increment: f64,
+    age: usize,
+    length: usize,
+}
+
+//Plays overlapping windowed grains read from a loaded sample,
+//notes pitch the grains relative to C4
+pub struct GranularSampler {
+    name: String,
+    file: Option<PathBuf>,
+    sample: Sample,
+    sample_rate: f32,
+    grains: Vec<Grain>,
+    countdown: f32,
+    rng: SmallRng,
+    frequency: f32,
+    velocity: f32,
+    position: f32,
+    spray: f32,
+    size: f32,
+    density: f32,
+    pitch: f32,
+    window: GrainWindow,
+    envelope: Envelope,
+}
+
+impl GranularSampler {
+    pub fn new(sample_rate: f32, envelope: Envelope) -> Self {
+        GranularSampler {
+            name: "Granular".to_string(),
+            file: None,
+            sample: Sample::new(Vec::new(), sample_rate),
+            sample_rate,
+            grains: Vec::with_capacity(MAX_GRAINS),
+            countdown: 0.0,
+            rng: SmallRng::seed_from_u64(0),
+            frequency: Note::C4.freq(),
+            velocity: 1.0,
+            position: 0.0,
+            spray: 0.05,
+            size: 0.1,
+            density: 20.0,
+            pitch: 0.0,
+            window: GrainWindow::Hann,
+            envelope,
+        }
+    }
+
+    fn spawn(&mut self) {
+        if self.grains.len() == MAX_GRAINS || self.sample.is_empty() {
+            return;
+        }
+
+        let offset = self.spray * self.rng.random_range(-0.5..0.5);
+        let start = (self.position + offset).clamp(0.0, 1.0) * self.sample.len() as f32;
+        let ratio = self.frequency / Note::C4.freq() * 2f32.powf(self.pitch / 12.0);
+
+        self.grains.push(Grain {
+            position: start as f64,
+            increment: ratio as f64,
+            age: 0,
+            length: ((self.size * self.sample_rate) as usize).max(1),
+        });
+    }
+}
+
+impl Instrument for GranularSampler {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&mut self) -> f32 {
+        if self.envelope.is_active() {
+            self.countdown -= 1.0;
+            if self.countdown <= 0.0 {
+                self.spawn();
+                self.countdown += self.sample_rate / self.density;
+            }
+        }
+
+        let mut output = 0.0;
+        for grain in self.grains.iter_mut() {
+            let t = grain.age as f32 / grain.length as f32;
+            output += self.sample.read_cubic(grain.position) * self.window.gain(t);
+            grain.position += grain.increment;
+            grain.age += 1;
+        }
+        self.grains.retain(|grain| grain.age < grain.length);
+
+        //Grains are uncorrelated so they add up by power
+        let overlap = (self.size * self.density).max(1.0);
+        self.envelope.process(output / overlap.sqrt()) * self.velocity
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        self.frequency = frequency;
+        self.velocity = velocity;
+        self.countdown = 0.0;
+        self.envelope.start();
+    }
+
+    //Grains already playing finish on their own
+    fn note_off(&mut self) {
+        self.envelope.stop();
+    }
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        0.0
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let window = GRAIN_WINDOWS
+            .iter()
+            .position(|window| *window == self.window)
+            .unwrap_or(0);
+
+        let mut parameters = vec![
+            Parameter::new("Position", self.position, 0.0, 1.0, 0.01),
+            Parameter::new("Spray", self.spray, 0.0, 1.0, 0.01),
+            Parameter::new("Size", self.size, 0.005, 1.0, 0.1)
+                .unit("s")
+                .logarithmic(),
+            Parameter::new("Density", self.density, 1.0, 200.0, 0.1)
+                .unit("/s")
+                .logarithmic(),
+            Parameter::new("Pitch", self.pitch, -24.0, 24.0, 1.0).unit("st"),
+            Parameter::choice("Window", window, &GRAIN_WINDOW_NAMES),
+        ];
+        parameters.extend(self.envelope.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.position = value.clamp(0.0, 1.0),
+            1 => self.spray = value.clamp(0.0, 1.0),
+            2 => self.size = value.clamp(0.005, 1.0),
+            3 => self.density = value.clamp(1.0, 200.0),
+            4 => self.pitch = value.clamp(-24.0, 24.0),
+            5 => {
+                let window = (value.round().max(0.0) as usize).min(GRAIN_WINDOWS.len() - 1);
+                self.window = GRAIN_WINDOWS[window];
+            }
+            index => self.envelope.set_parameter(index - 6, value),
+        }
+    }
+
+    //The sample is converted to the engine rate once so grains only resample for pitch
+    fn load_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let sample = Sample::load(path)?;
+        if sample.is_empty() {
+            return Err("the file has no audio".into());
+        }
+
+        self.sample = sample.resampled(self.sample_rate);
+        self.grains.clear();
+
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        self.name = format!("Granular {}", file);
+        self.file = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    fn loaded_file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    fn reset(&mut self) {
+        self.grains.clear();
+        self.countdown = 0.0;
+        self.rng = SmallRng::seed_from_u64(0);
+        self.envelope.reset();
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use crate::{scales::Key, sequencer::PatternSnapshot, time_signature::TimeSignature, track::Track};
+
+//Oldest edits are dropped past this many undo steps
+pub const MAX_HISTORY: usize = 100;
+
+//A reversible change to the project, undone and redone by the mixer
+pub enum Edit {
+    Pattern {
+        track: usize,
+        before: PatternSnapshot,
+        after: PatternSnapshot,
+    },
+    Volume {
+        track: usize,
+        before: f32,
+        after: f32,
+    },
+    //A track added or removed. Holds the track while it is out of the mixer,
+    //so undo and redo both swap it in or out
+    TrackList {
+        id: usize,
+        index: usize,
+        track: Option<Box<Track>>,
+    },
+    Key {
+        before: Key,
+        after: Key,
+    },
+    TimeSignature {
+        before: TimeSignature,
+        after: TimeSignature,
+    },
+    //Edits made by a single command, undone together in reverse order
+    Group(Vec<Edit>),
+}
+
+impl Edit {
+    //Repeated volume nudges on one track become a single undo step
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                Edit::Volume { track, after, .. },
+                Edit::Volume {
+                    track: next_track,
+                    after: next_after,
+                    ..
+                },
+            ) if track == next_track => {
+                *after = *next_after;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+//Undo and redo stacks, edits pushed while a group is open are collected into one step
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    group: Option<Vec<Edit>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            group: None,
+        }
+    }
+
+    pub fn push(&mut self, edit: Edit) {
+        if let Some(group) = &mut self.group {
+            group.push(edit);
+            return;
+        }
+
+        self.redo.clear();
+
+        if let Some(last) = self.undo.back_mut()
+            && last.merge(&edit)
+        {
+            return;
+        }
+
+        self.undo.push_back(edit);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn begin_group(&mut self) {
+        self.group.get_or_insert_with(Vec::new);
+    }
+
+    //Closes the open group, a group with a single edit is stored as that edit
+    pub fn end_group(&mut self) {
+        let Some(mut edits) = self.group.take() else {
+            return;
+        };
+
+        match edits.len() {
+            0 => {}
+            1 => self.push(edits.remove(0)),
+            _ => self.push(Edit::Group(edits)),
+        }
+    }
+
+    pub fn take_undo(&mut self) -> Option<Edit> {
+        self.undo.pop_back()
+    }
+
+    pub fn take_redo(&mut self) -> Option<Edit> {
+        self.redo.pop()
+    }
+
+    //Stores an edit that was just undone so it can be redone
+    pub fn undone(&mut self, edit: Edit) {
+        self.redo.push(edit);
+    }
+
+    //Stores an edit that was just redone, keeping the rest of the redo stack
+    pub fn redone(&mut self, edit: Edit) {
+        self.undo.push_back(edit);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(track: usize, before: f32, after: f32) -> Edit {
+        Edit::Volume {
+            track,
+            before,
+            after,
+        }
+    }
+
+    fn volume_of(edit: Option<Edit>) -> (usize, f32, f32) {
+        match edit {
+            Some(Edit::Volume {
+                track,
+                before,
+                after,
+            }) => (track, before, after),
+            _ => panic!("expected a volume edit"),
+        }
+    }
+
+    #[test]
+    fn oldest_edits_are_dropped() {
+        let mut history = History::new();
+        for track in 0..MAX_HISTORY + 5 {
+            history.push(volume(track, 0.0, 1.0));
+        }
+
+        assert_eq!(history.undo_len(), MAX_HISTORY);
+        let mut oldest = None;
+        while let Some(edit) = history.take_undo() {
+            oldest = Some(edit);
+        }
+        assert_eq!(volume_of(oldest).0, 5);
+    }
+
+    #[test]
+    fn volume_edits_on_one_track_merge() {
+        let mut history = History::new();
+        history.push(volume(0, 0.5, 0.6));
+        history.push(volume(0, 0.6, 0.7));
+        assert_eq!(history.undo_len(), 1);
+        assert_eq!(volume_of(history.take_undo()), (0, 0.5, 0.7));
+
+        history.push(volume(0, 0.5, 0.6));
+        history.push(volume(1, 0.5, 0.6));
+        assert_eq!(history.undo_len(), 2);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut history = History::new();
+        history.push(volume(0, 0.5, 0.6));
+
+        let edit = history.take_undo().unwrap();
+        history.undone(edit);
+        assert_eq!(history.redo_len(), 1);
+
+        history.push(volume(1, 0.5, 0.6));
+        assert_eq!(history.redo_len(), 0);
+        assert!(history.take_redo().is_none());
+    }
+
+    #[test]
+    fn group_is_one_undo_step() {
+        let mut history = History::new();
+        history.begin_group();
+        history.push(volume(0, 0.5, 0.6));
+        history.push(volume(1, 0.5, 0.6));
+        history.end_group();
+
+        assert_eq!(history.undo_len(), 1);
+        assert!(matches!(history.take_undo(), Some(Edit::Group(edits)) if edits.len() == 2));
+    }
+}
@@ -0,0 +1,247 @@
+use std::{error::Error, fs, path::Path, sync::Mutex};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::{info, warn};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+use crate::{mixer::Mixer, preset::Preset, track::Track, user_interface::InputWindow};
+
+//Width of the parameter names on the left
+const NAME_WIDTH: u16 = 16;
+//Width of the value after the bar
+const VALUE_WIDTH: u16 = 12;
+//Steps moved by shift and an arrow
+const COARSE_STEPS: f32 = 10.0;
+
+//Parameters of the instrument on the selected mixer track
+pub struct InstrumentView {
+    selected: usize,
+    input_window: InputWindow,
+}
+
+impl InstrumentView {
+    pub fn new() -> Self {
+        InstrumentView {
+            selected: 0,
+            input_window: InputWindow::new(),
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    //True while the file prompt is taking text input
+    pub fn is_editing(&self) -> bool {
+        self.input_window.is_editing()
+    }
+
+    //Takes the mixer unlocked so files can load without holding up the audio thread
+    pub fn handle_keyboard_input(&mut self, key_event: KeyEvent, mixer: &Mutex<Mixer>) {
+        self.input_window.handle_keyboard_input(key_event);
+
+        if self.input_window.is_editing() || key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        let input = self.input_window.get_last_string_input().trim().to_string();
+        match key_event.code {
+            KeyCode::Char('l') => match Self::load_file(Path::new(&input), mixer) {
+                Ok(()) => info!("Loaded {}", input),
+                Err(e) => warn!("Could not load {}: {}", input, e),
+            },
+            KeyCode::Char('r') => match Self::load_preset(&input, mixer) {
+                Ok(()) => info!("Loaded preset {}", input),
+                Err(e) => warn!("Could not load preset {}: {}", input, e),
+            },
+            _ => {}
+        }
+
+        let Ok(mut mixer) = mixer.lock() else {
+            return;
+        };
+        let mixer = &mut *mixer;
+
+        let steps = if key_event.modifiers.contains(KeyModifiers::SHIFT) {
+            COARSE_STEPS
+        } else {
+            1.0
+        };
+
+        match key_event.code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected += 1,
+            KeyCode::Left => self.nudge_parameter(mixer, -steps),
+            KeyCode::Right => self.nudge_parameter(mixer, steps),
+            KeyCode::PageUp => mixer.previous_track(),
+            KeyCode::PageDown => mixer.next_track(),
+            KeyCode::Char('i') | KeyCode::Char('I') => {
+                let Some(kind) = mixer.selected_track().map(|track| track.instrument_kind()) else {
+                    return;
+                };
+                let kind = match key_event.code {
+                    KeyCode::Char('i') => kind.next(),
+                    _ => kind.previous(),
+                };
+                mixer.set_selected_instrument(kind);
+                self.selected = 0;
+            }
+            KeyCode::Char('w') => {
+                let Some(preset) = mixer.selected_track().and_then(|track| {
+                    let kind = track.instrument_kind();
+                    track
+                        .instrument()
+                        .map(|instrument| Preset::from_instrument(kind, instrument))
+                }) else {
+                    return;
+                };
+
+                match fs::write(&input, preset.to_string()) {
+                    Ok(()) => info!("Saved preset {}", input),
+                    Err(e) => warn!("Could not save preset {}: {}", input, e),
+                }
+            }
+            _ => {}
+        }
+
+        //Instruments have different parameter counts, keep the cursor on one of them
+        let count = mixer
+            .selected_track()
+            .and_then(|track| track.instrument())
+            .map(|instrument| instrument.parameters().len())
+            .unwrap_or(0);
+        self.selected = self.selected.min(count.saturating_sub(1));
+    }
+
+    //Loads the file into a copy of the selected instrument, keeping its settings
+    fn load_file(path: &Path, mixer: &Mutex<Mixer>) -> Result<(), Box<dyn Error>> {
+        let preset = {
+            let mut mixer = mixer.lock().map_err(|_| "the mixer is unavailable")?;
+            let track = mixer.selected_track().ok_or("no track is selected")?;
+            let kind = track.instrument_kind();
+            let instrument = track.instrument().ok_or("the track has no instrument")?;
+            Preset::from_instrument(kind, instrument)
+        };
+
+        Self::swap_instrument(&preset, Some(path), mixer)
+    }
+
+    //Builds the preset's instrument and loads the file it was saved with
+    fn load_preset(path: &str, mixer: &Mutex<Mixer>) -> Result<(), Box<dyn Error>> {
+        let preset = fs::read_to_string(path)?.parse::<Preset>()?;
+        Self::swap_instrument(&preset, preset.file(), mixer)
+    }
+
+    //Builds the instrument and reads its file with the mixer unlocked so the audio thread keeps running,
+    //then swaps it onto the selected track. The old instrument is dropped after the lock is released
+    fn swap_instrument(
+        preset: &Preset,
+        file: Option<&Path>,
+        mixer: &Mutex<Mixer>,
+    ) -> Result<(), Box<dyn Error>> {
+        let (sample_rate, quality) = {
+            let mut mixer = mixer.lock().map_err(|_| "the mixer is unavailable")?;
+            let quality = mixer.oscillator_quality();
+            let track = mixer.selected_track().ok_or("no track is selected")?;
+            (track.sample_rate(), quality)
+        };
+
+        let mut instrument = preset.kind().build(sample_rate);
+        instrument.set_quality(quality);
+        preset.apply(instrument.as_mut());
+        if let Some(file) = file {
+            instrument.load_file(file)?;
+        }
+
+        let _replaced = {
+            let mut mixer = mixer.lock().map_err(|_| "the mixer is unavailable")?;
+            let track = mixer.selected_track().ok_or("no track is selected")?;
+            track.set_instrument(preset.kind(), instrument)
+        };
+        Ok(())
+    }
+
+    fn nudge_parameter(&self, mixer: &mut Mixer, steps: f32) {
+        let Some(instrument) = mixer
+            .selected_track()
+            .and_then(|track| track.instrument_mut())
+        else {
+            return;
+        };
+
+        if let Some(parameter) = instrument.parameters().get(self.selected) {
+            instrument.set_parameter(self.selected, parameter.nudged(steps));
+        }
+    }
+}
+
+impl Default for InstrumentView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Instrument view paired with the track it edits, built for every frame
+pub struct InstrumentEditor<'a> {
+    pub view: &'a InstrumentView,
+    pub track: &'a Track,
+}
+
+impl Widget for InstrumentEditor<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let Some(instrument) = self.track.instrument() else {
+            return;
+        };
+
+        let bar_width = area.width.saturating_sub(NAME_WIDTH + VALUE_WIDTH + 2) as usize;
+
+        for (row, parameter) in instrument.parameters().iter().enumerate() {
+            if row as u16 >= area.height {
+                break;
+            }
+
+            let y = area.y + row as u16;
+            let style = if row == self.view.selected {
+                Style::default().fg(Color::Black).bg(Color::Green)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+
+            let filled = (parameter.normalised() * bar_width as f32).round() as usize;
+            let bar = format!(
+                "{}{}",
+                "█".repeat(filled),
+                "·".repeat(bar_width.saturating_sub(filled))
+            );
+
+            buf.set_string(
+                area.x,
+                y,
+                format!("{:<width$}", parameter.name, width = NAME_WIDTH as usize),
+                style,
+            );
+            buf.set_string(
+                area.x + NAME_WIDTH + 1,
+                y,
+                bar,
+                Style::default().fg(Color::Cyan),
+            );
+            buf.set_string(
+                area.x + NAME_WIDTH + 2 + bar_width as u16,
+                y,
+                parameter.to_string(),
+                Style::default().fg(Color::White),
+            );
+        }
+
+        self.view.input_window.render(area, buf);
+    }
+}
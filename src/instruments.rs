@@ -0,0 +1,131 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    drums::{Clap, HiHat, Kick, Snare},
+    fm::FmSynth,
+    generators::{Envelope, Instrument, PrimitiveWave, WaveType},
+    granular::GranularSampler,
+    multisample::MultiSampler,
+    noise::NoiseInstrument,
+    pluck::PluckedString,
+    subtractive::SubtractiveSynth,
+    wavetable_synth::WavetableSynth,
+};
+
+//Instruments a track can play, picked in the instrument window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstrumentKind {
+    Primitive,
+    Wavetable,
+    Subtractive,
+    Fm,
+    Kick,
+    Snare,
+    HiHat,
+    Clap,
+    Noise,
+    Pluck,
+    Granular,
+    MultiSample,
+}
+
+//Order of the instruments in the instrument window
+pub const INSTRUMENT_KINDS: [InstrumentKind; 12] = [
+    InstrumentKind::Primitive,
+    InstrumentKind::Wavetable,
+    InstrumentKind::Subtractive,
+    InstrumentKind::Fm,
+    InstrumentKind::Kick,
+    InstrumentKind::Snare,
+    InstrumentKind::HiHat,
+    InstrumentKind::Clap,
+    InstrumentKind::Noise,
+    InstrumentKind::Pluck,
+    InstrumentKind::Granular,
+    InstrumentKind::MultiSample,
+];
+
+impl InstrumentKind {
+    fn index(&self) -> usize {
+        INSTRUMENT_KINDS
+            .iter()
+            .position(|kind| kind == self)
+            .unwrap_or(0)
+    }
+
+    pub fn next(&self) -> Self {
+        INSTRUMENT_KINDS[(self.index() + 1) % INSTRUMENT_KINDS.len()]
+    }
+
+    pub fn previous(&self) -> Self {
+        INSTRUMENT_KINDS[(self.index() + INSTRUMENT_KINDS.len() - 1) % INSTRUMENT_KINDS.len()]
+    }
+
+    //New instrument of this kind with default settings
+    pub fn build(&self, sample_rate: f32) -> Box<dyn Instrument> {
+        let envelope = Envelope::new(0.010, 0.01, 1.0, 0.03, sample_rate);
+
+        match self {
+            InstrumentKind::Primitive => Box::new(PrimitiveWave::new(
+                144.0,
+                WaveType::Sine,
+                sample_rate,
+                envelope,
+            )),
+            InstrumentKind::Wavetable => Box::new(WavetableSynth::new(sample_rate, envelope)),
+            InstrumentKind::Subtractive => Box::new(SubtractiveSynth::new(sample_rate, envelope)),
+            InstrumentKind::Fm => Box::new(FmSynth::new(sample_rate)),
+            InstrumentKind::Kick => Box::new(Kick::new(sample_rate)),
+            InstrumentKind::Snare => Box::new(Snare::new(sample_rate)),
+            InstrumentKind::HiHat => Box::new(HiHat::new(sample_rate)),
+            InstrumentKind::Clap => Box::new(Clap::new(sample_rate)),
+            InstrumentKind::Noise => Box::new(NoiseInstrument::new(envelope)),
+            InstrumentKind::Pluck => Box::new(PluckedString::new(sample_rate)),
+            InstrumentKind::Granular => Box::new(GranularSampler::new(sample_rate, envelope)),
+            InstrumentKind::MultiSample => Box::new(MultiSampler::new(sample_rate, envelope)),
+        }
+    }
+}
+
+impl fmt::Display for InstrumentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InstrumentKind::Primitive => "Primitive Wave",
+            InstrumentKind::Wavetable => "Wavetable",
+            InstrumentKind::Subtractive => "Subtractive",
+            InstrumentKind::Fm => "FM",
+            InstrumentKind::Kick => "Kick",
+            InstrumentKind::Snare => "Snare",
+            InstrumentKind::HiHat => "Hi-Hat",
+            InstrumentKind::Clap => "Clap",
+            InstrumentKind::Noise => "Noise",
+            InstrumentKind::Pluck => "Plucked String",
+            InstrumentKind::Granular => "Granular",
+            InstrumentKind::MultiSample => "Multi-Sample",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseInstrumentKindError;
+
+impl fmt::Display for ParseInstrumentKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid instrument string")
+    }
+}
+
+impl std::error::Error for ParseInstrumentKindError {}
+
+//Takes the names shown in the instrument window, ignoring case
+impl FromStr for InstrumentKind {
+    type Err = ParseInstrumentKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        INSTRUMENT_KINDS
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or(ParseInstrumentKindError)
+    }
+}
@@ -0,0 +1,193 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::notes::Note;
+
+//Semitones above C for the lower and upper rows of a QWERTY keyboard, like most trackers
+const LOWER_ROW: [(char, u8); 17] = [
+    ('z', 0),
+    ('s', 1),
+    ('x', 2),
+    ('d', 3),
+    ('c', 4),
+    ('v', 5),
+    ('g', 6),
+    ('b', 7),
+    ('h', 8),
+    ('n', 9),
+    ('j', 10),
+    ('m', 11),
+    (',', 12),
+    ('l', 13),
+    ('.', 14),
+    (';', 15),
+    ('/', 16),
+];
+const UPPER_ROW: [(char, u8); 17] = [
+    ('q', 12),
+    ('2', 13),
+    ('w', 14),
+    ('3', 15),
+    ('e', 16),
+    ('r', 17),
+    ('5', 18),
+    ('t', 19),
+    ('6', 20),
+    ('y', 21),
+    ('7', 22),
+    ('u', 23),
+    ('i', 24),
+    ('9', 25),
+    ('o', 26),
+    ('0', 27),
+    ('p', 28),
+];
+
+const MAX_OCTAVE: u8 = 7;
+
+//Where notes played on the piano go besides being heard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PianoRecord {
+    Off,
+    //Writes to the cursor step and moves on
+    Step,
+    //Writes at the transport position while playing
+    Live,
+}
+
+impl PianoRecord {
+    fn next(&self) -> Self {
+        match self {
+            PianoRecord::Off => PianoRecord::Step,
+            PianoRecord::Step => PianoRecord::Live,
+            PianoRecord::Live => PianoRecord::Off,
+        }
+    }
+}
+
+//What a key pressed in piano mode asks the track to do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PianoAction {
+    Play(Note),
+    //Step record only, clears the step at the cursor and moves on
+    Rest,
+    Back,
+}
+
+//Plays the computer keyboard like a piano, the lower row starts at the base octave
+pub struct KeyboardPiano {
+    enabled: bool,
+    record: PianoRecord,
+    octave: u8,
+}
+
+impl KeyboardPiano {
+    pub fn new() -> Self {
+        KeyboardPiano {
+            enabled: false,
+            record: PianoRecord::Off,
+            octave: 4,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    //Keys the piano plays or is controlled with while enabled, every other key stays global
+    pub fn takes_key(&self, key_event: KeyEvent) -> bool {
+        if !self.enabled || key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+
+        match key_event.code {
+            KeyCode::Esc
+            | KeyCode::Enter
+            | KeyCode::Up
+            | KeyCode::Down
+            | KeyCode::Left
+            | KeyCode::Right => true,
+            KeyCode::Delete | KeyCode::Backspace => self.is_step_recording(),
+            KeyCode::Char(key) => self.note_for_key(key).is_some(),
+            _ => false,
+        }
+    }
+
+    pub fn record(&self) -> PianoRecord {
+        self.record
+    }
+
+    pub fn is_step_recording(&self) -> bool {
+        self.enabled && self.record == PianoRecord::Step
+    }
+
+    pub fn is_live_recording(&self) -> bool {
+        self.enabled && self.record == PianoRecord::Live
+    }
+
+    pub fn octave(&self) -> u8 {
+        self.octave
+    }
+
+    pub fn note_for_key(&self, key: char) -> Option<Note> {
+        let semitone = LOWER_ROW
+            .iter()
+            .chain(UPPER_ROW.iter())
+            .find(|(row_key, _)| *row_key == key)
+            .map(|(_, semitone)| *semitone)?;
+
+        Note::from_midi(Note::C0.midi() + self.octave * 12 + semitone)
+    }
+
+    pub fn handle_keyboard_input(&mut self, key_event: KeyEvent) -> Option<PianoAction> {
+        match key_event.code {
+            KeyCode::Esc => self.enabled = false,
+            KeyCode::Enter => self.record = self.record.next(),
+            KeyCode::Up => self.octave = (self.octave + 1).min(MAX_OCTAVE),
+            KeyCode::Down => self.octave = self.octave.saturating_sub(1),
+            KeyCode::Delete if self.is_step_recording() => return Some(PianoAction::Rest),
+            KeyCode::Backspace if self.is_step_recording() => return Some(PianoAction::Back),
+            KeyCode::Char(key) => return self.note_for_key(key).map(PianoAction::Play),
+            _ => {}
+        }
+
+        None
+    }
+}
+
+impl Default for KeyboardPiano {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn enabled_piano_leaves_global_keys() {
+        let mut piano = KeyboardPiano::new();
+        piano.toggle();
+
+        assert!(piano.takes_key(press(KeyCode::Char('z'))));
+        assert!(piano.takes_key(press(KeyCode::Char('q'))));
+        assert!(!piano.takes_key(press(KeyCode::Char(' '))));
+        assert!(!piano.takes_key(press(KeyCode::Char('+'))));
+        assert!(!piano.takes_key(press(KeyCode::Char('k'))));
+        assert!(!piano.takes_key(KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL)));
+    }
+
+    #[test]
+    fn disabled_piano_takes_no_keys() {
+        let piano = KeyboardPiano::new();
+        assert!(!piano.takes_key(press(KeyCode::Char('z'))));
+    }
+}
@@ -1,9 +1,41 @@
 pub mod app;
+pub mod clipboard;
+pub mod drums;
 pub mod engine;
+pub mod euclidean;
+pub mod fft;
+pub mod filter;
+pub mod fm;
 pub mod generators;
+pub mod granular;
+pub mod history;
 pub mod input_handeler;
+pub mod instrument_view;
+pub mod instruments;
+pub mod keyboard_piano;
+pub mod metronome;
 pub mod mixer;
+pub mod multisample;
+pub mod noise;
+pub mod note_processors;
 pub mod notes;
+pub mod oscillator;
+pub mod parameter;
+pub mod piano_roll;
+pub mod pluck;
+pub mod preset;
+pub mod randomise;
+pub mod recorder;
+pub mod sample;
+pub mod scales;
 pub mod sequencer;
+pub mod step_grid;
+pub mod subtractive;
+pub mod tempo;
+pub mod time_signature;
 pub mod track;
+pub mod tracker;
+pub mod transform;
 pub mod user_interface;
+pub mod wavetable;
+pub mod wavetable_synth;
@@ -0,0 +1,235 @@
+use std::fmt;
+
+use rand::{RngExt, SeedableRng, rngs::SmallRng};
+
+use crate::time_signature::TimeSignature;
+
+const LEVEL_STEP: f32 = 0.1;
+const MAX_COUNT_IN_BARS: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ClickSound {
+    Beep,
+    Click,
+    Woodblock,
+}
+
+impl ClickSound {
+    fn next(self) -> Self {
+        match self {
+            ClickSound::Beep => ClickSound::Click,
+            ClickSound::Click => ClickSound::Woodblock,
+            ClickSound::Woodblock => ClickSound::Beep,
+        }
+    }
+}
+
+impl fmt::Display for ClickSound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ClickSound::Beep => "Beep",
+            ClickSound::Click => "Click",
+            ClickSound::Woodblock => "Woodblock",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Click that follows the transport, mixed in after the tracks so it is not a track itself
+pub struct Metronome {
+    enabled: bool,
+    level: f32,
+    sound: ClickSound,
+    count_in_bars: u8,
+    sample_rate: f32,
+    phase: f32,
+    frequency: f32,
+    amplitude: f32,
+    decay: f32,
+    rng: SmallRng,
+}
+
+impl Metronome {
+    pub fn new(sample_rate: f32) -> Self {
+        Metronome {
+            enabled: false,
+            level: 0.5,
+            sound: ClickSound::Beep,
+            count_in_bars: 0,
+            sample_rate,
+            phase: 0.0,
+            frequency: 0.0,
+            amplitude: 0.0,
+            decay: 0.0,
+            rng: SmallRng::seed_from_u64(0),
+        }
+    }
+
+    //Adds clicks to the output, `start` is the transport position in quarter notes
+    //and `quarters_per_sample` how far it moves each sample. Forced clicks play while disabled
+    pub fn process_block(
+        &mut self,
+        output: &mut [f32],
+        start: f64,
+        quarters_per_sample: f64,
+        time_signature: TimeSignature,
+        force: bool,
+    ) {
+        let quarters_per_beat = time_signature.quarters_per_beat();
+
+        for (i, sample) in output.iter_mut().enumerate() {
+            let position = start + i as f64 * quarters_per_sample;
+            let beat = (position / quarters_per_beat).floor();
+
+            //First sample on or after a beat boundary
+            if (self.enabled || force) && position - beat * quarters_per_beat < quarters_per_sample
+            {
+                let accent = (beat as i64).rem_euclid(time_signature.beats_per_bar() as i64) == 0;
+                self.trigger(accent);
+            }
+
+            *sample += self.next_sample() * self.level;
+        }
+    }
+
+    fn trigger(&mut self, accent: bool) {
+        let (frequency, length) = match self.sound {
+            ClickSound::Beep => (if accent { 1600.0 } else { 1000.0 }, 0.04),
+            ClickSound::Click => (0.0, 0.01),
+            ClickSound::Woodblock => (if accent { 1200.0 } else { 800.0 }, 0.025),
+        };
+
+        self.frequency = frequency;
+        self.amplitude = if accent { 1.0 } else { 0.6 };
+        self.phase = 0.0;
+        //Exponential decay down to -60dB over the click length
+        self.decay = 0.001f32.powf(1.0 / (length * self.sample_rate));
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if self.amplitude < 0.0001 {
+            return 0.0;
+        }
+
+        let tau = 2.0 * std::f32::consts::PI;
+        let sample = match self.sound {
+            ClickSound::Beep => (self.phase * tau).sin(),
+            ClickSound::Click => self.rng.random_range(-1.0..1.0),
+            ClickSound::Woodblock => {
+                (self.phase * tau).sin() * 0.7 + (self.phase * 2.7 * tau).sin() * 0.3
+            }
+        };
+
+        self.phase = (self.phase + self.frequency / self.sample_rate).fract();
+        self.amplitude *= self.decay;
+
+        sample * self.amplitude
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: f32) {
+        self.level = level.clamp(0.0, 1.0);
+    }
+
+    pub fn increase_level(&mut self) {
+        self.set_level(self.level + LEVEL_STEP);
+    }
+
+    pub fn decrease_level(&mut self) {
+        self.set_level(self.level - LEVEL_STEP);
+    }
+
+    pub fn sound(&self) -> ClickSound {
+        self.sound
+    }
+
+    pub fn next_sound(&mut self) {
+        self.sound = self.sound.next();
+    }
+
+    pub fn count_in_bars(&self) -> u8 {
+        self.count_in_bars
+    }
+
+    //Cycles the count-in through off, one and two bars
+    pub fn cycle_count_in(&mut self) {
+        self.count_in_bars = (self.count_in_bars + 1) % (MAX_COUNT_IN_BARS + 1);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    //Beats clicked over two bars at 120 bpm, and whether each one was accented
+    fn clicks(time_signature: TimeSignature) -> Vec<(usize, bool)> {
+        let mut metronome = Metronome::new(SAMPLE_RATE);
+        metronome.toggle();
+        let quarters_per_sample = 2.0 / SAMPLE_RATE as f64;
+        let samples = (2.0 * time_signature.quarters_per_bar() / quarters_per_sample) as usize;
+
+        let mut clicks = Vec::new();
+        for i in 0..samples {
+            let amplitude = metronome.amplitude;
+            let position = i as f64 * quarters_per_sample;
+            metronome.process_block(
+                &mut [0.0],
+                position,
+                quarters_per_sample,
+                time_signature,
+                false,
+            );
+
+            if metronome.amplitude > amplitude {
+                let beat = (position / time_signature.quarters_per_beat()).round() as usize;
+                clicks.push((beat, metronome.frequency == 1600.0));
+            }
+        }
+        clicks
+    }
+
+    #[test]
+    fn accent_falls_on_the_first_beat() {
+        for time_signature in [TimeSignature::new(4, 4), TimeSignature::new(7, 8)] {
+            let beats = time_signature.beats_per_bar() as usize;
+            let expected: Vec<(usize, bool)> = (0..beats * 2)
+                .map(|beat| (beat, beat % beats == 0))
+                .collect();
+            assert_eq!(clicks(time_signature), expected);
+        }
+    }
+
+    #[test]
+    fn zero_level_is_silent() {
+        let mut metronome = Metronome::new(SAMPLE_RATE);
+        metronome.toggle();
+        metronome.set_level(0.0);
+
+        let mut output = vec![0.0; 48000];
+        metronome.process_block(
+            &mut output,
+            0.0,
+            2.0 / SAMPLE_RATE as f64,
+            TimeSignature::new(4, 4),
+            false,
+        );
+        assert!(output.iter().all(|sample| *sample == 0.0));
+    }
+}
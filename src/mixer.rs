@@ -1,18 +1,32 @@
 use std::collections::HashMap;
 
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::warn;
 use ratatui::{
-    Frame,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
-    widgets::{Bar, BarChart, BarGroup, Block, Borders, Widget},
+    widgets::{Block, Borders, Widget},
 };
 
 use crate::{
-    generators::{Envelope, PrimitiveWave, WaveType},
+    clipboard::Clipboard,
+    history::{Edit, History},
+    instruments::InstrumentKind,
+    metronome::Metronome,
+    oscillator::OscillatorQuality,
+    scales::Key,
+    sequencer::{PatternSnapshot, Sequencer},
+    tempo::{MAX_BPM, MIN_BPM, TempoEvent, TempoMap},
+    time_signature::TimeSignature,
     track::Track,
+    user_interface::InputWindow,
 };
 
+//Tempo map changes are picked up at this interval in samples
+const TEMPO_UPDATE_INTERVAL: usize = 64;
+//Sixteenth notes, new tracks start with this until it is changed in the sequencer
+pub const DEFAULT_STEP_DIVISION: u8 = 4;
+
 //Sums all levels from track and mixes it together
 //Also used for managing track behavoir on a high level
 pub struct Mixer {
@@ -24,6 +38,20 @@ pub struct Mixer {
     increment_volume: f32,
     bpm: f32,
     next_id: usize,
+    tempo_map: TempoMap,
+    beat_position: f64,
+    time_signature: TimeSignature,
+    //Transport position and bar count where the current meter took over
+    meter_start_position: f64,
+    meter_start_bar: f64,
+    metronome: Metronome,
+    key: Key,
+    clipboard: Clipboard,
+    history: History,
+    count_in_position: f64,
+    count_in_length: f64,
+    oscillator_quality: OscillatorQuality,
+    input_window: InputWindow,
 }
 
 impl Mixer {
@@ -37,29 +65,138 @@ impl Mixer {
             increment_volume: 0.1,
             bpm,
             next_id: 0,
+            tempo_map: TempoMap::new(),
+            beat_position: 0.0,
+            time_signature: TimeSignature::default(),
+            meter_start_position: 0.0,
+            meter_start_bar: 0.0,
+            metronome: Metronome::new(sample_rate),
+            key: Key::default(),
+            clipboard: Clipboard::new(),
+            history: History::new(),
+            count_in_position: 0.0,
+            count_in_length: 0.0,
+            oscillator_quality: OscillatorQuality::Standard,
+            input_window: InputWindow::new(),
         }
     }
 
     //Main audio processing function
     pub fn process_block(&mut self, num_samples: usize) -> Vec<f32> {
         let mut mix = vec![0.0f32; num_samples];
+        let mut clicks = vec![0.0f32; num_samples];
+
+        //Process in small chunks so tempo changes land close to their bar
+        let mut offset = 0;
+        while offset < num_samples {
+            let mut chunk_len = TEMPO_UPDATE_INTERVAL.min(num_samples - offset);
+            let quarters_per_sample = self.bpm as f64 / 60.0 / self.sample_rate as f64;
+
+            //Count-in only clicks, the tracks and transport wait until it is done
+            if self.count_in_position < self.count_in_length {
+                //Rounded so the drift of adding up positions does not add a sample
+                let remaining = ((self.count_in_length - self.count_in_position)
+                    / quarters_per_sample)
+                    .round()
+                    .max(1.0) as usize;
+                chunk_len = chunk_len.min(remaining);
+
+                self.metronome.process_block(
+                    &mut clicks[offset..offset + chunk_len],
+                    self.count_in_position,
+                    quarters_per_sample,
+                    self.time_signature,
+                    true,
+                );
+
+                self.count_in_position = if chunk_len == remaining {
+                    self.count_in_length
+                } else {
+                    self.count_in_position + chunk_len as f64 * quarters_per_sample
+                };
+                offset += chunk_len;
+                continue;
+            }
 
-        self.tracks.iter_mut().for_each(|(_i, track)| {
-            let track_output = track.process_block(num_samples);
-
-            for (i, sample) in track_output.iter().enumerate() {
-                mix[i] += sample;
+            if let Some(bpm) = self.tempo_map.bpm_at(self.bar_position())
+                && bpm != self.bpm
+            {
+                self.set_bpm(bpm);
             }
-        });
 
-        for sample in &mut mix {
+            let quarters_per_sample = self.bpm as f64 / 60.0 / self.sample_rate as f64;
+            let position = self.beat_position;
+            self.tracks.iter_mut().for_each(|(_i, track)| {
+                let track_output = track.process_block(chunk_len, position, quarters_per_sample);
+
+                for (i, sample) in track_output.iter().enumerate() {
+                    mix[offset + i] += sample;
+                }
+            });
+
+            self.metronome.process_block(
+                &mut clicks[offset..offset + chunk_len],
+                self.meter_position(),
+                quarters_per_sample,
+                self.time_signature,
+                false,
+            );
+
+            self.beat_position += chunk_len as f64 * quarters_per_sample;
+            offset += chunk_len;
+        }
+
+        //Clicks bypass the master volume so they stay audible
+        for (sample, click) in mix.iter_mut().zip(clicks) {
             *sample = (*sample * self.master_volume).tanh(); //Softclipping 
-            *sample = sample.clamp(-1.0, 1.0);
+            *sample = (*sample + click).clamp(-1.0, 1.0);
+        }
+
+        mix
+    }
+
+    //Audio while the transport is stopped, only auditioned notes and release tails are heard
+    pub fn process_idle(&mut self, num_samples: usize) -> Vec<f32> {
+        let mut mix = vec![0.0f32; num_samples];
+
+        for track in self.tracks.values_mut() {
+            for (sample, track_sample) in mix.iter_mut().zip(track.process_idle(num_samples)) {
+                *sample += track_sample;
+            }
+        }
+
+        for sample in mix.iter_mut() {
+            *sample = (*sample * self.master_volume).tanh();
         }
 
         mix
     }
 
+    //Releases every sounding note and ends live takes, called when playback stops
+    pub fn release_all(&mut self) {
+        for track in self.tracks.values_mut() {
+            track.release();
+            track.end_take();
+        }
+
+        self.collect_takes();
+    }
+
+    //Every finished live recording take becomes its own undo step.
+    //Called from the UI thread so the audio callback never touches the history
+    pub fn collect_takes(&mut self) {
+        for (id, track) in self.tracks.iter_mut() {
+            track.sync_take();
+            if let Some(take) = track.take_finished() {
+                self.history.push(Edit::Pattern {
+                    track: *id,
+                    before: take.before,
+                    after: take.after,
+                });
+            }
+        }
+    }
+
     //Create new track and store the id in the hashmap
     pub fn add_track(
         &mut self,
@@ -73,13 +210,12 @@ impl Mixer {
         self.next_id += 1;
 
         let mut track = Track::new(volume, name, sample_rate, self.bpm, length, step_division);
+        track
+            .sequencer_mut()
+            .set_time_signature(self.time_signature);
+        track.sequencer_mut().set_project_key(self.key);
 
-        track.set_instrument(Box::new(PrimitiveWave::new(
-            144.0,
-            WaveType::Sine,
-            sample_rate,
-            Envelope::new(0.010, 0.01, 1.0, 0.03, sample_rate),
-        )));
+        track.set_instrument_kind(InstrumentKind::Primitive, self.oscillator_quality);
 
         self.tracks.insert(id, track);
 
@@ -89,7 +225,7 @@ impl Mixer {
     }
 
     // ---- Track controls ----
-    fn next_track(&mut self) {
+    pub fn next_track(&mut self) {
         if self.track_order.is_empty() {
             return;
         }
@@ -97,7 +233,7 @@ impl Mixer {
         self.selected_index = (self.selected_index + 1) % self.track_order.len();
     }
 
-    fn previous_track(&mut self) {
+    pub fn previous_track(&mut self) {
         if self.tracks.is_empty() {
             return;
         }
@@ -110,40 +246,89 @@ impl Mixer {
     }
 
     pub fn remove_selected_track(&mut self) {
-        if self.track_order.is_empty() {
+        if let Some(&id) = self.track_order.get(self.selected_index) {
+            let index = self.selected_index;
+            let mut track = None;
+            self.swap_track(id, index, &mut track);
+            self.history.push(Edit::TrackList { id, index, track });
+        }
+    }
+
+    //Puts a stored track back at `index`, or takes the track out of the mixer into `slot`
+    fn swap_track(&mut self, id: usize, index: usize, slot: &mut Option<Box<Track>>) {
+        if let Some(track) = slot.take() {
+            let index = index.min(self.track_order.len());
+            self.tracks.insert(id, *track);
+            self.track_order.insert(index, id);
+            self.selected_index = index;
             return;
         }
 
-        let id = self.track_order[self.selected_index];
-        self.tracks.remove(&id);
-        self.track_order.remove(self.selected_index);
+        let Some(position) = self.track_order.iter().position(|t| *t == id) else {
+            return;
+        };
+
+        self.track_order.remove(position);
+        *slot = self.tracks.remove(&id).map(Box::new);
 
         if self.selected_index >= self.track_order.len() && self.selected_index > 0 {
             self.selected_index -= 1;
         }
     }
 
+    fn select_track_id(&mut self, id: usize) {
+        if let Some(index) = self.track_order.iter().position(|t| *t == id) {
+            self.selected_index = index;
+        }
+    }
+
     pub fn selected_track(&mut self) -> Option<&mut Track> {
         self.track_order
             .get(self.selected_index)
             .and_then(|id| self.tracks.get_mut(id))
     }
 
+    //Tracks in the order they are shown
+    pub fn ordered_tracks(&self) -> impl Iterator<Item = &Track> {
+        self.track_order.iter().filter_map(|id| self.tracks.get(id))
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
     pub fn get_track_id(&mut self, id: usize) -> Option<&mut Track> {
         self.tracks.get_mut(&id)
     }
 
     fn increment_selected_track_volume(&mut self) {
         let inccrement = self.increment_volume;
-        if let Some(track) = self.selected_track() {
-            track.increse_volume(inccrement);
-        }
+        self.edit_selected_track_volume(|track| track.increse_volume(inccrement));
     }
 
     fn decrease_selected_track_volume(&mut self) {
         let increment = self.increment_volume;
-        if let Some(track) = self.selected_track() {
-            track.decrease_volume(increment);
+        self.edit_selected_track_volume(|track| track.decrease_volume(increment));
+    }
+
+    fn edit_selected_track_volume(&mut self, action: impl FnOnce(&mut Track)) {
+        let Some(&id) = self.track_order.get(self.selected_index) else {
+            return;
+        };
+        let Some(track) = self.tracks.get_mut(&id) else {
+            return;
+        };
+
+        let before = track.get_volume();
+        action(track);
+        let after = track.get_volume();
+
+        if before != after {
+            self.history.push(Edit::Volume {
+                track: id,
+                before,
+                after,
+            });
         }
     }
 
@@ -151,8 +336,149 @@ impl Mixer {
         self.master_volume = vol.clamp(0.0, 2.0);
     }
 
+    //Renders a number of bars without an audio device, starting from the top
+    pub fn render_offline(&mut self, bars: u32) -> Vec<f32> {
+        self.rewind();
+
+        let mut output = Vec::new();
+        while self.bar_position() < bars as f64 {
+            output.extend(self.process_block(TEMPO_UPDATE_INTERVAL));
+        }
+
+        output
+    }
+
+    //Called when playback starts, queues the count-in if one is set
+    pub fn begin_playback(&mut self) {
+        self.count_in_position = 0.0;
+        self.count_in_length =
+            self.metronome.count_in_bars() as f64 * self.time_signature.quarters_per_bar();
+    }
+
+    pub fn is_counting_in(&self) -> bool {
+        self.count_in_position < self.count_in_length
+    }
+
+    pub fn metronome(&self) -> &Metronome {
+        &self.metronome
+    }
+
+    pub fn metronome_mut(&mut self) -> &mut Metronome {
+        &mut self.metronome
+    }
+
+    //Moves the transport and all sequencers back to the start
+    pub fn rewind(&mut self) {
+        self.beat_position = 0.0;
+        self.meter_start_position = 0.0;
+        self.meter_start_bar = 0.0;
+        self.count_in_length = 0.0;
+
+        for track in self.tracks.values_mut() {
+            track.reset();
+        }
+    }
+
+    //Position of the transport in bars, starting at 0.
+    //Bars are counted on from where the meter last changed so the count never jumps
+    pub fn bar_position(&self) -> f64 {
+        self.meter_start_bar
+            + (self.beat_position - self.meter_start_position)
+                / self.time_signature.quarters_per_bar()
+    }
+
+    //Position in quarter notes as if the whole song was in the current meter, keeps clicks on the bars
+    fn meter_position(&self) -> f64 {
+        self.bar_position() * self.time_signature.quarters_per_bar()
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    //Sets the project meter, new tracks and the transport bar count follow it
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.meter_start_bar = self.bar_position();
+        self.meter_start_position = self.beat_position;
+        self.time_signature = time_signature;
+    }
+
+    pub fn key(&self) -> Key {
+        self.key
+    }
+
+    //Oscillator quality new instruments are built with
+    pub fn oscillator_quality(&self) -> OscillatorQuality {
+        self.oscillator_quality
+    }
+
+    //Quality is a project setting, every track follows it
+    pub fn set_oscillator_quality(&mut self, quality: OscillatorQuality) {
+        self.oscillator_quality = quality;
+
+        for track in self.tracks.values_mut() {
+            track.set_oscillator_quality(quality);
+        }
+    }
+
+    //New instrument on the selected track, built with the project oscillator quality
+    pub fn set_selected_instrument(&mut self, kind: InstrumentKind) {
+        let quality = self.oscillator_quality;
+        if let Some(track) = self.selected_track() {
+            track.set_instrument_kind(kind, quality);
+        }
+    }
+
+    //Sets the project key, tracks without their own key follow it
+    pub fn set_key(&mut self, key: Key) {
+        self.key = key;
+
+        for track in self.tracks.values_mut() {
+            track.sequencer_mut().set_project_key(key);
+        }
+    }
+
+    //Length in steps of a one bar pattern in the project meter
+    pub fn default_pattern_length(&self, step_division: u8) -> usize {
+        self.time_signature.steps_per_bar(step_division)
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    pub fn tempo_map_mut(&mut self) -> &mut TempoMap {
+        &mut self.tempo_map
+    }
+
+    //Adds the tempo change written in the prompt, e.g. "9 140 ramp"
+    fn insert_tempo_event(&mut self) {
+        let input = self.input_window.get_last_string_input().trim();
+
+        match input.parse::<TempoEvent>() {
+            Ok(event) => self.tempo_map.insert(event),
+            Err(e) => warn!("Could not add tempo change {}: {}", input, e),
+        }
+    }
+
+    //Removes the tempo change on the bar written first in the prompt
+    fn remove_tempo_event(&mut self) {
+        let input = self.input_window.get_last_string_input().trim();
+
+        match input.split_whitespace().next().map(str::parse::<u32>) {
+            Some(Ok(bar)) if bar >= 1 => self.tempo_map.remove(bar - 1),
+            _ => warn!("Unknown bar {}", input),
+        }
+    }
+
+    //True while the tempo prompt is taking text input
+    pub fn is_editing(&self) -> bool {
+        self.input_window.is_editing()
+    }
+
     //Updates bpm for all tracks
     pub fn set_bpm(&mut self, bpm: f32) {
+        let bpm = bpm.clamp(MIN_BPM, MAX_BPM);
         self.bpm = bpm;
 
         for track in self.tracks.values_mut() {
@@ -166,28 +492,198 @@ impl Mixer {
 
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
         self.sample_rate = sample_rate;
+        self.metronome.set_sample_rate(sample_rate);
 
         for track in self.tracks.values_mut() {
             track.set_sample_rate(sample_rate);
         }
     }
 
+    pub fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(mut edit) = self.history.take_undo() {
+            self.apply_edit(&mut edit, true);
+            self.history.undone(edit);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(mut edit) = self.history.take_redo() {
+            self.apply_edit(&mut edit, false);
+            self.history.redone(edit);
+        }
+    }
+
+    //Sets the state from before the edit when `undo` is true, otherwise from after it
+    fn apply_edit(&mut self, edit: &mut Edit, undo: bool) {
+        match edit {
+            Edit::Pattern {
+                track,
+                before,
+                after,
+            } => {
+                let snapshot = if undo { before } else { after };
+                if let Some(sequencer) = self.tracks.get_mut(track).map(Track::sequencer_mut) {
+                    sequencer.restore(snapshot);
+                }
+                self.select_track_id(*track);
+            }
+            Edit::Volume {
+                track,
+                before,
+                after,
+            } => {
+                let volume = if undo { *before } else { *after };
+                if let Some(track) = self.tracks.get_mut(track) {
+                    track.set_volume(volume);
+                }
+                self.select_track_id(*track);
+            }
+            Edit::TrackList { id, index, track } => self.swap_track(*id, *index, track),
+            Edit::Key { before, after } => self.set_key(if undo { *before } else { *after }),
+            Edit::TimeSignature { before, after } => {
+                self.set_time_signature(if undo { *before } else { *after })
+            }
+            Edit::Group(edits) => {
+                if undo {
+                    edits
+                        .iter_mut()
+                        .rev()
+                        .for_each(|edit| self.apply_edit(edit, true));
+                } else {
+                    edits
+                        .iter_mut()
+                        .for_each(|edit| self.apply_edit(edit, false));
+                }
+            }
+        }
+    }
+
+    //Keys for the sequencer window, clipboard commands work across tracks so they live here
+    pub fn handle_sequencer_keyboard_input(&mut self, key_event: KeyEvent) {
+        let Some(&id) = self.track_order.get(self.selected_index) else {
+            return;
+        };
+        let Some(track) = self.tracks.get_mut(&id) else {
+            return;
+        };
+
+        let before = track.sequencer().snapshot();
+        track.handle_keyboard_input(key_event);
+
+        let sequencer = track.sequencer_mut();
+        if !sequencer.is_editing() && key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            match key_event.code {
+                KeyCode::Char('c') => self.clipboard.set(sequencer.copy_selection()),
+                KeyCode::Char('x') => self.clipboard.set(sequencer.cut_selection()),
+                KeyCode::Char('v') => sequencer.paste(self.clipboard.steps()),
+                KeyCode::Char('d') => sequencer.duplicate_selection(),
+                _ => {}
+            }
+        }
+
+        //Live recorded notes are undone with their take
+        if !self.tracks.get(&id).is_some_and(Track::is_taking) {
+            self.record_pattern_edit(id, before);
+        }
+    }
+
+    //Runs an edit on the selected pattern and records it for undo
+    pub fn edit_selected_pattern(&mut self, action: impl FnOnce(&mut Sequencer)) {
+        let Some(&id) = self.track_order.get(self.selected_index) else {
+            return;
+        };
+        let Some(track) = self.tracks.get_mut(&id) else {
+            return;
+        };
+
+        let before = track.sequencer().snapshot();
+        action(track.sequencer_mut());
+        self.record_pattern_edit(id, before);
+    }
+
+    //Every key that changed the pattern becomes one undo step
+    fn record_pattern_edit(&mut self, id: usize, before: PatternSnapshot) {
+        let Some(track) = self.tracks.get(&id) else {
+            return;
+        };
+
+        let after = track.sequencer().snapshot();
+        if before != after {
+            self.history.push(Edit::Pattern {
+                track: id,
+                before,
+                after,
+            });
+        }
+    }
+
     pub fn handle_keyboard_input(&mut self, key_event: KeyEvent) {
+        self.input_window.handle_keyboard_input(key_event);
+
+        if self.input_window.is_editing() || key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        //Everything a single key changes is undone in one step
+        self.history.begin_group();
+        self.handle_mixer_keys(key_event);
+        self.history.end_group();
+    }
+
+    fn handle_mixer_keys(&mut self, key_event: KeyEvent) {
+        let key = self.key;
+        let time_signature = self.time_signature;
+
         match key_event.code {
-            KeyCode::Char('t') => self.add_track(
-                0.3,
-                format!("Track {}", self.next_id),
-                16,
-                4,
-                self.sample_rate,
-            ),
+            KeyCode::Char('t') => {
+                self.add_track(
+                    0.3,
+                    format!("Track {}", self.next_id),
+                    self.default_pattern_length(DEFAULT_STEP_DIVISION),
+                    DEFAULT_STEP_DIVISION,
+                    self.sample_rate,
+                );
+                self.history.push(Edit::TrackList {
+                    id: self.next_id - 1,
+                    index: self.selected_index,
+                    track: None,
+                });
+            }
+            KeyCode::Char('m') => self.set_time_signature(self.time_signature.next_preset()),
+            KeyCode::Char('s') => self.set_key(Key::new(self.key.root + 1, self.key.scale)),
+            KeyCode::Char('S') => self.set_key(Key::new(self.key.root, self.key.scale.next())),
             KeyCode::Char('r') => self.remove_selected_track(),
+            KeyCode::Char('o') => self.set_oscillator_quality(self.oscillator_quality.next()),
+            KeyCode::Char('c') => self.insert_tempo_event(),
+            KeyCode::Char('C') => self.remove_tempo_event(),
             KeyCode::Right => self.next_track(),
             KeyCode::Left => self.previous_track(),
             KeyCode::Up => self.increment_selected_track_volume(),
             KeyCode::Down => self.decrease_selected_track_volume(),
             _ => {}
         }
+
+        if key != self.key {
+            self.history.push(Edit::Key {
+                before: key,
+                after: self.key,
+            });
+        }
+
+        if time_signature != self.time_signature {
+            self.history.push(Edit::TimeSignature {
+                before: time_signature,
+                after: self.time_signature,
+            });
+        }
     }
 }
 
@@ -197,15 +693,32 @@ impl Widget for &Mixer {
         Self: Sized,
     {
         if self.track_order.is_empty() {
+            self.input_window.render(area, buf);
             return;
         }
 
+        let tempo_map: String = self
+            .tempo_map
+            .events()
+            .iter()
+            .map(|event| format!(" {}", event))
+            .collect();
+        let tempo_map = if tempo_map.is_empty() {
+            String::new()
+        } else {
+            format!(" | Tempo{}", tempo_map)
+        };
+
         // Outer block with mixer info
         let block = Block::default()
             .title(format!(
-                "Mixer | BPM {:.1} | Master: {:.0}%",
+                "Mixer | BPM {:.1} | {} | {} | Master: {:.0}% | Osc {}{}",
                 self.bpm,
-                self.master_volume * 100.0
+                self.time_signature,
+                self.key,
+                self.master_volume * 100.0,
+                self.oscillator_quality,
+                tempo_map
             ))
             .borders(Borders::ALL);
         let inner = block.inner(area);
@@ -239,5 +752,221 @@ impl Widget for &Mixer {
 
             track.render(*col, buf);
         }
+
+        self.input_window.render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn mixer() -> Mixer {
+        let mut mixer = Mixer::new(48000.0, 120.0);
+        mixer.add_track(0.5, "Track".to_string(), 4, DEFAULT_STEP_DIVISION, 48000.0);
+        mixer
+    }
+
+    //Undoes and redoes the last edit, checking the state read before and after it
+    fn round_trip<T: PartialEq + std::fmt::Debug>(
+        mixer: &mut Mixer,
+        before: T,
+        after: T,
+        read: impl Fn(&mut Mixer) -> T,
+    ) {
+        assert_eq!(read(mixer), after);
+        mixer.undo();
+        assert_eq!(read(mixer), before);
+        mixer.redo();
+        assert_eq!(read(mixer), after);
+    }
+
+    #[test]
+    fn pattern_edit_round_trip() {
+        let mut mixer = mixer();
+        let frequency = crate::notes::Note::C4.freq();
+        mixer
+            .selected_track()
+            .unwrap()
+            .sequencer_mut()
+            .set_note_at(0, frequency, 1.0);
+
+        mixer.handle_sequencer_keyboard_input(KeyEvent::new(
+            KeyCode::Char('x'),
+            KeyModifiers::CONTROL,
+        ));
+
+        let notes = |mixer: &mut Mixer| {
+            mixer
+                .selected_track()
+                .unwrap()
+                .sequencer()
+                .get_event(0)
+                .len()
+        };
+        round_trip(&mut mixer, 1, 0, notes);
+    }
+
+    #[test]
+    fn volume_edit_round_trip() {
+        let mut mixer = mixer();
+        mixer.handle_keyboard_input(press(KeyCode::Up));
+        mixer.handle_keyboard_input(press(KeyCode::Up));
+
+        let volume = |mixer: &mut Mixer| {
+            (mixer.selected_track().unwrap().get_volume() * 100.0).round() as i32
+        };
+        round_trip(&mut mixer, 50, 70, volume);
+    }
+
+    #[test]
+    fn track_list_edit_round_trip() {
+        let mut mixer = mixer();
+        let tracks = |mixer: &mut Mixer| mixer.ordered_tracks().count();
+
+        mixer.handle_keyboard_input(press(KeyCode::Char('t')));
+        round_trip(&mut mixer, 1, 2, tracks);
+
+        mixer.handle_keyboard_input(press(KeyCode::Char('r')));
+        round_trip(&mut mixer, 2, 1, tracks);
+    }
+
+    #[test]
+    fn key_edit_round_trip() {
+        let mut mixer = mixer();
+        let before = mixer.key();
+        mixer.handle_keyboard_input(press(KeyCode::Char('s')));
+        let after = mixer.key();
+
+        assert_ne!(before, after);
+        round_trip(&mut mixer, before, after, |mixer| mixer.key());
+    }
+
+    #[test]
+    fn time_signature_edit_round_trip() {
+        let mut mixer = mixer();
+        let before = mixer.time_signature();
+        mixer.handle_keyboard_input(press(KeyCode::Char('m')));
+        let after = mixer.time_signature();
+
+        assert_ne!(before, after);
+        round_trip(&mut mixer, before, after, |mixer| mixer.time_signature());
+    }
+
+    #[test]
+    fn group_edit_round_trip() {
+        let mut mixer = mixer();
+        let state = |mixer: &mut Mixer| (mixer.key(), mixer.time_signature());
+        let before = state(&mut mixer);
+
+        mixer.history.begin_group();
+        mixer.handle_mixer_keys(press(KeyCode::Char('s')));
+        mixer.handle_mixer_keys(press(KeyCode::Char('m')));
+        mixer.history.end_group();
+        let after = state(&mut mixer);
+
+        assert_eq!(mixer.history().undo_len(), 1);
+        round_trip(&mut mixer, before, after, state);
+    }
+
+    #[test]
+    fn clipboard_is_shared_between_tracks() {
+        let mut mixer = mixer();
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        let frequency = crate::notes::Note::E4.freq();
+        mixer
+            .selected_track()
+            .unwrap()
+            .sequencer_mut()
+            .set_note_at(2, frequency, 1.0);
+
+        mixer.handle_sequencer_keyboard_input(ctrl('c'));
+        assert_eq!(mixer.clipboard().len(), 4);
+
+        mixer.add_track(0.5, "Other".to_string(), 4, DEFAULT_STEP_DIVISION, 48000.0);
+        mixer.handle_sequencer_keyboard_input(ctrl('v'));
+
+        let pasted = mixer
+            .selected_track()
+            .unwrap()
+            .sequencer()
+            .get_event(2)
+            .to_vec();
+        assert_eq!(pasted.len(), 1);
+        assert_eq!(pasted[0].frequency, frequency);
+    }
+
+    #[test]
+    fn bars_count_on_across_meter_changes() {
+        //One quarter note is half a second at 120 bpm
+        let quarter = 24000;
+        let mut mixer = mixer();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+
+        mixer.process_block(6 * quarter);
+        assert!(close(mixer.bar_position(), 1.5));
+
+        mixer.set_time_signature(TimeSignature::new(3, 4));
+        assert!(close(mixer.bar_position(), 1.5));
+        mixer.process_block(3 * quarter);
+        assert!(close(mixer.bar_position(), 2.5));
+
+        mixer.rewind();
+        mixer.process_block(3 * quarter);
+        assert!(close(mixer.bar_position(), 1.0));
+    }
+
+    #[test]
+    fn renders_from_the_top_are_identical() {
+        let mut mixer = Mixer::new(48000.0, 120.0);
+        let kinds = [
+            InstrumentKind::Noise,
+            InstrumentKind::Snare,
+            InstrumentKind::HiHat,
+            InstrumentKind::Clap,
+            InstrumentKind::Pluck,
+            InstrumentKind::Subtractive,
+        ];
+        for kind in kinds {
+            mixer.add_track(0.2, kind.to_string(), 4, DEFAULT_STEP_DIVISION, 48000.0);
+            mixer.set_selected_instrument(kind);
+            let sequencer = mixer.selected_track().unwrap().sequencer_mut();
+            sequencer.set_note_at(0, crate::notes::Note::C4.freq(), 1.0);
+            sequencer.set_note_at(2, crate::notes::Note::E4.freq(), 0.7);
+        }
+
+        let first = mixer.render_offline(1);
+        let second = mixer.render_offline(1);
+        assert_eq!(first.len(), second.len());
+        assert!(
+            first
+                .iter()
+                .zip(&second)
+                .all(|(a, b)| a.to_bits() == b.to_bits())
+        );
+    }
+
+    #[test]
+    fn count_in_lasts_its_bars() {
+        for bars in 1..=2 {
+            let mut mixer = mixer();
+            for _ in 0..bars {
+                mixer.metronome_mut().cycle_count_in();
+            }
+            mixer.begin_playback();
+
+            //A 4/4 bar at 120 bpm is two seconds, the transport only moves once it is over
+            let samples = bars * 96000;
+            mixer.process_block(samples - 1);
+            assert!(mixer.is_counting_in());
+            assert_eq!(mixer.beat_position, 0.0);
+
+            mixer.process_block(1);
+            assert!(!mixer.is_counting_in());
+        }
     }
 }
@@ -0,0 +1,716 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    f32::consts::FRAC_PI_2,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+
+use crate::{
+    generators::{Envelope, Instrument, Processor},
+    notes::{Note, freq_to_midi},
+    parameter::Parameter,
+    sample::Sample,
+};
+
+//Samples sounding at once from one note, the rest of the layers are dropped
+const MAX_LAYERS: usize = 8;
+//Key a lone WAV file is mapped to
+const DEFAULT_ROOT_KEY: u8 = 60;
+
+//One sample mapped to a range of keys and velocities, the keys and velocities are MIDI numbers
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub sample: usize,
+    pub low_key: u8,
+    pub high_key: u8,
+    pub root_key: u8,
+    pub low_velocity: u8,
+    pub high_velocity: u8,
+    //Velocities the layer fades in and out over, equal power like SFZ's default curve
+    pub fade_in: (u8, u8),
+    pub fade_out: (u8, u8),
+    //Round robin, the zone plays on every sequence_length'th note of its key
+    pub sequence_length: usize,
+    pub sequence_position: usize,
+    //Cents per key away from the root, 0 keeps drums at their recorded pitch
+    pub key_tracking: f32,
+    pub tune: f32,
+    pub volume: f32,
+    //Plays to the end of the sample whatever the note length
+    pub one_shot: bool,
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Zone {
+            sample: 0,
+            low_key: 0,
+            high_key: 127,
+            root_key: DEFAULT_ROOT_KEY,
+            low_velocity: 0,
+            high_velocity: 127,
+            fade_in: (0, 0),
+            fade_out: (127, 127),
+            sequence_length: 1,
+            sequence_position: 1,
+            key_tracking: 100.0,
+            tune: 0.0,
+            volume: 0.0,
+            one_shot: false,
+        }
+    }
+}
+
+impl Zone {
+    pub fn contains(&self, key: u8, velocity: u8) -> bool {
+        (self.low_key..=self.high_key).contains(&key)
+            && (self.low_velocity..=self.high_velocity).contains(&velocity)
+    }
+
+    //Playback rate for a key, fractional keys bend the pitch
+    pub fn pitch_ratio(&self, key: f32) -> f32 {
+        let cents = (key - self.root_key as f32) * self.key_tracking + self.tune;
+        2f32.powf(cents / 1200.0)
+    }
+
+    //Volume and velocity crossfades as a linear gain
+    pub fn gain(&self, velocity: f32) -> f32 {
+        let fade = |(low, high): (u8, u8), velocity: f32| {
+            let t = (velocity - low as f32) / (high as f32 - low as f32).max(1.0);
+            (t.clamp(0.0, 1.0) * FRAC_PI_2).sin()
+        };
+
+        let fade_in = if velocity >= self.fade_in.1 as f32 {
+            1.0
+        } else {
+            fade(self.fade_in, velocity)
+        };
+        let fade_out = if velocity <= self.fade_out.0 as f32 {
+            1.0
+        } else {
+            fade(
+                self.fade_out,
+                self.fade_out.1 as f32 + self.fade_out.0 as f32 - velocity,
+            )
+        };
+
+        10f32.powf(self.volume / 20.0) * fade_in * fade_out
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseSampleMapError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseSampleMapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseSampleMapError {}
+
+//A zone and the path of its sample, as written in the mapping file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    pub path: PathBuf,
+    pub zone: Zone,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Header {
+    Control,
+    Global,
+    Group,
+    Region,
+    Unknown,
+}
+
+//Opcodes with the line they were read from
+type Opcodes = Vec<(usize, String, String)>;
+
+//Zones and their samples, read from a subset of SFZ:
+//<control> default_path, <global>, <group> and <region> headers with
+//sample, key, lokey, hikey, pitch_keycenter, lovel, hivel, xfin_lovel, xfin_hivel,
+//xfout_lovel, xfout_hivel, seq_length, seq_position, pitch_keytrack, tune, volume and loop_mode
+#[derive(Default)]
+pub struct SampleMap {
+    zones: Vec<Zone>,
+    samples: Vec<Sample>,
+}
+
+impl SampleMap {
+    //A single sample played across every key and velocity
+    pub fn from_sample(sample: Sample) -> Self {
+        SampleMap {
+            zones: vec![Zone::default()],
+            samples: vec![sample],
+        }
+    }
+
+    //Reads the mapping file and its samples, sample paths are relative to the file.
+    //Samples are converted to the sample rate as they load
+    pub fn load(path: &Path, sample_rate: f32) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+
+        let mut map = SampleMap::default();
+        let mut loaded: HashMap<PathBuf, usize> = HashMap::new();
+
+        for region in Self::parse(&text)? {
+            let sample_path = directory.join(&region.path);
+            let sample = match loaded.get(&sample_path) {
+                Some(&sample) => sample,
+                None => {
+                    let sample = Sample::load(&sample_path)
+                        .map_err(|e| format!("{}: {}", sample_path.display(), e))?;
+                    map.samples.push(sample.resampled(sample_rate));
+                    loaded.insert(sample_path, map.samples.len() - 1);
+                    map.samples.len() - 1
+                }
+            };
+
+            map.zones.push(Zone {
+                sample,
+                ..region.zone
+            });
+        }
+
+        if map.zones.is_empty() {
+            return Err("the mapping has no regions".into());
+        }
+
+        Ok(map)
+    }
+
+    //Regions in file order, group and global opcodes apply to the regions after them.
+    //Unknown opcodes and headers are skipped with a warning
+    pub fn parse(text: &str) -> Result<Vec<Region>, ParseSampleMapError> {
+        let mut regions = Vec::new();
+        let mut header = Header::Unknown;
+        let mut default_path = PathBuf::new();
+        let mut global = Opcodes::new();
+        let mut group = Opcodes::new();
+        let mut region: Option<(usize, Opcodes)> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let line = line.split("//").next().unwrap_or_default();
+
+            for token in Self::tokens(line) {
+                if token.starts_with('<') {
+                    if let Some((line, opcodes)) = region.take() {
+                        regions.push(Self::region(
+                            line,
+                            &default_path,
+                            [&global, &group, &opcodes],
+                        )?);
+                    }
+
+                    header = match token {
+                        "<control>" => Header::Control,
+                        "<global>" => Header::Global,
+                        "<group>" => Header::Group,
+                        "<region>" => Header::Region,
+                        _ => {
+                            warn!("Unknown sample map header {}", token);
+                            Header::Unknown
+                        }
+                    };
+
+                    match header {
+                        Header::Global => global.clear(),
+                        Header::Group => group.clear(),
+                        Header::Region => region = Some((number, Opcodes::new())),
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                let opcodes = match header {
+                    Header::Global => &mut global,
+                    Header::Group => &mut group,
+                    Header::Region => match region.as_mut() {
+                        Some((_, opcodes)) => opcodes,
+                        None => continue,
+                    },
+                    Header::Control | Header::Unknown => {
+                        if let Some(path) = token.strip_prefix("default_path=") {
+                            default_path = PathBuf::from(path.replace('\\', "/"));
+                        } else if header == Header::Control {
+                            warn!("Unknown control opcode {}", token);
+                        }
+                        continue;
+                    }
+                };
+
+                match token.split_once('=') {
+                    Some((name, value)) => {
+                        opcodes.push((number, name.to_string(), value.to_string()));
+                    }
+                    //Sample paths can have spaces in them
+                    None => match opcodes.last_mut() {
+                        Some((_, _, value)) => {
+                            value.push(' ');
+                            value.push_str(token);
+                        }
+                        None => {
+                            return Err(ParseSampleMapError {
+                                line: number,
+                                message: format!("expected an opcode, found {}", token),
+                            });
+                        }
+                    },
+                }
+            }
+        }
+
+        if let Some((line, opcodes)) = region.take() {
+            regions.push(Self::region(
+                line,
+                &default_path,
+                [&global, &group, &opcodes],
+            )?);
+        }
+
+        Ok(regions)
+    }
+
+    //Whitespace separated tokens with headers split off, "<region>sample=a.wav" is two tokens
+    fn tokens(line: &str) -> Vec<&str> {
+        let mut tokens = Vec::new();
+
+        for mut word in line.split_whitespace() {
+            while let Some(start) = word.find('<') {
+                let end = word[start..]
+                    .find('>')
+                    .map_or(word.len(), |end| start + end + 1);
+                if start > 0 {
+                    tokens.push(&word[..start]);
+                }
+                tokens.push(&word[start..end]);
+                word = &word[end..];
+            }
+
+            if !word.is_empty() {
+                tokens.push(word);
+            }
+        }
+
+        tokens
+    }
+
+    fn region(
+        line: usize,
+        default_path: &Path,
+        scopes: [&Opcodes; 3],
+    ) -> Result<Region, ParseSampleMapError> {
+        let mut zone = Zone::default();
+        let mut path = None;
+
+        for (number, name, value) in scopes.into_iter().flatten() {
+            let error = |message: String| ParseSampleMapError {
+                line: *number,
+                message,
+            };
+            let key = || parse_key(value).ok_or_else(|| error(format!("invalid key {}", value)));
+            let velocity = || {
+                value
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|velocity| *velocity <= 127)
+                    .ok_or_else(|| error(format!("invalid velocity {}", value)))
+            };
+            let decimal = || {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| error(format!("invalid number {}", value)))
+            };
+            let count = || {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|count| *count > 0)
+                    .ok_or_else(|| error(format!("invalid count {}", value)))
+            };
+
+            match name.as_str() {
+                "sample" => path = Some(default_path.join(value.replace('\\', "/"))),
+                "key" => {
+                    let key = key()?;
+                    zone.low_key = key;
+                    zone.high_key = key;
+                    zone.root_key = key;
+                }
+                "lokey" => zone.low_key = key()?,
+                "hikey" => zone.high_key = key()?,
+                "pitch_keycenter" => zone.root_key = key()?,
+                "lovel" => zone.low_velocity = velocity()?,
+                "hivel" => zone.high_velocity = velocity()?,
+                "xfin_lovel" => zone.fade_in.0 = velocity()?,
+                "xfin_hivel" => zone.fade_in.1 = velocity()?,
+                "xfout_lovel" => zone.fade_out.0 = velocity()?,
+                "xfout_hivel" => zone.fade_out.1 = velocity()?,
+                "seq_length" => zone.sequence_length = count()?,
+                "seq_position" => zone.sequence_position = count()?,
+                "pitch_keytrack" => zone.key_tracking = decimal()?,
+                "tune" => zone.tune = decimal()?,
+                "volume" => zone.volume = decimal()?,
+                "loop_mode" => zone.one_shot = value == "one_shot",
+                _ => warn!("Unknown sample map opcode {}", name),
+            }
+        }
+
+        let path = path.ok_or(ParseSampleMapError {
+            line,
+            message: "region has no sample".to_string(),
+        })?;
+
+        Ok(Region { path, zone })
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+}
+
+//MIDI number or note name, e.g. "60", "c4" or "F#3"
+fn parse_key(value: &str) -> Option<u8> {
+    if let Ok(key) = value.parse::<u8>() {
+        return (key <= 127).then_some(key);
+    }
+
+    let mut chars = value.chars();
+    let first = chars.next()?.to_ascii_uppercase();
+    let note: Note = format!("{}{}", first, chars.as_str()).parse().ok()?;
+    Some(note.midi())
+}
+
+struct Voice {
+    sample: usize,
+    position: f64,
+    increment: f64,
+    gain: f32,
+    one_shot: bool,
+}
+
+//Plays the zones of a sample map that contain the note's key and velocity
+pub struct MultiSampler {
+    name: String,
+    file: Option<PathBuf>,
+    map: SampleMap,
+    sample_rate: f32,
+    voices: Vec<Voice>,
+    round_robin: [usize; 128],
+    velocity: f32,
+    transpose: f32,
+    envelope: Envelope,
+}
+
+impl MultiSampler {
+    pub fn new(sample_rate: f32, envelope: Envelope) -> Self {
+        MultiSampler {
+            name: "Multi-Sample".to_string(),
+            file: None,
+            map: SampleMap::default(),
+            sample_rate,
+            voices: Vec::with_capacity(MAX_LAYERS),
+            round_robin: [0; 128],
+            velocity: 1.0,
+            transpose: 0.0,
+            envelope,
+        }
+    }
+}
+
+impl Instrument for MultiSampler {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&mut self) -> f32 {
+        let mut output = 0.0;
+        for voice in self.voices.iter_mut() {
+            output += self.map.samples[voice.sample].read_cubic(voice.position) * voice.gain;
+            voice.position += voice.increment;
+        }
+
+        let samples = &self.map.samples;
+        self.voices
+            .retain(|voice| voice.position < samples[voice.sample].len() as f64);
+
+        //Nothing left to play once every sample has run out
+        if self.voices.is_empty() && self.envelope.is_active() {
+            self.envelope.stop();
+        }
+
+        self.envelope.process(output) * self.velocity
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        let key = freq_to_midi(frequency) + self.transpose;
+        let nearest = key.round().clamp(0.0, 127.0) as u8;
+        let midi_velocity = (velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+
+        let count = self.round_robin[nearest as usize];
+        self.round_robin[nearest as usize] += 1;
+
+        self.voices.clear();
+        for zone in self.map.zones.iter() {
+            if !zone.contains(nearest, midi_velocity)
+                || count % zone.sequence_length + 1 != zone.sequence_position
+            {
+                continue;
+            }
+
+            let gain = zone.gain(velocity * 127.0);
+            if gain <= 0.0 || self.voices.len() == MAX_LAYERS {
+                continue;
+            }
+
+            self.voices.push(Voice {
+                sample: zone.sample,
+                position: 0.0,
+                increment: zone.pitch_ratio(key) as f64,
+                gain,
+                one_shot: zone.one_shot,
+            });
+        }
+
+        self.velocity = velocity;
+        self.envelope.start();
+    }
+
+    //One shot samples ignore the end of the note
+    fn note_off(&mut self) {
+        if !self.voices.iter().all(|voice| voice.one_shot) {
+            self.envelope.stop();
+        }
+    }
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        0.0
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters =
+            vec![Parameter::new("Transpose", self.transpose, -24.0, 24.0, 1.0).unit("st")];
+        parameters.extend(self.envelope.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.transpose = value.clamp(-24.0, 24.0),
+            index => self.envelope.set_parameter(index - 1, value),
+        }
+    }
+
+    //An .sfz mapping file, any other file is loaded as one sample rooted at C4
+    fn load_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let is_mapping = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("sfz"));
+
+        let map = if is_mapping {
+            SampleMap::load(path, self.sample_rate)?
+        } else {
+            let sample = Sample::load(path)?;
+            if sample.is_empty() {
+                return Err("the file has no audio".into());
+            }
+            SampleMap::from_sample(sample.resampled(self.sample_rate))
+        };
+
+        self.map = map;
+        self.voices.clear();
+        self.round_robin = [0; 128];
+
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        self.name = format!("Multi-Sample {}", file);
+        self.file = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    fn loaded_file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::midi_to_freq;
+
+    //Each zone gets its own sample so the playing voices show which zones were picked
+    fn sampler(mut zones: Vec<Zone>) -> MultiSampler {
+        let mut sampler = MultiSampler::new(48000.0, Envelope::new(0.0, 0.0, 1.0, 0.0, 48000.0));
+        for (sample, zone) in zones.iter_mut().enumerate() {
+            zone.sample = sample;
+        }
+        let samples = zones
+            .iter()
+            .map(|_| Sample::new(vec![1.0; 64], 48000.0))
+            .collect();
+        sampler.map = SampleMap { zones, samples };
+        sampler
+    }
+
+    fn playing(sampler: &MultiSampler) -> Vec<usize> {
+        sampler.voices.iter().map(|voice| voice.sample).collect()
+    }
+
+    #[test]
+    fn tokens_split_headers_and_keep_path_words() {
+        assert_eq!(
+            SampleMap::tokens("<region>sample=a.wav key=60"),
+            ["<region>", "sample=a.wav", "key=60"]
+        );
+        assert_eq!(
+            SampleMap::tokens("lokey=1<region><group>"),
+            ["lokey=1", "<region>", "<group>"]
+        );
+
+        let regions = SampleMap::parse(
+            "<control> default_path=Piano\\\n<region>sample=Soft C4.wav // comment key=1\n",
+        )
+        .unwrap();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].path, Path::new("Piano/Soft C4.wav"));
+        assert_eq!(regions[0].zone.low_key, 0);
+    }
+
+    #[test]
+    fn scopes_apply_to_following_regions() {
+        let text = "
+            <global> volume=-6 lokey=10
+            <group> hikey=20
+            <region> sample=a.wav
+            <region> sample=b.wav hikey=30 volume=-3
+            <group> tune=5
+            <region> sample=c.wav
+        ";
+        let regions = SampleMap::parse(text).unwrap();
+        let zones: Vec<_> = regions.iter().map(|region| &region.zone).collect();
+
+        assert_eq!(regions.len(), 3);
+        assert_eq!(
+            (zones[0].low_key, zones[0].high_key, zones[0].volume),
+            (10, 20, -6.0)
+        );
+        assert_eq!(
+            (zones[1].low_key, zones[1].high_key, zones[1].volume),
+            (10, 30, -3.0)
+        );
+        assert_eq!((zones[2].low_key, zones[2].high_key), (10, 127));
+        assert_eq!((zones[2].tune, zones[2].volume), (5.0, -6.0));
+    }
+
+    #[test]
+    fn errors_report_their_line() {
+        for (text, line) in [
+            ("<region> sample=a.wav\nkey=200", 2),
+            ("<region> sample=a.wav lovel=128", 1),
+            ("\n\n<region> key=60", 3),
+            ("<region> stray sample=a.wav", 1),
+            ("<region> sample=a.wav\n seq_length=0", 2),
+            ("<region> sample=a.wav tune=sharp", 1),
+        ] {
+            let error = SampleMap::parse(text).unwrap_err();
+            assert_eq!(error.line, line, "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_keys_as_numbers_or_names() {
+        assert_eq!(parse_key("60"), Some(60));
+        assert_eq!(parse_key("c4"), Some(60));
+        assert_eq!(parse_key("F#3"), Some(54));
+        assert_eq!(parse_key("128"), None);
+        assert_eq!(parse_key("h2"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn crossfades_are_equal_power() {
+        let zone = Zone {
+            fade_in: (20, 60),
+            fade_out: (80, 120),
+            ..Zone::default()
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+
+        assert!(close(zone.gain(20.0), 0.0));
+        assert!(close(zone.gain(60.0), 1.0));
+        assert!(close(zone.gain(80.0), 1.0));
+        assert!(close(zone.gain(120.0), 0.0));
+        assert!(close(
+            zone.gain(40.0).powi(2) + zone.gain(100.0).powi(2),
+            1.0
+        ));
+
+        let quieter = Zone {
+            volume: -6.0,
+            ..Zone::default()
+        };
+        assert!(close(quieter.gain(100.0), 10f32.powf(-0.3)));
+    }
+
+    #[test]
+    fn round_robin_cycles_by_sequence_position() {
+        let zone = |sequence_position| Zone {
+            sequence_length: 3,
+            sequence_position,
+            ..Zone::default()
+        };
+        let mut sampler = sampler(vec![zone(1), zone(2), zone(3)]);
+        for (i, sample) in [0, 1, 2, 0].into_iter().enumerate() {
+            sampler.note_on(midi_to_freq(60.0), 1.0);
+            assert_eq!(playing(&sampler), [sample], "note {}", i);
+        }
+
+        //Each key keeps its own count
+        sampler.note_on(midi_to_freq(62.0), 1.0);
+        assert_eq!(playing(&sampler), [0]);
+    }
+
+    #[test]
+    fn note_on_picks_zones_by_key_and_velocity() {
+        let mut sampler = sampler(vec![
+            Zone {
+                high_key: 59,
+                ..Zone::default()
+            },
+            Zone {
+                low_key: 60,
+                high_velocity: 63,
+                ..Zone::default()
+            },
+            Zone {
+                low_key: 60,
+                low_velocity: 64,
+                ..Zone::default()
+            },
+        ]);
+
+        sampler.note_on(midi_to_freq(48.0), 1.0);
+        assert_eq!(playing(&sampler), [0]);
+        sampler.note_on(midi_to_freq(60.0), 0.25);
+        assert_eq!(playing(&sampler), [1]);
+        sampler.note_on(midi_to_freq(60.0), 1.0);
+        assert_eq!(playing(&sampler), [2]);
+    }
+}
@@ -0,0 +1,221 @@
+use rand::{RngExt, SeedableRng, rngs::SmallRng};
+
+use crate::{
+    generators::{Envelope, Instrument, Processor},
+    parameter::Parameter,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseColour {
+    //Equal energy at every frequency
+    White,
+    //Falls 3 dB per octave
+    Pink,
+    //Falls 6 dB per octave
+    Brown,
+}
+
+pub const NOISE_COLOURS: [NoiseColour; 3] =
+    [NoiseColour::White, NoiseColour::Pink, NoiseColour::Brown];
+pub const NOISE_COLOUR_NAMES: [&str; 3] = ["White", "Pink", "Brown"];
+
+impl NoiseColour {
+    pub fn index(&self) -> usize {
+        NOISE_COLOURS
+            .iter()
+            .position(|colour| colour == self)
+            .unwrap_or(0)
+    }
+
+    pub fn from_index(index: f32) -> Self {
+        NOISE_COLOURS[(index.round().max(0.0) as usize).min(NOISE_COLOURS.len() - 1)]
+    }
+}
+
+//Noise source for instruments, the same seed always gives the same samples
+pub struct Noise {
+    colour: NoiseColour,
+    seed: u64,
+    rng: SmallRng,
+    //Filter states of the pink noise
+    pink: [f32; 7],
+    brown: f32,
+}
+
+impl Noise {
+    pub fn new(colour: NoiseColour, seed: u64) -> Self {
+        Noise {
+            colour,
+            seed,
+            rng: SmallRng::seed_from_u64(seed),
+            pink: [0.0; 7],
+            brown: 0.0,
+        }
+    }
+
+    pub fn colour(&self) -> NoiseColour {
+        self.colour
+    }
+
+    pub fn set_colour(&mut self, colour: NoiseColour) {
+        self.colour = colour;
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    //Starts the sequence again from the seed
+    pub fn set_seed(&mut self, seed: u64) {
+        *self = Noise::new(self.colour, seed);
+    }
+
+    //Roughly -1 to 1 for every colour
+    pub fn process(&mut self) -> f32 {
+        let white = self.rng.random_range(-1.0..1.0);
+
+        match self.colour {
+            NoiseColour::White => white,
+            NoiseColour::Pink => {
+                //Paul Kellet's filter, a sum of one pole filters spread over the spectrum
+                let b = &mut self.pink;
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.153852;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b.iter().sum::<f32>() + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            NoiseColour::Brown => {
+                //Leaky integrator, the leak keeps it from drifting away from zero
+                self.brown = (self.brown + 0.02 * white) / 1.02;
+                self.brown * 3.5
+            }
+        }
+    }
+}
+
+//Noise on its own, shaped by the envelope
+pub struct NoiseInstrument {
+    noise: Noise,
+    velocity: f32,
+    envelope: Envelope,
+}
+
+//Highest seed that can be set from the instrument window
+const MAX_SEED: f32 = 9999.0;
+
+impl NoiseInstrument {
+    pub fn new(envelope: Envelope) -> Self {
+        NoiseInstrument {
+            noise: Noise::new(NoiseColour::White, 0),
+            velocity: 1.0,
+            envelope,
+        }
+    }
+}
+
+impl Instrument for NoiseInstrument {
+    fn get_name(&self) -> &str {
+        "Noise"
+    }
+
+    fn process(&mut self) -> f32 {
+        let sample = self.noise.process();
+        self.envelope.process(sample) * self.velocity
+    }
+
+    fn note_on(&mut self, _frequency: f32, velocity: f32) {
+        self.velocity = velocity;
+        self.envelope.start();
+    }
+
+    fn note_off(&mut self) {
+        self.envelope.stop();
+    }
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        0.0
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::choice("Colour", self.noise.colour().index(), &NOISE_COLOUR_NAMES),
+            Parameter::new("Seed", self.noise.seed() as f32, 0.0, MAX_SEED, 1.0),
+        ];
+        parameters.extend(self.envelope.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.noise.set_colour(NoiseColour::from_index(value)),
+            1 => self
+                .noise
+                .set_seed(value.round().clamp(0.0, MAX_SEED) as u64),
+            index => self.envelope.set_parameter(index - 2, value),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.noise.set_seed(self.noise.seed());
+        self.envelope.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(noise: &mut Noise, len: usize) -> Vec<f32> {
+        (0..len).map(|_| noise.process()).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_samples() {
+        let first = samples(&mut Noise::new(NoiseColour::Pink, 7), 1000);
+        assert_eq!(first, samples(&mut Noise::new(NoiseColour::Pink, 7), 1000));
+        assert_ne!(first, samples(&mut Noise::new(NoiseColour::Pink, 8), 1000));
+
+        //Setting the seed starts again from the top, filter state included
+        let mut noise = Noise::new(NoiseColour::Pink, 7);
+        samples(&mut noise, 500);
+        noise.set_seed(7);
+        assert_eq!(samples(&mut noise, 1000), first);
+    }
+
+    #[test]
+    fn colours_stay_in_range_around_zero() {
+        for colour in NOISE_COLOURS {
+            let output = samples(&mut Noise::new(colour, 1), 48000);
+            let mean = output.iter().sum::<f32>() / output.len() as f32;
+            let peak = output.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+
+            assert!(mean.abs() < 0.1, "{:?} mean {}", colour, mean);
+            assert!(peak < 1.5 && peak > 0.3, "{:?} peak {}", colour, peak);
+        }
+    }
+
+    #[test]
+    fn instrument_reset_repeats_the_noise() {
+        let mut instrument = NoiseInstrument::new(Envelope::new(0.001, 0.01, 1.0, 0.01, 48000.0));
+        instrument.set_parameter(1, 42.0);
+
+        let play = |instrument: &mut NoiseInstrument| {
+            instrument.note_on(440.0, 1.0);
+            (0..1000).map(|_| instrument.process()).collect::<Vec<_>>()
+        };
+        let first = play(&mut instrument);
+        assert_ne!(play(&mut instrument), first);
+
+        instrument.reset();
+        assert_eq!(play(&mut instrument), first);
+    }
+}
@@ -0,0 +1,406 @@
+use std::{fmt, str::FromStr};
+
+use rand::{RngExt, SeedableRng, rngs::SmallRng};
+
+use crate::{
+    scales::Key,
+    sequencer::{NoteEvent, division_label, parse_division_label},
+};
+
+const MAX_ARP_OCTAVES: u8 = 4;
+//Notes of a chord the arpeggiator plays, the rest are dropped
+const MAX_ARP_NOTES: usize = 16;
+
+//Messages passed from the sequencer through the note processors to the instrument
+#[derive(Clone, Copy, Debug)]
+pub enum NoteMessage {
+    On(NoteEvent),
+    //Releases every held note
+    Off,
+}
+
+//Timing handed to note processors every sample
+#[derive(Clone, Copy, Debug)]
+pub struct NoteContext {
+    //Transport position in quarter notes
+    pub position: f64,
+    pub bpm: f32,
+    pub sample_rate: f32,
+    //Key of the track the processor runs on
+    pub key: Key,
+}
+
+//A stage between the sequencer and the instrument, a track runs its processors as a chain
+pub trait NoteProcessor: Send {
+    fn get_name(&self) -> &str;
+    //Reads the messages arriving this sample and pushes the messages for the next stage
+    fn process(
+        &mut self,
+        input: &[NoteMessage],
+        output: &mut Vec<NoteMessage>,
+        context: &NoteContext,
+    );
+    //Short summary of the settings shown in the UI
+    fn describe(&self) -> String;
+    //Updates settings from prompt text, returns false when the text is not understood
+    fn configure(&mut self, settings: &str) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArpMode {
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+}
+
+impl fmt::Display for ArpMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ArpMode::Up => "up",
+            ArpMode::Down => "down",
+            ArpMode::UpDown => "updown",
+            ArpMode::Random => "random",
+            ArpMode::AsPlayed => "played",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseArpModeError;
+
+impl FromStr for ArpMode {
+    type Err = ParseArpModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(ArpMode::Up),
+            "down" => Ok(ArpMode::Down),
+            "updown" => Ok(ArpMode::UpDown),
+            "random" => Ok(ArpMode::Random),
+            "played" => Ok(ArpMode::AsPlayed),
+            _ => Err(ParseArpModeError),
+        }
+    }
+}
+
+//Plays the notes of the last chord one at a time, synced to the transport
+pub struct Arpeggiator {
+    mode: ArpMode,
+    octaves: u8,
+    //Steps per quarter note, same units as the sequencer step division
+    rate: u8,
+    //Fraction of an arp step the note is held
+    gate: f32,
+    held: Vec<NoteEvent>,
+    sequence: Vec<NoteEvent>,
+    index: usize,
+    last_tick: Option<i64>,
+    gate_end: Option<f64>,
+    rng: SmallRng,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Arpeggiator {
+            mode: ArpMode::Up,
+            octaves: 1,
+            rate: 4,
+            gate: 0.5,
+            //Room for the longest up and down sequence so the audio thread never allocates
+            held: Vec::with_capacity(MAX_ARP_NOTES),
+            sequence: Vec::with_capacity(MAX_ARP_NOTES * MAX_ARP_OCTAVES as usize * 2),
+            index: 0,
+            last_tick: None,
+            gate_end: None,
+            rng: SmallRng::seed_from_u64(0),
+        }
+    }
+
+    //Builds the play order from the held notes, mode and octave range, in place
+    fn rebuild_sequence(&mut self) {
+        self.sequence.clear();
+        self.sequence.extend_from_slice(&self.held);
+        if self.mode != ArpMode::AsPlayed {
+            self.sequence
+                .sort_unstable_by(|a, b| a.frequency.total_cmp(&b.frequency));
+        }
+
+        let count = self.sequence.len();
+        for octave in 1..self.octaves {
+            for index in 0..count {
+                let note = self.sequence[index];
+                self.sequence.push(NoteEvent {
+                    frequency: note.frequency * 2f32.powi(octave as i32),
+                    ..note
+                });
+            }
+        }
+
+        match self.mode {
+            ArpMode::Down => self.sequence.reverse(),
+            ArpMode::UpDown => {
+                //Walk back down without repeating the top and bottom notes
+                let len = self.sequence.len();
+                for index in (1..len.saturating_sub(1)).rev() {
+                    let note = self.sequence[index];
+                    self.sequence.push(note);
+                }
+            }
+            _ => {}
+        }
+
+        self.index = 0;
+    }
+
+    fn next_note(&mut self) -> NoteEvent {
+        if self.mode == ArpMode::Random {
+            return self.sequence[self.rng.random_range(0..self.sequence.len())];
+        }
+
+        let note = self.sequence[self.index % self.sequence.len()];
+        self.index = (self.index + 1) % self.sequence.len();
+        note
+    }
+}
+
+impl NoteProcessor for Arpeggiator {
+    fn get_name(&self) -> &str {
+        "Arpeggiator"
+    }
+
+    fn process(
+        &mut self,
+        input: &[NoteMessage],
+        output: &mut Vec<NoteMessage>,
+        context: &NoteContext,
+    ) {
+        if !input.is_empty() {
+            self.held.clear();
+            self.held.extend(
+                input
+                    .iter()
+                    .filter_map(|message| match message {
+                        NoteMessage::On(note) => Some(*note),
+                        NoteMessage::Off => None,
+                    })
+                    .take(MAX_ARP_NOTES),
+            );
+            self.rebuild_sequence();
+
+            if self.held.is_empty() && self.gate_end.take().is_some() {
+                output.push(NoteMessage::Off);
+            }
+
+            //Start the new chord right away instead of waiting for the next arp step
+            self.last_tick = None;
+        }
+
+        if let Some(gate_end) = self.gate_end
+            && context.position >= gate_end
+        {
+            output.push(NoteMessage::Off);
+            self.gate_end = None;
+        }
+
+        let tick = (context.position * self.rate as f64).floor() as i64;
+        if self.sequence.is_empty() || self.last_tick == Some(tick) {
+            return;
+        }
+
+        self.last_tick = Some(tick);
+        let note = self.next_note();
+        output.push(NoteMessage::On(note));
+        self.gate_end = Some(context.position + self.gate as f64 / self.rate as f64);
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "Arp {} x{} {} {:.0}%",
+            self.mode,
+            self.octaves,
+            division_label(self.rate),
+            self.gate * 100.0
+        )
+    }
+
+    //Takes any of "mode octaves rate gate" in any order, e.g. "updown 2 1/16 0.5"
+    fn configure(&mut self, settings: &str) -> bool {
+        for part in settings.split_whitespace() {
+            if let Ok(mode) = part.parse::<ArpMode>() {
+                self.mode = mode;
+            } else if let Some(rate) = parse_division_label(part) {
+                self.rate = rate;
+            } else if let Ok(octaves) = part.parse::<u8>() {
+                self.octaves = octaves.clamp(1, MAX_ARP_OCTAVES);
+            } else if let Ok(gate) = part.parse::<f32>() {
+                self.gate = gate.clamp(0.05, 1.0);
+            } else {
+                return false;
+            }
+        }
+
+        self.rebuild_sequence();
+        true
+    }
+}
+
+impl Default for Arpeggiator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Snaps every note passing through to the track key at playback time
+pub struct ScaleQuantiser;
+
+impl ScaleQuantiser {
+    pub fn new() -> Self {
+        ScaleQuantiser
+    }
+}
+
+impl NoteProcessor for ScaleQuantiser {
+    fn get_name(&self) -> &str {
+        "Scale Quantiser"
+    }
+
+    fn process(
+        &mut self,
+        input: &[NoteMessage],
+        output: &mut Vec<NoteMessage>,
+        context: &NoteContext,
+    ) {
+        output.extend(input.iter().map(|message| match message {
+            NoteMessage::On(note) => NoteMessage::On(NoteEvent {
+                frequency: context.key.quantise_frequency(note.frequency),
+                ..*note
+            }),
+            NoteMessage::Off => NoteMessage::Off,
+        }));
+    }
+
+    fn describe(&self) -> String {
+        "Quantise".to_string()
+    }
+
+    //Nothing to set, the key comes from the track
+    fn configure(&mut self, settings: &str) -> bool {
+        settings.trim().is_empty()
+    }
+}
+
+impl Default for ScaleQuantiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        notes::{Note, freq_to_midi},
+        scales::Scale,
+    };
+
+    fn context(key: Key) -> NoteContext {
+        NoteContext {
+            position: 0.0,
+            bpm: 120.0,
+            sample_rate: 48000.0,
+            key,
+        }
+    }
+
+    #[test]
+    fn scale_quantiser_snaps_notes_on() {
+        let mut quantiser = ScaleQuantiser::new();
+        let input = [
+            NoteMessage::On(NoteEvent::new(Note::Cs4.freq(), 0.5)),
+            NoteMessage::On(NoteEvent::new(Note::G4.freq(), 1.0)),
+            NoteMessage::Off,
+        ];
+        let mut output = Vec::new();
+        quantiser.process(&input, &mut output, &context(Key::new(0, Scale::Major)));
+
+        let notes: Vec<(f32, f32)> = output
+            .iter()
+            .filter_map(|message| match message {
+                NoteMessage::On(note) => Some((note.frequency, note.velocity)),
+                NoteMessage::Off => None,
+            })
+            .collect();
+        assert_eq!(notes.len(), 2);
+        assert!((notes[0].0 - Note::C4.freq()).abs() < 0.01);
+        assert_eq!(notes[0].1, 0.5);
+        assert!((notes[1].0 - Note::G4.freq()).abs() < 0.01);
+        assert!(matches!(output.last(), Some(NoteMessage::Off)));
+    }
+
+    fn arp_notes(arp: &Arpeggiator) -> Vec<Note> {
+        arp.sequence
+            .iter()
+            .map(|note| Note::from_midi(freq_to_midi(note.frequency).round() as u8).unwrap())
+            .collect()
+    }
+
+    fn hold(arp: &mut Arpeggiator, notes: &[Note]) {
+        let input: Vec<NoteMessage> = notes
+            .iter()
+            .map(|note| NoteMessage::On(NoteEvent::new(note.freq(), 1.0)))
+            .collect();
+        arp.process(&input, &mut Vec::new(), &context(Key::default()));
+    }
+
+    #[test]
+    fn arpeggiator_orders_notes_by_mode() {
+        let mut arp = Arpeggiator::new();
+        hold(&mut arp, &[Note::G4, Note::C4, Note::E4]);
+
+        for (settings, order) in [
+            ("up 1", vec![Note::C4, Note::E4, Note::G4]),
+            ("down 1", vec![Note::G4, Note::E4, Note::C4]),
+            ("played 1", vec![Note::G4, Note::C4, Note::E4]),
+            (
+                "up 2",
+                vec![Note::C4, Note::E4, Note::G4, Note::C5, Note::E5, Note::G5],
+            ),
+            (
+                "updown 2",
+                vec![
+                    Note::C4,
+                    Note::E4,
+                    Note::G4,
+                    Note::C5,
+                    Note::E5,
+                    Note::G5,
+                    Note::E5,
+                    Note::C5,
+                    Note::G4,
+                    Note::E4,
+                ],
+            ),
+        ] {
+            assert!(arp.configure(settings));
+            assert_eq!(arp_notes(&arp), order, "{}", settings);
+        }
+    }
+
+    #[test]
+    fn arpeggiator_rebuilds_without_growing() {
+        let mut arp = Arpeggiator::new();
+        let capacity = (arp.held.capacity(), arp.sequence.capacity());
+        arp.configure("updown 4");
+
+        let chord: Vec<Note> = (40..40 + MAX_ARP_NOTES as u8 + 4)
+            .filter_map(Note::from_midi)
+            .collect();
+        hold(&mut arp, &chord);
+
+        assert_eq!(arp.held.len(), MAX_ARP_NOTES);
+        assert_eq!((arp.held.capacity(), arp.sequence.capacity()), capacity);
+    }
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 macro_rules! notes {
     ($($name:ident),*) => {
@@ -22,12 +22,75 @@ impl Note {
         *self as u8 + 12 // MIDI C0 = 12
     }
 
+    pub fn from_midi(midi: u8) -> Option<Note> {
+        ALL_NOTES.get((midi as usize).checked_sub(12)?).copied()
+    }
+
+    //Semitone within the octave, C = 0
+    pub fn pitch_class(&self) -> u8 {
+        *self as u8 % 12
+    }
+
+    pub fn octave(&self) -> u8 {
+        *self as u8 / 12
+    }
+
     pub fn freq(&self) -> f32 {
         let semitones = self.midi() as f32 - 69.0; // A4 = 69
         440.0 * 2f32.powf(semitones / 12.0)
     }
 }
 
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            pitch_class_name(self.pitch_class()),
+            self.octave()
+        )
+    }
+}
+
+//Fractional MIDI note number of a frequency, A4 = 69
+pub fn freq_to_midi(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+pub fn midi_to_freq(midi: f32) -> f32 {
+    440.0 * 2f32.powf((midi - 69.0) / 12.0)
+}
+
+//Name of a semitone offset from C without octave, the inverse of `parse_pitch_class`
+pub fn pitch_class_name(pitch_class: u8) -> &'static str {
+    NOTE_NAMES[pitch_class as usize % 12]
+}
+
+//Parses a note name without octave, e.g. "C#" or "Bb", into a semitone offset from C
+pub fn parse_pitch_class(name: &str) -> Option<u8> {
+    let semitone = match name {
+        "C" => 0,
+        "C#" | "Cs" | "Db" => 1,
+        "D" => 2,
+        "D#" | "Ds" | "Eb" => 3,
+        "E" => 4,
+        "F" => 5,
+        "F#" | "Fs" | "Gb" => 6,
+        "G" => 7,
+        "G#" | "Gs" | "Ab" => 8,
+        "A" => 9,
+        "A#" | "As" | "Bb" => 10,
+        "B" => 11,
+        _ => return None,
+    };
+
+    Some(semitone)
+}
+
 #[derive(Debug)]
 pub struct ParseNoteError;
 
@@ -52,133 +115,120 @@ impl FromStr for Note {
         let octave: u8 = octave_str.parse().map_err(|_| ParseNoteError)?;
 
         // Semitone offset within octave
-        let semitone: u8 = match name {
-            "C" => 0,
-            "C#" | "Cs" | "Db" => 1,
-            "D" => 2,
-            "D#" | "Ds" | "Eb" => 3,
-            "E" => 4,
-            "F" => 5,
-            "F#" | "Fs" | "Gb" => 6,
-            "G" => 7,
-            "G#" | "Gs" | "Ab" => 8,
-            "A" => 9,
-            "A#" | "As" | "Bb" => 10,
-            "B" => 11,
-            _ => return Err(ParseNoteError),
-        };
+        let semitone = parse_pitch_class(name).ok_or(ParseNoteError)?;
 
         let idx = octave as usize * 12 + semitone as usize;
 
-        const ALL: &[Note] = &[
-            // Octave 0
-            Note::C0,
-            Note::Cs0,
-            Note::D0,
-            Note::Ds0,
-            Note::E0,
-            Note::F0,
-            Note::Fs0,
-            Note::G0,
-            Note::Gs0,
-            Note::A0,
-            Note::As0,
-            Note::B0,
-            // Octave 1
-            Note::C1,
-            Note::Cs1,
-            Note::D1,
-            Note::Ds1,
-            Note::E1,
-            Note::F1,
-            Note::Fs1,
-            Note::G1,
-            Note::Gs1,
-            Note::A1,
-            Note::As1,
-            Note::B1,
-            // Octave 2
-            Note::C2,
-            Note::Cs2,
-            Note::D2,
-            Note::Ds2,
-            Note::E2,
-            Note::F2,
-            Note::Fs2,
-            Note::G2,
-            Note::Gs2,
-            Note::A2,
-            Note::As2,
-            Note::B2,
-            // Octave 3
-            Note::C3,
-            Note::Cs3,
-            Note::D3,
-            Note::Ds3,
-            Note::E3,
-            Note::F3,
-            Note::Fs3,
-            Note::G3,
-            Note::Gs3,
-            Note::A3,
-            Note::As3,
-            Note::B3,
-            // Octave 4
-            Note::C4,
-            Note::Cs4,
-            Note::D4,
-            Note::Ds4,
-            Note::E4,
-            Note::F4,
-            Note::Fs4,
-            Note::G4,
-            Note::Gs4,
-            Note::A4,
-            Note::As4,
-            Note::B4,
-            // Octave 5
-            Note::C5,
-            Note::Cs5,
-            Note::D5,
-            Note::Ds5,
-            Note::E5,
-            Note::F5,
-            Note::Fs5,
-            Note::G5,
-            Note::Gs5,
-            Note::A5,
-            Note::As5,
-            Note::B5,
-            // Octave 6
-            Note::C6,
-            Note::Cs6,
-            Note::D6,
-            Note::Ds6,
-            Note::E6,
-            Note::F6,
-            Note::Fs6,
-            Note::G6,
-            Note::Gs6,
-            Note::A6,
-            Note::As6,
-            Note::B6,
-            // Octave 7
-            Note::C7,
-            Note::Cs7,
-            Note::D7,
-            Note::Ds7,
-            Note::E7,
-            Note::F7,
-            Note::Fs7,
-            Note::G7,
-            Note::Gs7,
-            Note::A7,
-            Note::As7,
-            Note::B7,
-            // Octave 8
-            Note::C8,
-        ];
-
-        ALL.get(idx).copied().ok_or(ParseNoteError)
+        ALL_NOTES.get(idx).copied().ok_or(ParseNoteError)
     }
 }
+
+//Every note in enum order, index is the number of semitones above C0
+const ALL_NOTES: &[Note] = &[
+    // Octave 0
+    Note::C0,
+    Note::Cs0,
+    Note::D0,
+    Note::Ds0,
+    Note::E0,
+    Note::F0,
+    Note::Fs0,
+    Note::G0,
+    Note::Gs0,
+    Note::A0,
+    Note::As0,
+    Note::B0,
+    // Octave 1
+    Note::C1,
+    Note::Cs1,
+    Note::D1,
+    Note::Ds1,
+    Note::E1,
+    Note::F1,
+    Note::Fs1,
+    Note::G1,
+    Note::Gs1,
+    Note::A1,
+    Note::As1,
+    Note::B1,
+    // Octave 2
+    Note::C2,
+    Note::Cs2,
+    Note::D2,
+    Note::Ds2,
+    Note::E2,
+    Note::F2,
+    Note::Fs2,
+    Note::G2,
+    Note::Gs2,
+    Note::A2,
+    Note::As2,
+    Note::B2,
+    // Octave 3
+    Note::C3,
+    Note::Cs3,
+    Note::D3,
+    Note::Ds3,
+    Note::E3,
+    Note::F3,
+    Note::Fs3,
+    Note::G3,
+    Note::Gs3,
+    Note::A3,
+    Note::As3,
+    Note::B3,
+    // Octave 4
+    Note::C4,
+    Note::Cs4,
+    Note::D4,
+    Note::Ds4,
+    Note::E4,
+    Note::F4,
+    Note::Fs4,
+    Note::G4,
+    Note::Gs4,
+    Note::A4,
+    Note::As4,
+    Note::B4,
+    // Octave 5
+    Note::C5,
+    Note::Cs5,
+    Note::D5,
+    Note::Ds5,
+    Note::E5,
+    Note::F5,
+    Note::Fs5,
+    Note::G5,
+    Note::Gs5,
+    Note::A5,
+    Note::As5,
+    Note::B5,
+    // Octave 6
+    Note::C6,
+    Note::Cs6,
+    Note::D6,
+    Note::Ds6,
+    Note::E6,
+    Note::F6,
+    Note::Fs6,
+    Note::G6,
+    Note::Gs6,
+    Note::A6,
+    Note::As6,
+    Note::B6,
+    // Octave 7
+    Note::C7,
+    Note::Cs7,
+    Note::D7,
+    Note::Ds7,
+    Note::E7,
+    Note::F7,
+    Note::Fs7,
+    Note::G7,
+    Note::Gs7,
+    Note::A7,
+    Note::As7,
+    Note::B7,
+    // Octave 8
+    Note::C8,
+];
@@ -0,0 +1,209 @@
+use std::{f32::consts::PI, fmt, str::FromStr, sync::OnceLock};
+
+use crate::{generators::WaveType, wavetable::MipmapTable};
+
+//How much work goes into keeping harmonics above Nyquist out of the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OscillatorQuality {
+    //Naive waveforms, cheapest but aliases on high notes
+    Draft,
+    //PolyBLEP and PolyBLAMP corrected waveforms
+    Standard,
+    //Mipmapped band-limited wavetables
+    High,
+}
+
+impl OscillatorQuality {
+    pub fn next(&self) -> Self {
+        match self {
+            OscillatorQuality::Draft => OscillatorQuality::Standard,
+            OscillatorQuality::Standard => OscillatorQuality::High,
+            OscillatorQuality::High => OscillatorQuality::Draft,
+        }
+    }
+}
+
+impl fmt::Display for OscillatorQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            OscillatorQuality::Draft => "draft",
+            OscillatorQuality::Standard => "standard",
+            OscillatorQuality::High => "high",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseOscillatorQualityError;
+
+impl fmt::Display for ParseOscillatorQualityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid oscillator quality string")
+    }
+}
+
+impl FromStr for OscillatorQuality {
+    type Err = ParseOscillatorQualityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "draft" => Ok(OscillatorQuality::Draft),
+            "standard" => Ok(OscillatorQuality::Standard),
+            "high" => Ok(OscillatorQuality::High),
+            _ => Err(ParseOscillatorQualityError),
+        }
+    }
+}
+
+//Band-limited tables for square, triangle and saw, shared by every oscillator
+struct Tables {
+    square: MipmapTable,
+    triangle: MipmapTable,
+    saw: MipmapTable,
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+
+    //Fourier series matching the phase of the naive waveforms below
+    TABLES.get_or_init(|| Tables {
+        square: MipmapTable::from_harmonics(|k| match k % 2 {
+            1 => (4.0 / (PI * k as f32), 0.0),
+            _ => (0.0, 0.0),
+        }),
+        triangle: MipmapTable::from_harmonics(|k| match k % 2 {
+            1 => (0.0, -8.0 / (PI * PI * (k * k) as f32)),
+            _ => (0.0, 0.0),
+        }),
+        saw: MipmapTable::from_harmonics(|k| (-2.0 / (PI * k as f32), 0.0)),
+    })
+}
+
+//A single oscillator, the phase runs from 0 to 1
+pub struct Oscillator {
+    wave_type: WaveType,
+    quality: OscillatorQuality,
+    phase: f32,
+}
+
+impl Oscillator {
+    pub fn new(wave_type: WaveType, quality: OscillatorQuality) -> Self {
+        Oscillator {
+            wave_type,
+            quality,
+            phase: 0.0,
+        }
+    }
+
+    pub fn wave_type(&self) -> WaveType {
+        self.wave_type
+    }
+
+    pub fn set_wave_type(&mut self, wave_type: WaveType) {
+        self.wave_type = wave_type;
+    }
+
+    pub fn quality(&self) -> OscillatorQuality {
+        self.quality
+    }
+
+    pub fn set_quality(&mut self, quality: OscillatorQuality) {
+        self.quality = quality;
+    }
+
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    //Next sample, then moves the phase on by one sample
+    pub fn process(&mut self, frequency: f32, sample_rate: f32) -> f32 {
+        let increment = frequency / sample_rate;
+        let sample = match self.quality {
+            OscillatorQuality::Draft => naive(self.wave_type, self.phase),
+            OscillatorQuality::Standard => poly_blep_wave(self.wave_type, self.phase, increment),
+            OscillatorQuality::High => match self.wave_type {
+                WaveType::Sine => naive(WaveType::Sine, self.phase),
+                WaveType::Square => tables().square.sample(self.phase, frequency, sample_rate),
+                WaveType::Triangle => tables().triangle.sample(self.phase, frequency, sample_rate),
+                WaveType::Saw => tables().saw.sample(self.phase, frequency, sample_rate),
+            },
+        };
+
+        self.phase = (self.phase + increment).rem_euclid(1.0);
+        sample
+    }
+}
+
+pub fn naive(wave_type: WaveType, phase: f32) -> f32 {
+    match wave_type {
+        WaveType::Sine => (phase * 2.0 * PI).sin(),
+        WaveType::Square => {
+            if phase < 0.5 {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+        WaveType::Triangle => {
+            if phase < 0.5 {
+                // Rising: 0 -> 0.5 becomes -1 -> 1
+                4.0 * phase - 1.0
+            } else {
+                // Falling: 0.5 -> 1 becomes 1 -> -1
+                -4.0 * phase + 3.0
+            }
+        }
+        // Linear ramp from -1 to 1
+        WaveType::Saw => 2.0 * phase - 1.0,
+    }
+}
+
+//Naive waveform with the jumps smoothed by PolyBLEP and the corners by PolyBLAMP
+fn poly_blep_wave(wave_type: WaveType, phase: f32, increment: f32) -> f32 {
+    //The corrections overlap past a quarter of the sample rate, keep them apart
+    let dt = increment.clamp(1e-6, 0.25);
+    let half = (phase + 0.5) % 1.0;
+
+    match wave_type {
+        WaveType::Sine => naive(WaveType::Sine, phase),
+        WaveType::Square => {
+            naive(WaveType::Square, phase) + poly_blep(phase, dt) - poly_blep(half, dt)
+        }
+        WaveType::Triangle => {
+            naive(WaveType::Triangle, phase)
+                + 4.0 * dt * (poly_blamp(phase, dt) - poly_blamp(half, dt))
+        }
+        WaveType::Saw => naive(WaveType::Saw, phase) - poly_blep(phase, dt),
+    }
+}
+
+//Residual of a band-limited step of height 2 at phase 0
+fn poly_blep(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt;
+        2.0 * x - x * x - 1.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt;
+        x * x + 2.0 * x + 1.0
+    } else {
+        0.0
+    }
+}
+
+//Integrated step residual, smooths a change of slope at phase 0
+fn poly_blamp(t: f32, dt: f32) -> f32 {
+    if t < dt {
+        let x = t / dt - 1.0;
+        -x * x * x / 3.0
+    } else if t > 1.0 - dt {
+        let x = (t - 1.0) / dt + 1.0;
+        x * x * x / 3.0
+    } else {
+        0.0
+    }
+}
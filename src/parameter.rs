@@ -0,0 +1,94 @@
+use std::fmt;
+
+//A value of an instrument exposed for editing, choices are stored as an index into options.
+//Logarithmic values move by a ratio of step every nudge, used for times and frequencies
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    pub unit: &'static str,
+    pub options: &'static [&'static str],
+    pub logarithmic: bool,
+}
+
+impl Parameter {
+    pub fn new(name: &'static str, value: f32, min: f32, max: f32, step: f32) -> Self {
+        Parameter {
+            name,
+            value,
+            min,
+            max,
+            step,
+            unit: "",
+            options: &[],
+            logarithmic: false,
+        }
+    }
+
+    pub fn choice(name: &'static str, index: usize, options: &'static [&'static str]) -> Self {
+        Parameter {
+            options,
+            ..Parameter::new(name, index as f32, 0.0, options.len() as f32 - 1.0, 1.0)
+        }
+    }
+
+    pub fn unit(self, unit: &'static str) -> Self {
+        Parameter { unit, ..self }
+    }
+
+    //The range must be above zero
+    pub fn logarithmic(self) -> Self {
+        Parameter {
+            logarithmic: true,
+            ..self
+        }
+    }
+
+    //Value moved by a number of steps, kept in range
+    pub fn nudged(&self, steps: f32) -> f32 {
+        let value = if self.logarithmic {
+            self.value.max(self.min) * (1.0 + self.step).powf(steps)
+        } else {
+            self.value + self.step * steps
+        };
+
+        value.clamp(self.min, self.max)
+    }
+
+    //Position of the value between min and max, from 0 to 1
+    pub fn normalised(&self) -> f32 {
+        if self.max <= self.min {
+            return 0.0;
+        }
+
+        let position = if self.logarithmic {
+            (self.value / self.min).ln() / (self.max / self.min).ln()
+        } else {
+            (self.value - self.min) / (self.max - self.min)
+        };
+        position.clamp(0.0, 1.0)
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(option) = self.options.get(self.value.round().max(0.0) as usize) {
+            return write!(f, "{}", option);
+        }
+
+        //Show as many decimals as the step needs
+        let decimals = match self.step {
+            _ if self.logarithmic && self.value >= 100.0 => 0,
+            _ if self.logarithmic && self.value >= 1.0 => 2,
+            _ if self.logarithmic => 3,
+            step if step >= 1.0 => 0,
+            step if step >= 0.1 => 1,
+            step if step >= 0.01 => 2,
+            _ => 3,
+        };
+        write!(f, "{:.*}{}", decimals, self.value, self.unit)
+    }
+}
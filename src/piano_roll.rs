@@ -0,0 +1,284 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+use crate::{
+    notes::{Note, freq_to_midi, midi_to_freq},
+    sequencer::{NoteEvent, Sequencer, TICKS_PER_STEP},
+};
+
+//Width of the note names on the left
+const KEYBOARD_WIDTH: u16 = 5;
+//Characters drawn per step
+const STEP_WIDTH: u16 = 2;
+//Longest note that fits in the gate of a note event
+const MAX_LENGTH: u8 = u8::MAX / TICKS_PER_STEP;
+const BLACK_KEYS: [u8; 5] = [1, 3, 6, 8, 10];
+
+//Pitch over time view of the selected pattern, the step cursor is the sequencer cursor
+pub struct PianoRollView {
+    //MIDI note of the cursor row
+    pitch: u8,
+    //MIDI note of the bottom row, moved an octave at a time
+    low_note: u8,
+    //Length in steps given to new notes
+    length: u8,
+}
+
+impl PianoRollView {
+    pub fn new() -> Self {
+        PianoRollView {
+            pitch: Note::C4.midi(),
+            low_note: Note::C3.midi(),
+            length: 1,
+        }
+    }
+
+    pub fn pitch(&self) -> Note {
+        Note::from_midi(self.pitch).unwrap_or(Note::C4)
+    }
+
+    pub fn length(&self) -> u8 {
+        self.length
+    }
+
+    pub fn handle_keyboard_input(&mut self, key_event: KeyEvent, sequencer: &mut Sequencer) {
+        let shift = key_event.modifiers.contains(KeyModifiers::SHIFT);
+        let alt = key_event.modifiers.contains(KeyModifiers::ALT);
+
+        if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        match key_event.code {
+            KeyCode::Left if alt => self.move_note(sequencer, -1, 0),
+            KeyCode::Right if alt => self.move_note(sequencer, 1, 0),
+            KeyCode::Up if alt => self.move_note(sequencer, 0, 1),
+            KeyCode::Down if alt => self.move_note(sequencer, 0, -1),
+            KeyCode::Left if shift => self.resize_note(sequencer, -1),
+            KeyCode::Right if shift => self.resize_note(sequencer, 1),
+            KeyCode::Left => sequencer.move_selected_step(-1),
+            KeyCode::Right => sequencer.move_selected_step(1),
+            KeyCode::Up => self.set_pitch(self.pitch as i32 + 1),
+            KeyCode::Down => self.set_pitch(self.pitch as i32 - 1),
+            KeyCode::PageUp => self.scroll_octave(1),
+            KeyCode::PageDown => self.scroll_octave(-1),
+            KeyCode::Enter => self.toggle_note(sequencer),
+            KeyCode::Delete | KeyCode::Backspace => {
+                self.take_note(sequencer, sequencer.selected_step());
+            }
+            KeyCode::Char(']') => self.length = (self.length + 1).min(MAX_LENGTH),
+            KeyCode::Char('[') => self.length = self.length.saturating_sub(1).max(1),
+            _ => {}
+        }
+    }
+
+    fn set_pitch(&mut self, pitch: i32) {
+        self.pitch = pitch.clamp(Note::C0.midi() as i32, Note::C8.midi() as i32) as u8;
+
+        //Only the bottom of the view is known here, the top is kept in view while drawing
+        if self.pitch < self.low_note {
+            self.low_note = self.pitch - self.pitch % 12;
+        }
+    }
+
+    fn scroll_octave(&mut self, octaves: i32) {
+        let low = self.low_note as i32 + octaves * 12;
+        self.low_note = low.clamp(Note::C0.midi() as i32, Note::C7.midi() as i32) as u8;
+        self.set_pitch(self.pitch as i32 + octaves * 12);
+    }
+
+    //Removes the note at the cursor pitch from a step and returns it
+    fn take_note(&self, sequencer: &mut Sequencer, step: usize) -> Option<NoteEvent> {
+        let mut notes = sequencer.get_event(step).to_vec();
+        let idx = notes
+            .iter()
+            .position(|note| note_midi(note) == self.pitch)?;
+        let note = notes.remove(idx);
+        sequencer.set_chord_at(step, notes);
+        Some(note)
+    }
+
+    //Adds a note to a step, replacing a note with the same pitch
+    fn put_note(sequencer: &mut Sequencer, step: usize, note: NoteEvent) {
+        let mut notes = sequencer.get_event(step).to_vec();
+        notes.retain(|other| note_midi(other) != note_midi(&note));
+        notes.push(note);
+        sequencer.set_chord_at(step, notes);
+    }
+
+    fn toggle_note(&self, sequencer: &mut Sequencer) {
+        let step = sequencer.selected_step();
+        if self.take_note(sequencer, step).is_some() {
+            return;
+        }
+
+        let note = NoteEvent {
+            gate: self.length * TICKS_PER_STEP,
+            ..NoteEvent::new(midi_to_freq(self.pitch as f32), 1.0)
+        };
+        Self::put_note(sequencer, step, note);
+    }
+
+    //Changes the length of the note at the cursor by whole steps
+    fn resize_note(&mut self, sequencer: &mut Sequencer, steps: i32) {
+        let step = sequencer.selected_step();
+        let Some(mut note) = self.take_note(sequencer, step) else {
+            return;
+        };
+
+        let length = note_length(&note) as i32 + steps;
+        note.gate = length.clamp(1, MAX_LENGTH as i32) as u8 * TICKS_PER_STEP;
+        self.length = note.gate / TICKS_PER_STEP;
+        Self::put_note(sequencer, step, note);
+    }
+
+    //Moves the note at the cursor by steps and semitones, the cursor goes with it
+    fn move_note(&mut self, sequencer: &mut Sequencer, steps: i64, semitones: i32) {
+        let step = sequencer.selected_step();
+        let Some(mut note) = self.take_note(sequencer, step) else {
+            return;
+        };
+
+        sequencer.move_selected_step(steps);
+        self.set_pitch(self.pitch as i32 + semitones);
+        note.frequency = midi_to_freq(self.pitch as f32);
+        Self::put_note(sequencer, sequencer.selected_step(), note);
+    }
+}
+
+impl Default for PianoRollView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn note_midi(note: &NoteEvent) -> u8 {
+    freq_to_midi(note.frequency).round().clamp(0.0, 127.0) as u8
+}
+
+//Length in whole steps, held notes last until the next step
+fn note_length(note: &NoteEvent) -> u8 {
+    note.gate.div_ceil(TICKS_PER_STEP).max(1)
+}
+
+//Piano roll view paired with the pattern it shows, built for every frame
+pub struct PianoRoll<'a> {
+    pub view: &'a PianoRollView,
+    pub sequencer: &'a Sequencer,
+}
+
+impl Widget for PianoRoll<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        if area.width <= KEYBOARD_WIDTH || area.height == 0 {
+            return;
+        }
+
+        let rows = area.height.min(u8::MAX as u16) as u8;
+        let columns = ((area.width - KEYBOARD_WIDTH) / STEP_WIDTH) as usize;
+        let len = self.sequencer.pattern_len();
+        let cursor = self.sequencer.selected_step();
+
+        //Keep the cursor pitch inside the view, scrolling up when it is above the top row
+        let mut low_note = self.view.low_note;
+        if self.view.pitch >= low_note.saturating_add(rows) {
+            low_note = self.view.pitch + 1 - rows;
+        }
+
+        //Page through the pattern so the cursor stays visible
+        let first = cursor / columns.max(1) * columns.max(1);
+
+        for row in 0..rows {
+            let pitch = low_note.saturating_add(rows - 1 - row);
+            let y = area.y + row as u16;
+            let black = BLACK_KEYS.contains(&(pitch % 12));
+
+            let label = match Note::from_midi(pitch) {
+                Some(note) if note.pitch_class() == 0 || pitch == self.view.pitch => {
+                    note.to_string()
+                }
+                Some(_) => String::new(),
+                None => continue,
+            };
+            let key_style = if pitch == self.view.pitch {
+                Style::default().fg(Color::Black).bg(Color::Green)
+            } else if black {
+                Style::default().fg(Color::Gray).bg(Color::Black)
+            } else {
+                Style::default().fg(Color::Black).bg(Color::Gray)
+            };
+            buf.set_string(
+                area.x,
+                y,
+                format!("{:<width$}", label, width = KEYBOARD_WIDTH as usize - 1),
+                key_style,
+            );
+
+            for column in 0..columns {
+                let step = first + column;
+                if step >= len {
+                    break;
+                }
+
+                let x = area.x + KEYBOARD_WIDTH + column as u16 * STEP_WIDTH;
+                let background = if step == self.sequencer.current_step() {
+                    Color::Rgb(60, 60, 0)
+                } else if self
+                    .sequencer
+                    .time_signature()
+                    .is_beat_start(step, self.sequencer.step_division())
+                {
+                    Color::Rgb(40, 40, 40)
+                } else if black {
+                    Color::Black
+                } else {
+                    Color::Rgb(20, 20, 20)
+                };
+
+                let is_cursor = step == cursor && pitch == self.view.pitch;
+                let style = if is_cursor {
+                    Style::default().bg(Color::Green)
+                } else {
+                    Style::default().bg(background).fg(Color::DarkGray)
+                };
+                buf.set_string(x, y, if is_cursor { "  " } else { " ." }, style);
+            }
+
+            //Notes are drawn after the grid so long notes cover the following steps
+            for step in 0..len {
+                for note in self.sequencer.get_event(step) {
+                    if note_midi(note) != pitch {
+                        continue;
+                    }
+
+                    let length = note_length(note) as usize;
+                    let color = if step == cursor && pitch == self.view.pitch {
+                        Color::LightGreen
+                    } else {
+                        Color::Blue
+                    };
+
+                    for offset in 0..length {
+                        let Some(column) = (step + offset).checked_sub(first) else {
+                            continue;
+                        };
+                        if column >= columns || step + offset >= len {
+                            break;
+                        }
+
+                        let x = area.x + KEYBOARD_WIDTH + column as u16 * STEP_WIDTH;
+                        let body = if offset == 0 { "▐█" } else { "██" };
+                        buf.set_string(x, y, body, Style::default().fg(color));
+                    }
+                }
+            }
+        }
+    }
+}
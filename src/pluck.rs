@@ -0,0 +1,324 @@
+use std::f32::consts::TAU;
+
+use crate::{
+    generators::{Envelope, Instrument, Processor},
+    noise::{Noise, NoiseColour},
+    notes::Note,
+    parameter::Parameter,
+};
+
+//Lowest note the delay line is long enough for
+const MIN_FREQUENCY: f32 = 15.0;
+//Loop filter coefficient at full damping, kept low enough that the top notes stay in tune
+const MAX_DAMPING: f32 = 0.7;
+//Highest loop gain, keeps the string from ringing forever
+const MAX_LOOP_GAIN: f32 = 0.99999;
+
+//Karplus-Strong string, a burst of noise circulating in a delay line tuned to the note.
+//The loop is an integer delay, an allpass for the fractional part and a lowpass for damping
+pub struct PluckedString {
+    sample_rate: f32,
+    delay: Vec<f32>,
+    write: usize,
+    //Whole samples of the loop read from the delay line
+    length: usize,
+    allpass_coefficient: f32,
+    allpass_input: f32,
+    allpass_output: f32,
+    lowpass_output: f32,
+    lowpass_coefficient: f32,
+    loop_gain: f32,
+    excitation: Vec<f32>,
+    excitation_index: usize,
+    noise: Noise,
+    damping: f32,
+    brightness: f32,
+    pluck_position: f32,
+    decay: f32,
+    frequency: f32,
+    envelope: Envelope,
+}
+
+impl PluckedString {
+    pub fn new(sample_rate: f32) -> Self {
+        let capacity = (sample_rate / MIN_FREQUENCY) as usize + 4;
+
+        PluckedString {
+            sample_rate,
+            delay: vec![0.0; capacity],
+            write: 0,
+            length: 1,
+            allpass_coefficient: 0.0,
+            allpass_input: 0.0,
+            allpass_output: 0.0,
+            lowpass_output: 0.0,
+            lowpass_coefficient: 0.0,
+            loop_gain: 0.0,
+            excitation: Vec::with_capacity(capacity),
+            excitation_index: 0,
+            noise: Noise::new(NoiseColour::White, 0),
+            damping: 0.3,
+            brightness: 0.7,
+            pluck_position: 0.2,
+            decay: 2.0,
+            frequency: Note::A4.freq(),
+            envelope: Envelope::new(0.001, 0.001, 1.0, 0.1, sample_rate),
+        }
+    }
+
+    //Damping filter coefficient, short periods get less damping so the loop gain can still reach the decay time
+    fn lowpass_coefficient(&self, omega: f32, trip_gain: f32) -> f32 {
+        //Largest coefficient whose gain at the fundamental is made up within MAX_LOOP_GAIN,
+        //the smaller root of (1 - a)^2 = g^2 * (1 - 2a * cos(omega) + a^2)
+        let g2 = (trip_gain / MAX_LOOP_GAIN).min(1.0).powi(2);
+        let k = 1.0 - g2;
+        let b = 1.0 - g2 * omega.cos();
+        let limit = if k <= f32::EPSILON {
+            0.0
+        } else {
+            (b - (b * b - k * k).max(0.0).sqrt()) / k
+        };
+
+        (self.damping * MAX_DAMPING).min(limit)
+    }
+
+    //Splits the loop delay between the delay line, the allpass and the damping filter
+    fn tune(&mut self) {
+        let period = self.sample_rate / self.frequency.max(MIN_FREQUENCY);
+        let omega = TAU * self.frequency / self.sample_rate;
+        let trip_gain = 10f32.powf(-3.0 / (self.frequency * self.decay));
+        let a = self.lowpass_coefficient(omega, trip_gain);
+        self.lowpass_coefficient = a;
+
+        //Phase delay of the one pole lowpass at the note frequency
+        let lowpass_delay = (a * omega.sin()).atan2(1.0 - a * omega.cos()) / omega;
+        let remaining = (period - lowpass_delay).max(1.5);
+
+        //Keeping the allpass delay between 0.5 and 1.5 samples keeps its phase flat
+        let length = ((remaining - 0.5).floor() as usize).clamp(1, self.delay.len() - 1);
+        let fraction = remaining - length as f32;
+        self.length = length;
+        //Exact phase delay at the note frequency, (1 - d) / (1 + d) drifts flat on the top notes
+        self.allpass_coefficient =
+            ((1.0 - fraction) * omega / 2.0).sin() / ((1.0 + fraction) * omega / 2.0).sin();
+
+        //Gain per trip round the loop so the fundamental falls 60 dB over the decay time,
+        //the lowpass loss at the fundamental is made up here
+        let lowpass_gain = (1.0 - a) / (1.0 - 2.0 * a * omega.cos() + a * a).sqrt();
+        self.loop_gain = (trip_gain / lowpass_gain).min(MAX_LOOP_GAIN);
+    }
+
+    //One period of noise, darkened by the brightness and combed by the pluck position
+    fn excite(&mut self, velocity: f32) {
+        let length = self.length + 1;
+        let smoothing = 1.0 - self.brightness.clamp(0.05, 1.0);
+        let mut previous = 0.0;
+
+        self.excitation.clear();
+        for _ in 0..length {
+            let sample = self.noise.process();
+            previous = sample * (1.0 - smoothing) + previous * smoothing;
+            self.excitation.push(previous);
+        }
+
+        //Plucking at a point along the string cancels the harmonics with a node there
+        let offset = ((length as f32 * self.pluck_position) as usize).max(1);
+        for index in (offset..length).rev() {
+            self.excitation[index] -= self.excitation[index - offset];
+        }
+
+        //The loop barely loses anything at DC, so any offset in the burst would never die away
+        let mean = self.excitation.iter().sum::<f32>() / length as f32;
+        self.excitation
+            .iter_mut()
+            .for_each(|sample| *sample -= mean);
+
+        let peak = self
+            .excitation
+            .iter()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        let scale = velocity / peak.max(f32::EPSILON);
+        self.excitation
+            .iter_mut()
+            .for_each(|sample| *sample *= scale);
+        self.excitation_index = 0;
+    }
+}
+
+impl Instrument for PluckedString {
+    fn get_name(&self) -> &str {
+        "Plucked String"
+    }
+
+    fn process(&mut self) -> f32 {
+        let len = self.delay.len();
+        let delayed = self.delay[(self.write + len - self.length) % len];
+
+        let allpass = self.allpass_coefficient * delayed + self.allpass_input
+            - self.allpass_coefficient * self.allpass_output;
+        self.allpass_input = delayed;
+        self.allpass_output = allpass;
+
+        let a = self.lowpass_coefficient;
+        self.lowpass_output = allpass * (1.0 - a) + self.lowpass_output * a;
+
+        let excitation = self
+            .excitation
+            .get(self.excitation_index)
+            .copied()
+            .unwrap_or(0.0);
+        self.excitation_index += 1;
+
+        let sample = self.lowpass_output * self.loop_gain + excitation;
+        self.delay[self.write] = sample;
+        self.write = (self.write + 1) % len;
+
+        self.envelope.process(sample)
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        self.frequency = frequency;
+        self.delay.fill(0.0);
+        self.allpass_input = 0.0;
+        self.allpass_output = 0.0;
+        self.lowpass_output = 0.0;
+        self.tune();
+        self.excite(velocity);
+        self.envelope.start();
+    }
+
+    //Letting go of the note mutes the string over the release time
+    fn note_off(&mut self) {
+        self.envelope.stop();
+    }
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        0.0
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        vec![
+            Parameter::new("Damping", self.damping, 0.0, 1.0, 0.01),
+            Parameter::new("Brightness", self.brightness, 0.0, 1.0, 0.01),
+            Parameter::new("Pluck Position", self.pluck_position, 0.02, 0.5, 0.01),
+            Parameter::new("Decay", self.decay, 0.05, 30.0, 0.1)
+                .unit("s")
+                .logarithmic(),
+            self.envelope.parameters()[3].clone(),
+        ]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.damping = value.clamp(0.0, 1.0),
+            1 => self.brightness = value.clamp(0.0, 1.0),
+            2 => self.pluck_position = value.clamp(0.02, 0.5),
+            3 => self.decay = value.clamp(0.05, 30.0),
+            4 => self.envelope.set_parameter(3, value),
+            _ => {}
+        }
+
+        //Damping and decay change the loop, a ringing string follows right away
+        self.tune();
+    }
+
+    fn reset(&mut self) {
+        self.delay.fill(0.0);
+        self.allpass_input = 0.0;
+        self.allpass_output = 0.0;
+        self.lowpass_output = 0.0;
+        self.excitation.clear();
+        self.excitation_index = 0;
+        self.noise.set_seed(self.noise.seed());
+        self.envelope.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{PI, TAU};
+
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    fn pluck(note: Note, damping: f32, decay: f32, len: usize) -> Vec<f32> {
+        let mut string = PluckedString::new(SAMPLE_RATE);
+        string.set_parameter(0, damping);
+        string.set_parameter(3, decay);
+        string.note_on(note.freq(), 1.0);
+        (0..len).map(|_| string.process()).collect()
+    }
+
+    //Hann windowed magnitude and phase at one frequency, the phase is taken from sample `start` of the note
+    fn partial(output: &[f32], frequency: f64, start: usize, len: usize) -> (f64, f64) {
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, sample) in output[start..start + len].iter().enumerate() {
+            let window = 0.5 - 0.5 * (TAU * i as f64 / len as f64).cos();
+            let phase = TAU * frequency * (start + i) as f64 / SAMPLE_RATE as f64;
+            re += *sample as f64 * window * phase.cos();
+            im -= *sample as f64 * window * phase.sin();
+        }
+        ((re * re + im * im).sqrt() * 4.0 / len as f64, im.atan2(re))
+    }
+
+    #[test]
+    fn every_note_is_in_tune() {
+        let (start, gap, len) = (4800, 24000, 24000);
+
+        for midi in Note::C0.midi()..=Note::C8.midi() {
+            let note = Note::from_midi(midi).unwrap();
+            let frequency = note.freq() as f64;
+            let output = pluck(note, 0.3, 10.0, start + gap + len);
+
+            //How far the fundamental's phase drifts from the note's gives the frequency error
+            let (_, first) = partial(&output, frequency, start, len);
+            let (_, second) = partial(&output, frequency, start + gap, len);
+            let drift = (second - first + PI).rem_euclid(TAU) - PI;
+            let error = drift / (TAU * gap as f64 / SAMPLE_RATE as f64);
+            let cents = 1200.0 * ((frequency + error) / frequency).log2();
+
+            assert!(cents.abs() < 1.0, "{} is {:+.2} cents out", note, cents);
+        }
+    }
+
+    #[test]
+    fn fundamental_decays_over_decay_time() {
+        for (note, damping) in [
+            (Note::C2, 0.3),
+            (Note::C4, 1.0),
+            (Note::C7, 1.0),
+            (Note::C8, 0.3),
+        ] {
+            let output = pluck(note, damping, 2.0, 48000);
+            let frequency = note.freq() as f64;
+
+            let (early, _) = partial(&output, frequency, 4800, 4800);
+            let (late, _) = partial(&output, frequency, 28800, 4800);
+            let db_per_second = 20.0 * (early / late).log10() / 0.5;
+
+            assert!(
+                (db_per_second - 30.0).abs() < 3.0,
+                "{} falls {:.1} dB/s",
+                note,
+                db_per_second
+            );
+        }
+    }
+
+    #[test]
+    fn no_offset_is_left_in_the_loop() {
+        for midi in [72, 96, 100, 108] {
+            let note = Note::from_midi(midi).unwrap();
+            let output = pluck(note, 1.0, 0.2, 48000);
+
+            let tail = &output[24000..];
+            let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+            assert!(mean.abs() < 1e-4, "{} holds {} of DC", note, mean);
+        }
+    }
+}
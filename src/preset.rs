@@ -0,0 +1,182 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use log::warn;
+
+use crate::{
+    generators::Instrument,
+    instruments::{InstrumentKind, ParseInstrumentKindError},
+};
+
+//Instrument settings as text, one "name = value" line per parameter after the instrument line.
+//Choices are written by name so presets stay readable, a loaded file is written as "file = path"
+pub struct Preset {
+    kind: InstrumentKind,
+    file: Option<PathBuf>,
+    values: Vec<(String, String)>,
+}
+
+impl Preset {
+    pub fn from_instrument(kind: InstrumentKind, instrument: &dyn Instrument) -> Self {
+        let values = instrument
+            .parameters()
+            .iter()
+            .map(|parameter| {
+                let value = if parameter.options.is_empty() {
+                    parameter.value.to_string()
+                } else {
+                    parameter.to_string()
+                };
+                (parameter.name.to_string(), value)
+            })
+            .collect();
+
+        Preset {
+            kind,
+            file: instrument.loaded_file().map(Path::to_path_buf),
+            values,
+        }
+    }
+
+    pub fn kind(&self) -> InstrumentKind {
+        self.kind
+    }
+
+    //File to load into the instrument, apply only sets the parameters
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    //Sets every parameter the instrument has, unknown names and values are skipped
+    pub fn apply(&self, instrument: &mut dyn Instrument) {
+        let parameters = instrument.parameters();
+
+        for (name, value) in &self.values {
+            let Some(index) = parameters.iter().position(|p| p.name == name) else {
+                warn!("Unknown parameter {}", name);
+                continue;
+            };
+
+            let parameter = &parameters[index];
+            let value = match parameter.options.iter().position(|option| option == value) {
+                Some(option) => option as f32,
+                None => match value.parse::<f32>() {
+                    Ok(value) => value,
+                    Err(_) => {
+                        warn!("Unknown value {} for {}", value, name);
+                        continue;
+                    }
+                },
+            };
+
+            instrument.set_parameter(index, value);
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "instrument = {}", self.kind)?;
+        if let Some(file) = &self.file {
+            writeln!(f, "file = {}", file.display())?;
+        }
+        for (name, value) in &self.values {
+            writeln!(f, "{} = {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = ParseInstrumentKindError;
+
+    //Blank lines and lines starting with # are ignored
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut kind = None;
+        let mut file = None;
+        let mut values = Vec::new();
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((name, value)) = line.split_once('=') else {
+                warn!("Unknown preset line {}", line);
+                continue;
+            };
+
+            let (name, value) = (name.trim(), value.trim());
+            if name == "instrument" {
+                kind = Some(value.parse()?);
+            } else if name == "file" {
+                file = Some(PathBuf::from(value));
+            } else {
+                values.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(Preset {
+            kind: kind.ok_or(ParseInstrumentKindError)?,
+            file,
+            values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+
+    #[test]
+    fn file_line_round_trips() {
+        let preset: Preset = "instrument = Granular\nfile = samples/pad one.wav\nDensity = 30\n"
+            .parse()
+            .unwrap();
+        assert_eq!(preset.file(), Some(Path::new("samples/pad one.wav")));
+        assert_eq!(preset.values, [("Density".to_string(), "30".to_string())]);
+
+        let text = preset.to_string();
+        assert!(text.contains("file = samples/pad one.wav\n"));
+        assert_eq!(text.parse::<Preset>().unwrap().file(), preset.file());
+    }
+
+    #[test]
+    fn saves_the_loaded_file() {
+        let path = env::temp_dir().join("terminal_daw_preset_test.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 48000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..4800 {
+            writer.write_sample((i % 100) as i16 * 100).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut instrument = InstrumentKind::Granular.build(48000.0);
+        let preset = Preset::from_instrument(InstrumentKind::Granular, instrument.as_ref());
+        assert_eq!(preset.file(), None);
+
+        instrument.load_file(&path).unwrap();
+        let preset = Preset::from_instrument(InstrumentKind::Granular, instrument.as_ref());
+        assert_eq!(preset.file(), Some(path.as_path()));
+
+        //A fresh instrument loads the file the preset was saved with
+        let preset: Preset = preset.to_string().parse().unwrap();
+        let mut copy = preset.kind().build(48000.0);
+        preset.apply(copy.as_mut());
+        copy.load_file(preset.file().unwrap()).unwrap();
+        assert_eq!(copy.loaded_file(), Some(path.as_path()));
+        assert_eq!(copy.get_name(), instrument.get_name());
+    }
+}
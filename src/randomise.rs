@@ -0,0 +1,221 @@
+use std::{fmt, str::FromStr};
+
+use rand::{RngExt, SeedableRng, rngs::SmallRng};
+
+use crate::{scales::Key, sequencer::NoteEvent, time_signature::TimeSignature};
+
+//Seeds are kept short so they are easy to read back and type into the prompt
+pub const MAX_SEED: u64 = 999_999;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RhythmBias {
+    Even,
+    Downbeats,
+    Offbeats,
+}
+
+impl fmt::Display for RhythmBias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RhythmBias::Even => "even",
+            RhythmBias::Downbeats => "down",
+            RhythmBias::Offbeats => "off",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//Constraints for generating a random pattern, the same settings and seed give the same pattern
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RandomFill {
+    pub seed: u64,
+    pub density: f32,
+    pub low_octave: u8,
+    pub high_octave: u8,
+    pub bias: RhythmBias,
+}
+
+impl RandomFill {
+    pub fn new() -> Self {
+        RandomFill {
+            seed: 0,
+            density: 0.5,
+            low_octave: 3,
+            high_octave: 4,
+            bias: RhythmBias::Even,
+        }
+    }
+
+    //Picks notes from the key, so changing the track key changes the result for the same seed
+    pub fn generate(
+        &self,
+        key: Key,
+        length: usize,
+        time_signature: TimeSignature,
+        step_division: u8,
+    ) -> Vec<Vec<NoteEvent>> {
+        let mut rng = SmallRng::seed_from_u64(self.seed);
+        let notes = key.notes_in_octaves(self.low_octave, self.high_octave);
+
+        (0..length)
+            .map(|step| {
+                let on_bar = time_signature.is_bar_start(step, step_division);
+                let on_beat = time_signature.is_beat_start(step, step_division);
+
+                //Weight the chance of a hit by where the step falls in the bar
+                let weight = match self.bias {
+                    RhythmBias::Even => 1.0,
+                    RhythmBias::Downbeats if on_bar => 2.0,
+                    RhythmBias::Downbeats if on_beat => 1.5,
+                    RhythmBias::Downbeats => 0.5,
+                    RhythmBias::Offbeats if on_beat => 0.3,
+                    RhythmBias::Offbeats => 1.5,
+                };
+
+                let chance = (self.density * weight).clamp(0.0, 1.0) as f64;
+
+                if notes.is_empty() || !rng.random_bool(chance) {
+                    return Vec::new();
+                }
+
+                let note = notes[rng.random_range(0..notes.len())];
+                vec![NoteEvent::new(note.freq(), rng.random_range(0.7..=1.0))]
+            })
+            .collect()
+    }
+
+    //Updates settings from "name=value" pairs, e.g. "seed=42 density=0.6 oct=3-5 bias=down"
+    pub fn apply_settings(&mut self, input: &str) -> Result<(), ParseRandomFillError> {
+        let mut settings = *self;
+
+        for part in input.split_whitespace() {
+            let (name, value) = part.split_once('=').ok_or(ParseRandomFillError)?;
+
+            match name {
+                "seed" => settings.seed = value.parse().map_err(|_| ParseRandomFillError)?,
+                "density" => {
+                    let density: f32 = value.parse().map_err(|_| ParseRandomFillError)?;
+                    settings.density = density.clamp(0.0, 1.0);
+                }
+                "oct" => {
+                    let (low, high) = value.split_once('-').unwrap_or((value, value));
+                    settings.low_octave = low.parse().map_err(|_| ParseRandomFillError)?;
+                    settings.high_octave = high.parse().map_err(|_| ParseRandomFillError)?;
+
+                    if settings.low_octave > settings.high_octave || settings.high_octave > 8 {
+                        return Err(ParseRandomFillError);
+                    }
+                }
+                "bias" => settings.bias = value.parse()?,
+                _ => return Err(ParseRandomFillError),
+            }
+        }
+
+        *self = settings;
+        Ok(())
+    }
+}
+
+impl Default for RandomFill {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseRandomFillError;
+
+impl fmt::Display for ParseRandomFillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid random fill settings")
+    }
+}
+
+impl FromStr for RhythmBias {
+    type Err = ParseRandomFillError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "even" => Ok(RhythmBias::Even),
+            "down" => Ok(RhythmBias::Downbeats),
+            "off" => Ok(RhythmBias::Offbeats),
+            _ => Err(ParseRandomFillError),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::{Note, freq_to_midi};
+
+    fn fill(settings: &str) -> RandomFill {
+        let mut fill = RandomFill::new();
+        fill.apply_settings(settings).unwrap();
+        fill
+    }
+
+    fn generate(fill: &RandomFill, key: Key) -> Vec<Vec<NoteEvent>> {
+        fill.generate(key, 64, TimeSignature::default(), 4)
+    }
+
+    fn note(event: &NoteEvent) -> Note {
+        let midi = freq_to_midi(event.frequency).round() as u8;
+        Note::from_midi(midi).unwrap()
+    }
+
+    #[test]
+    fn same_seed_gives_same_pattern() {
+        let key: Key = "D minor".parse().unwrap();
+        let fill = fill("seed=42 density=0.6 bias=down");
+
+        assert_eq!(generate(&fill, key), generate(&fill, key));
+        assert_ne!(
+            generate(&fill, key),
+            generate(&self::fill("seed=43 density=0.6 bias=down"), key)
+        );
+    }
+
+    #[test]
+    fn notes_stay_in_key_and_octaves() {
+        for key in ["C major", "F# minor", "A blues", "Eb whole-tone"] {
+            let key: Key = key.parse().unwrap();
+
+            for seed in 0..20 {
+                let fill = fill(&format!("seed={} density=0.8 oct=2-3", seed));
+                for event in generate(&fill, key).iter().flatten() {
+                    let note = note(event);
+                    assert!(key.contains(note), "{} not in {}", note, key);
+                    assert!((2..=3).contains(&note.octave()), "{} out of range", note);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn density_sets_how_many_steps_play() {
+        let key = Key::default();
+        assert!(generate(&fill("density=0"), key).iter().all(Vec::is_empty));
+        assert!(
+            generate(&fill("density=1"), key)
+                .iter()
+                .all(|notes| notes.len() == 1)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let mut fill = RandomFill::new();
+        for input in [
+            "seed",
+            "seed=x",
+            "oct=5-3",
+            "oct=2-9",
+            "bias=late",
+            "speed=2",
+        ] {
+            assert!(fill.apply_settings(input).is_err(), "{}", input);
+        }
+        assert_eq!(fill, RandomFill::new());
+    }
+}
@@ -0,0 +1,279 @@
+use std::{fmt, mem};
+
+use crate::sequencer::{MAX_PATTERN_LENGTH, PatternSnapshot, Sequencer, TICKS_PER_STEP};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordMode {
+    //Played notes are added to what is already in the pattern
+    Overdub,
+    //Steps from earlier passes are cleared as the playhead reaches them
+    Replace,
+}
+
+impl fmt::Display for RecordMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RecordMode::Overdub => "Overdub",
+            RecordMode::Replace => "Replace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+//A recorded pass over the pattern, kept so every loop can be undone on its own
+pub struct Take {
+    pub before: PatternSnapshot,
+    pub after: PatternSnapshot,
+}
+
+//Live recording state of a track. A take starts when recording begins and a new one
+//is started every time the pattern loops, so takes stack up while the transport runs.
+//The audio thread only copies the pattern into `boundary`, takes are built on the UI thread
+pub struct Recorder {
+    mode: RecordMode,
+    //How far notes are pulled towards the nearest step, 1 snaps fully
+    strength: f32,
+    take_start: Option<PatternSnapshot>,
+    //Set while the playhead enters steps with recording on
+    active: bool,
+    //Pattern as it was at the last loop point, reused so the audio thread does not allocate
+    boundary: PatternSnapshot,
+    boundary_pending: bool,
+    //Pattern pass the current take belongs to
+    pass: i64,
+    //Steps written during this pass and the next one, replace mode keeps them
+    written: Vec<bool>,
+    written_next: Vec<bool>,
+    finished: Option<Take>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            mode: RecordMode::Overdub,
+            strength: 1.0,
+            take_start: None,
+            active: false,
+            boundary: Sequencer::new(MAX_PATTERN_LENGTH, 1).snapshot(),
+            boundary_pending: false,
+            pass: 0,
+            written: Vec::with_capacity(MAX_PATTERN_LENGTH),
+            written_next: Vec::with_capacity(MAX_PATTERN_LENGTH),
+            finished: None,
+        }
+    }
+
+    pub fn mode(&self) -> RecordMode {
+        self.mode
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            RecordMode::Overdub => RecordMode::Replace,
+            RecordMode::Replace => RecordMode::Overdub,
+        };
+    }
+
+    pub fn strength(&self) -> f32 {
+        self.strength
+    }
+
+    pub fn set_strength(&mut self, strength: f32) {
+        self.strength = strength.clamp(0.0, 1.0);
+    }
+
+    pub fn is_taking(&self) -> bool {
+        self.take_start.is_some()
+    }
+
+    //Step count from the transport start and tick offset of a note played at `position`.
+    //The offset becomes a wait effect so partly quantised notes keep their feel
+    pub fn quantise(&self, position: f64, step_division: u8) -> (i64, u8) {
+        let exact = position * step_division as f64;
+        let quantised = exact + (exact.round() - exact) * self.strength as f64;
+
+        let mut step = quantised.floor() as i64;
+        let mut ticks = ((quantised - step as f64) * TICKS_PER_STEP as f64).round() as u8;
+        if ticks >= TICKS_PER_STEP {
+            step += 1;
+            ticks = 0;
+        }
+
+        (step, ticks)
+    }
+
+    //Called on the audio thread for every step the playhead enters while recording.
+    //Keeps the pattern at the loop point so `sync_takes` can start the next take from it
+    pub fn enter_step(&mut self, pass: i64, len: usize, sequencer: &Sequencer) {
+        if self.active && pass == self.pass {
+            return;
+        }
+
+        //Notes quantised into the next pass are kept when the loop carries on
+        let carry = self.active && pass == self.pass + 1;
+        sequencer.snapshot_into(&mut self.boundary);
+        self.boundary_pending = true;
+        self.active = true;
+        self.pass = pass;
+
+        if carry {
+            mem::swap(&mut self.written, &mut self.written_next);
+        } else {
+            self.written.clear();
+        }
+        self.written.resize(len, false);
+        self.written_next.clear();
+        self.written_next.resize(len, false);
+    }
+
+    //Called on the audio thread when a step is entered with recording off
+    pub fn stop(&mut self) {
+        self.active = false;
+    }
+
+    //Called on the UI thread, finishes the running take at the last loop point and starts the next.
+    //Loops passed between two calls end up in one take
+    pub fn sync_takes(&mut self) {
+        if !mem::take(&mut self.boundary_pending) {
+            return;
+        }
+
+        let boundary = self.boundary.clone();
+        if self.take_start.is_some() {
+            self.end_take(boundary.clone());
+        }
+        self.take_start = Some(boundary);
+    }
+
+    //Closes the current take, nothing is kept when the pattern did not change
+    pub fn end_take(&mut self, after: PatternSnapshot) {
+        if let Some(before) = self.take_start.take()
+            && before != after
+        {
+            self.finished = Some(Take { before, after });
+        }
+    }
+
+    pub fn take_finished(&mut self) -> Option<Take> {
+        self.finished.take()
+    }
+
+    //Marks a step written by a note played during the take
+    pub fn mark_written(&mut self, step: usize, pass: i64) {
+        let written = if pass > self.pass {
+            &mut self.written_next
+        } else {
+            &mut self.written
+        };
+
+        if let Some(flag) = written.get_mut(step) {
+            *flag = true;
+        }
+    }
+
+    //True when replace mode should clear a step the playhead entered
+    pub fn should_clear(&self, step: usize) -> bool {
+        self.mode == RecordMode::Replace && !self.written.get(step).copied().unwrap_or(false)
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequencer::{NoteEvent, Sequencer};
+
+    fn recorder(strength: f32) -> Recorder {
+        let mut recorder = Recorder::new();
+        recorder.set_strength(strength);
+        recorder
+    }
+
+    #[test]
+    fn full_strength_snaps_to_nearest_step() {
+        let recorder = recorder(1.0);
+        assert_eq!(recorder.quantise(0.26, 4), (1, 0));
+        assert_eq!(recorder.quantise(0.24, 4), (1, 0));
+    }
+
+    #[test]
+    fn zero_strength_keeps_tick_offset() {
+        let recorder = recorder(0.0);
+        assert_eq!(recorder.quantise(0.26, 4), (1, 1));
+        assert_eq!(recorder.quantise(0.24, 4), (0, 15));
+        //An offset that rounds to a whole step moves to the next step
+        assert_eq!(recorder.quantise(0.999, 4), (4, 0));
+    }
+
+    #[test]
+    fn overdub_keeps_earlier_notes() {
+        let sequencer = Sequencer::new(4, 4);
+        let mut recorder = recorder(1.0);
+        recorder.enter_step(0, 4, &sequencer);
+
+        assert_eq!(recorder.mode(), RecordMode::Overdub);
+        assert!((0..4).all(|step| !recorder.should_clear(step)));
+    }
+
+    #[test]
+    fn replace_clears_steps_not_written_this_pass() {
+        let sequencer = Sequencer::new(4, 4);
+        let mut recorder = recorder(1.0);
+        recorder.toggle_mode();
+        recorder.enter_step(0, 4, &sequencer);
+
+        recorder.mark_written(2, 0);
+        assert!(recorder.should_clear(1));
+        assert!(!recorder.should_clear(2));
+
+        //A note quantised past the loop point belongs to the next pass
+        recorder.mark_written(0, 1);
+        recorder.enter_step(1, 4, &sequencer);
+        assert!(!recorder.should_clear(0));
+        assert!(recorder.should_clear(2));
+    }
+
+    #[test]
+    fn loop_points_reuse_the_buffers() {
+        let mut sequencer = Sequencer::new(4, 4);
+        let mut recorder = recorder(1.0);
+        recorder.enter_step(0, 4, &sequencer);
+        let written = recorder.written.as_ptr();
+        let written_next = recorder.written_next.as_ptr();
+
+        for pass in 1..4 {
+            sequencer.set_chord_at(1, vec![NoteEvent::new(440.0, 1.0)]);
+            recorder.enter_step(pass, 4, &sequencer);
+        }
+
+        //Carried passes swap the two flag buffers, nothing is allocated
+        assert!([written, written_next].contains(&recorder.written.as_ptr()));
+        assert!([written, written_next].contains(&recorder.written_next.as_ptr()));
+    }
+
+    #[test]
+    fn takes_are_built_when_synced() {
+        let mut sequencer = Sequencer::new(4, 4);
+        let mut recorder = recorder(1.0);
+        recorder.enter_step(0, 4, &sequencer);
+        assert!(!recorder.is_taking());
+
+        recorder.sync_takes();
+        assert!(recorder.is_taking());
+
+        let before = sequencer.snapshot();
+        sequencer.set_chord_at(2, vec![NoteEvent::new(440.0, 1.0)]);
+        recorder.enter_step(1, 4, &sequencer);
+        assert!(recorder.take_finished().is_none());
+
+        recorder.sync_takes();
+        let take = recorder.take_finished().expect("the first pass is a take");
+        assert!(take.before == before);
+        assert!(take.after == sequencer.snapshot());
+    }
+}
@@ -0,0 +1,203 @@
+use std::{error::Error, f64::consts::PI, path::Path, sync::OnceLock};
+
+use hound::{SampleFormat, WavReader};
+
+//Zero crossings each side of the resampling filter
+const SINC_ZERO_CROSSINGS: usize = 16;
+//Kernel table points between zero crossings, read with linear interpolation
+const KERNEL_RESOLUTION: usize = 1024;
+
+//One side of the windowed sinc from the centre to the last zero crossing, shared by every resample
+fn kernel() -> &'static [f64] {
+    static KERNEL: OnceLock<Vec<f64>> = OnceLock::new();
+
+    KERNEL.get_or_init(|| {
+        let len = SINC_ZERO_CROSSINGS * KERNEL_RESOLUTION;
+        (0..=len + 1)
+            .map(|index| {
+                let x = index as f64 / KERNEL_RESOLUTION as f64;
+                if x >= SINC_ZERO_CROSSINGS as f64 {
+                    return 0.0;
+                }
+
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let w = 0.5 + 0.5 * x / SINC_ZERO_CROSSINGS as f64;
+                let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                sinc * window
+            })
+            .collect()
+    })
+}
+
+//Audio loaded from a file, channels are mixed down to mono
+pub struct Sample {
+    data: Vec<f32>,
+    sample_rate: f32,
+}
+
+impl Sample {
+    pub fn new(data: Vec<f32>, sample_rate: f32) -> Self {
+        Sample { data, sample_rate }
+    }
+
+    //Reads a WAV file, integer samples are scaled to -1 to 1
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        let channels = spec.channels.max(1) as usize;
+
+        let interleaved: Vec<f32> = match spec.sample_format {
+            SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            SampleFormat::Int => {
+                let scale = 1.0 / (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        let data = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        Ok(Sample::new(data, spec.sample_rate as f32))
+    }
+
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    //Linear interpolation at a fractional position, silence outside the sample
+    pub fn read(&self, position: f64) -> f32 {
+        if position < 0.0 {
+            return 0.0;
+        }
+
+        let index = position as usize;
+        let fraction = (position - index as f64) as f32;
+        let current = self.data.get(index).copied().unwrap_or(0.0);
+        let next = self.data.get(index + 1).copied().unwrap_or(0.0);
+
+        current + (next - current) * fraction
+    }
+
+    //Four point Hermite interpolation, cleaner than read when the sample is pitched
+    pub fn read_cubic(&self, position: f64) -> f32 {
+        if position < 0.0 {
+            return 0.0;
+        }
+
+        let index = position as usize;
+        let t = (position - index as f64) as f32;
+        let at = |index: Option<usize>| {
+            index
+                .and_then(|index| self.data.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        let y0 = at(index.checked_sub(1));
+        let y1 = at(Some(index));
+        let y2 = at(Some(index + 1));
+        let y3 = at(Some(index + 2));
+
+        let c1 = 0.5 * (y2 - y0);
+        let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+        let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+
+        ((c3 * t + c2) * t + c1) * t + y1
+    }
+
+    //Converts to another sample rate with a Blackman windowed sinc,
+    //the cutoff drops to the new Nyquist frequency when going down
+    pub fn resampled(&self, sample_rate: f32) -> Sample {
+        if self.sample_rate == sample_rate || self.is_empty() {
+            return Sample::new(self.data.clone(), sample_rate);
+        }
+
+        let step = self.sample_rate as f64 / sample_rate as f64;
+        let cutoff = (1.0 / step).min(1.0);
+        let radius = SINC_ZERO_CROSSINGS as f64 / cutoff;
+        let len = (self.len() as f64 / step).ceil() as usize;
+        let kernel = kernel();
+
+        let data = (0..len)
+            .map(|index| {
+                let centre = index as f64 * step;
+                let first = (centre - radius).ceil().max(0.0) as usize;
+                let last = ((centre + radius).floor() as usize).min(self.len() - 1);
+
+                (first..=last)
+                    .map(|source| {
+                        let x = (source as f64 - centre).abs() * cutoff * KERNEL_RESOLUTION as f64;
+                        let point = x as usize;
+                        let t = x - point as f64;
+                        let tap = kernel[point] + (kernel[point + 1] - kernel[point]) * t;
+                        self.data[source] as f64 * tap * cutoff
+                    })
+                    .sum::<f64>() as f32
+            })
+            .collect();
+
+        Sample::new(data, sample_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f64, sample_rate: f64, len: usize) -> Sample {
+        let data = (0..len)
+            .map(|i| (2.0 * PI * frequency * i as f64 / sample_rate).sin() as f32)
+            .collect();
+        Sample::new(data, sample_rate as f32)
+    }
+
+    //Skips the ends where the filter runs off the sample
+    fn middle(sample: &Sample) -> impl Iterator<Item = (usize, f32)> {
+        let edge = 2 * SINC_ZERO_CROSSINGS * 4;
+        sample
+            .data()
+            .iter()
+            .copied()
+            .enumerate()
+            .take(sample.len() - edge)
+            .skip(edge)
+    }
+
+    #[test]
+    fn resampling_keeps_tones_below_nyquist() {
+        let resampled = sine(1000.0, 44100.0, 44100).resampled(48000.0);
+        assert_eq!(resampled.len(), 48000);
+
+        for (i, value) in middle(&resampled) {
+            let expected = (2.0 * PI * 1000.0 * i as f64 / 48000.0).sin();
+            assert!((value as f64 - expected).abs() < 1e-4, "{} at {}", value, i);
+        }
+    }
+
+    #[test]
+    fn resampling_down_removes_tones_above_nyquist() {
+        let resampled = sine(15000.0, 44100.0, 44100).resampled(22050.0);
+        assert!(middle(&resampled).all(|(_, value)| value.abs() < 1e-3));
+    }
+}
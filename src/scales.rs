@@ -0,0 +1,349 @@
+use std::{fmt, str::FromStr};
+
+use crate::notes::{Note, freq_to_midi, midi_to_freq, parse_pitch_class, pitch_class_name};
+
+//Octave the root of the key sits in when notes are entered as scale degrees
+const DEGREE_OCTAVE: u8 = 4;
+
+//Scales cycled through from the keyboard, custom scales can only be typed in
+const PRESETS: [Scale; 14] = [
+    Scale::Major,
+    Scale::Minor,
+    Scale::Dorian,
+    Scale::Phrygian,
+    Scale::Lydian,
+    Scale::Mixolydian,
+    Scale::Locrian,
+    Scale::HarmonicMinor,
+    Scale::MelodicMinor,
+    Scale::MajorPentatonic,
+    Scale::MinorPentatonic,
+    Scale::Blues,
+    Scale::WholeTone,
+    Scale::Chromatic,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scale {
+    Major,
+    Minor,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Locrian,
+    HarmonicMinor,
+    MelodicMinor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Blues,
+    WholeTone,
+    Chromatic,
+    //Bit n is set when the scale contains the semitone n above the root
+    Custom(u16),
+}
+
+impl Scale {
+    //Semitones above the root for every degree of the scale
+    pub fn intervals(&self) -> Vec<u8> {
+        let mask = self.mask();
+        (0..12)
+            .filter(|semitone| mask & (1 << semitone) != 0)
+            .collect()
+    }
+
+    //Bit n is set when the scale contains the semitone n above the root
+    pub fn mask(&self) -> u16 {
+        let intervals: &[u8] = match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Phrygian => &[0, 1, 3, 5, 7, 8, 10],
+            Scale::Lydian => &[0, 2, 4, 6, 7, 9, 11],
+            Scale::Mixolydian => &[0, 2, 4, 5, 7, 9, 10],
+            Scale::Locrian => &[0, 1, 3, 5, 6, 8, 10],
+            Scale::HarmonicMinor => &[0, 2, 3, 5, 7, 8, 11],
+            Scale::MelodicMinor => &[0, 2, 3, 5, 7, 9, 11],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Blues => &[0, 3, 5, 6, 7, 10],
+            Scale::WholeTone => &[0, 2, 4, 6, 8, 10],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Custom(mask) => return *mask,
+        };
+
+        intervals
+            .iter()
+            .fold(0, |mask, semitone| mask | 1 << semitone)
+    }
+
+    //Next preset scale, used for cycling from the keyboard
+    pub fn next(&self) -> Self {
+        match PRESETS.iter().position(|scale| scale == self) {
+            Some(idx) => PRESETS[(idx + 1) % PRESETS.len()],
+            None => PRESETS[0],
+        }
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scale::Major => "major",
+            Scale::Minor => "minor",
+            Scale::Dorian => "dorian",
+            Scale::Phrygian => "phrygian",
+            Scale::Lydian => "lydian",
+            Scale::Mixolydian => "mixolydian",
+            Scale::Locrian => "locrian",
+            Scale::HarmonicMinor => "harmonic-minor",
+            Scale::MelodicMinor => "melodic-minor",
+            Scale::MajorPentatonic => "major-pentatonic",
+            Scale::MinorPentatonic => "minor-pentatonic",
+            Scale::Blues => "blues",
+            Scale::WholeTone => "whole-tone",
+            Scale::Chromatic => "chromatic",
+            Scale::Custom(_) => {
+                let intervals: Vec<String> =
+                    self.intervals().iter().map(|i| i.to_string()).collect();
+                return write!(f, "custom:{}", intervals.join(","));
+            }
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseScaleError;
+
+impl fmt::Display for ParseScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid scale or key string")
+    }
+}
+
+impl FromStr for Scale {
+    type Err = ParseScaleError;
+
+    //Custom scales list semitones above the root, e.g. "custom:0,2,3,7,8"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+
+        if let Some(intervals) = s.strip_prefix("custom:") {
+            let mut mask = 1u16; // The root is always part of the scale
+            for interval in intervals.split(',') {
+                let semitone: u8 = interval.trim().parse().map_err(|_| ParseScaleError)?;
+                if semitone > 11 {
+                    return Err(ParseScaleError);
+                }
+                mask |= 1 << semitone;
+            }
+
+            return Ok(Scale::Custom(mask));
+        }
+
+        let scale = match s.as_str() {
+            "major" | "maj" | "ionian" => Scale::Major,
+            "minor" | "min" | "aeolian" => Scale::Minor,
+            "dorian" => Scale::Dorian,
+            "phrygian" => Scale::Phrygian,
+            "lydian" => Scale::Lydian,
+            "mixolydian" => Scale::Mixolydian,
+            "locrian" => Scale::Locrian,
+            "harmonic-minor" => Scale::HarmonicMinor,
+            "melodic-minor" => Scale::MelodicMinor,
+            "major-pentatonic" | "pentatonic" => Scale::MajorPentatonic,
+            "minor-pentatonic" => Scale::MinorPentatonic,
+            "blues" => Scale::Blues,
+            "whole-tone" => Scale::WholeTone,
+            "chromatic" => Scale::Chromatic,
+            _ => return Err(ParseScaleError),
+        };
+
+        Ok(scale)
+    }
+}
+
+//Root pitch class and scale of the project or a track
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub root: u8,
+    pub scale: Scale,
+}
+
+impl Key {
+    pub fn new(root: u8, scale: Scale) -> Self {
+        Key {
+            root: root % 12,
+            scale,
+        }
+    }
+
+    pub fn contains(&self, note: Note) -> bool {
+        self.contains_pitch_class(note.pitch_class())
+    }
+
+    fn contains_pitch_class(&self, pitch_class: u8) -> bool {
+        let offset = (pitch_class + 12 - self.root) % 12;
+        self.scale.mask() & (1 << offset) != 0
+    }
+
+    //All notes of the key from the bottom of `low_octave` to the top of `high_octave`
+    pub fn notes_in_octaves(&self, low_octave: u8, high_octave: u8) -> Vec<Note> {
+        let low = Note::C0.midi() + low_octave * 12;
+        let high = Note::C0.midi() + high_octave * 12 + 11;
+
+        (low..=high)
+            .filter_map(Note::from_midi)
+            .filter(|note| self.contains(*note))
+            .collect()
+    }
+
+    //Snaps a MIDI note to the nearest note in the key, ties go down
+    pub fn quantise_midi(&self, midi: u8) -> u8 {
+        (0..=6u8)
+            .flat_map(|distance| [midi.saturating_sub(distance), midi.saturating_add(distance)])
+            .find(|candidate| self.contains_pitch_class(candidate % 12))
+            .unwrap_or(midi)
+    }
+
+    pub fn quantise_frequency(&self, frequency: f32) -> f32 {
+        let midi = freq_to_midi(frequency).round().clamp(0.0, 127.0) as u8;
+        midi_to_freq(self.quantise_midi(midi) as f32)
+    }
+
+    //Parses scale degree notation relative to the root in octave 4: "1", "b3", "#4", "5+", "2--".
+    //Degrees past the scale length wrap into the next octave, each + or - shifts an octave
+    pub fn parse_degree(&self, s: &str) -> Option<Note> {
+        let s = s.trim();
+
+        let (accidental, rest) = match s.chars().next()? {
+            'b' => (-1, &s[1..]),
+            '#' => (1, &s[1..]),
+            _ => (0, s),
+        };
+
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (degree, shifts) = rest.split_at(digits_end);
+
+        let degree: usize = degree.parse().ok()?;
+        if degree == 0 {
+            return None;
+        }
+
+        let mut octave_shift = 0i32;
+        for shift in shifts.chars() {
+            match shift {
+                '+' => octave_shift += 1,
+                '-' => octave_shift -= 1,
+                _ => return None,
+            }
+        }
+
+        let intervals = self.scale.intervals();
+        let degree = degree - 1;
+        let semitones = intervals[degree % intervals.len()] as i32
+            + (degree / intervals.len()) as i32 * 12
+            + octave_shift * 12
+            + accidental;
+
+        let root = Note::C0.midi() as i32 + DEGREE_OCTAVE as i32 * 12 + self.root as i32;
+        Note::from_midi(u8::try_from(root + semitones).ok()?)
+    }
+}
+
+impl Default for Key {
+    fn default() -> Self {
+        Key::new(0, Scale::Major)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", pitch_class_name(self.root), self.scale)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseScaleError;
+
+    //Parses a root and scale, e.g. "D minor" or "F#-major"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (root, scale) = s.trim().split_once([' ', '-']).ok_or(ParseScaleError)?;
+
+        let root = parse_pitch_class(root).ok_or(ParseScaleError)?;
+        Ok(Key::new(root, scale.parse()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrees_in_major() {
+        let key = Key::new(0, Scale::Major);
+        assert_eq!(key.parse_degree("1"), Some(Note::C4));
+        assert_eq!(key.parse_degree("3"), Some(Note::E4));
+        assert_eq!(key.parse_degree("b3"), Some(Note::Ds4));
+        assert_eq!(key.parse_degree("#4"), Some(Note::Fs4));
+    }
+
+    #[test]
+    fn degrees_follow_root_and_scale() {
+        let key: Key = "D minor".parse().unwrap();
+        assert_eq!(key.parse_degree("1"), Some(Note::D4));
+        assert_eq!(key.parse_degree("3"), Some(Note::F4));
+        assert_eq!(key.parse_degree("7"), Some(Note::C5));
+    }
+
+    #[test]
+    fn degrees_wrap_into_next_octave() {
+        let major = Key::new(0, Scale::Major);
+        assert_eq!(major.parse_degree("8"), Some(Note::C5));
+        assert_eq!(major.parse_degree("9"), Some(Note::D5));
+        assert_eq!(major.parse_degree("15"), Some(Note::C6));
+
+        let pentatonic = Key::new(0, Scale::MajorPentatonic);
+        assert_eq!(pentatonic.parse_degree("6"), Some(Note::C5));
+    }
+
+    #[test]
+    fn octave_shifts() {
+        let key = Key::new(0, Scale::Major);
+        assert_eq!(key.parse_degree("5+"), Some(Note::G5));
+        assert_eq!(key.parse_degree("2--"), Some(Note::D2));
+        assert_eq!(key.parse_degree("1+-"), Some(Note::C4));
+    }
+
+    #[test]
+    fn rejects_invalid_degrees() {
+        let key = Key::new(0, Scale::Major);
+        for input in ["", "0", "x", "b", "3?", "1++++++"] {
+            assert_eq!(key.parse_degree(input), None, "{}", input);
+        }
+    }
+
+    #[test]
+    fn quantise_snaps_to_nearest_note_ties_down() {
+        let major = Key::new(0, Scale::Major);
+        assert_eq!(major.quantise_midi(Note::E4.midi()), Note::E4.midi());
+        assert_eq!(major.quantise_midi(Note::Cs4.midi()), Note::C4.midi());
+        assert_eq!(major.quantise_midi(Note::As4.midi()), Note::A4.midi());
+
+        let pentatonic = Key::new(0, Scale::MajorPentatonic);
+        assert_eq!(pentatonic.quantise_midi(Note::F4.midi()), Note::E4.midi());
+        assert_eq!(pentatonic.quantise_midi(Note::B4.midi()), Note::C5.midi());
+    }
+
+    #[test]
+    fn key_names_parse_back() {
+        for root in 0..12 {
+            let key = Key::new(root, Scale::Minor);
+            assert_eq!(key.to_string().parse::<Key>().unwrap(), key);
+        }
+        assert_eq!(Key::new(10, Scale::Major).to_string(), "A# major");
+    }
+}
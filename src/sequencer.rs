@@ -1,6 +1,6 @@
-use std::vec;
+use std::{ops::RangeInclusive, vec};
 
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use log::{info, warn};
 use ratatui::{
     layout::Rect,
@@ -8,98 +8,251 @@ use ratatui::{
     widgets::{Block, Widget},
 };
 
-use crate::{notes::Note, user_interface::InputWindow};
+use crate::{
+    euclidean::EuclideanRhythm,
+    notes::Note,
+    randomise::{MAX_SEED, RandomFill},
+    scales::Key,
+    time_signature::TimeSignature,
+    transform::{self, Transform},
+    user_interface::InputWindow,
+};
+
+//Step divisions that can be cycled through, counted as steps per quarter note
+pub const STEP_DIVISIONS: [u8; 10] = [1, 2, 3, 4, 6, 8, 12, 16, 24, 32];
+pub const MAX_PATTERN_LENGTH: usize = 128;
 
 //The sequencer knows where all the events are in the sequnce
+//Steps are derived from the transport position so patterns of any length stay in phase
+//Every step holds the notes that start on it, more than one note makes a chord
 pub struct Sequencer {
-    events: Vec<Option<NoteEvent>>,
-    bpm: f32,
-    sample_rate: f32,
+    events: Vec<Vec<NoteEvent>>,
     current_step: usize,
     selcected_step: usize,
-    samples_per_step: f32,
-    samples_accumulated: f32,
+    //Other end of the selected range, the cursor is the end that moves
+    selection_anchor: Option<usize>,
+    last_step: Option<i64>,
     step_division: u8,
+    time_signature: TimeSignature,
+    //Follows the project key unless the track has its own
+    key_override: Option<Key>,
+    project_key: Key,
+    //Snap entered notes to the key
+    quantise_entry: bool,
+    random_fill: RandomFill,
+    //Seed of the last random fill, kept with the pattern so the result can be recalled
+    random_seed: Option<u64>,
     sequencer_input_window: InputWindow,
 }
 
-#[derive(Clone, Copy, Debug)]
+//Gate lengths are counted in ticks, a step is this many ticks long
+pub const TICKS_PER_STEP: u8 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct NoteEvent {
     pub frequency: f32,
     pub velocity: f32,
+    //Length in ticks, 0 holds the note until the next step with notes or a rest
+    pub gate: u8,
+    pub effect: Option<Effect>,
 }
 
-impl Sequencer {
-    pub fn new(bpm: f32, sample_rate: f32, length: usize, step_division: u8) -> Self {
-        // Calculate samples per step
-        let beats_per_second = bpm / 60.0;
-        let samples_per_beat = sample_rate / beats_per_second;
-        let samples_per_step = samples_per_beat / step_division as f32;
+impl NoteEvent {
+    pub fn new(frequency: f32, velocity: f32) -> Self {
+        NoteEvent {
+            frequency,
+            velocity,
+            gate: 0,
+            effect: None,
+        }
+    }
+}
+
+//Tracker effect commands, the parameter is in ticks or out of 255 for chances
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    //Plays the note again every n ticks within the step
+    Retrigger(u8),
+    //Chance out of 255 that the note plays
+    Chance(u8),
+    //Starts the note n ticks late
+    Wait(u8),
+}
+
+impl Effect {
+    pub fn command(&self) -> char {
+        match self {
+            Effect::Retrigger(_) => 'R',
+            Effect::Chance(_) => 'P',
+            Effect::Wait(_) => 'W',
+        }
+    }
+
+    pub fn parameter(&self) -> u8 {
+        match *self {
+            Effect::Retrigger(value) | Effect::Chance(value) | Effect::Wait(value) => value,
+        }
+    }
 
+    pub fn from_command(command: char, parameter: u8) -> Option<Self> {
+        match command.to_ascii_uppercase() {
+            'R' => Some(Effect::Retrigger(parameter)),
+            'P' => Some(Effect::Chance(parameter)),
+            'W' => Some(Effect::Wait(parameter)),
+            _ => None,
+        }
+    }
+
+    pub fn with_parameter(&self, parameter: u8) -> Self {
+        match self {
+            Effect::Retrigger(_) => Effect::Retrigger(parameter),
+            Effect::Chance(_) => Effect::Chance(parameter),
+            Effect::Wait(_) => Effect::Wait(parameter),
+        }
+    }
+}
+
+//Everything an edit in the sequencer window can change, kept by the undo history
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternSnapshot {
+    events: Vec<Vec<NoteEvent>>,
+    step_division: u8,
+    time_signature: TimeSignature,
+    key_override: Option<Key>,
+    random_seed: Option<u64>,
+}
+
+impl Sequencer {
+    pub fn new(length: usize, step_division: u8) -> Self {
         Sequencer {
-            events: vec![None; length],
-            bpm,
-            sample_rate,
+            events: vec![Vec::new(); length.clamp(1, MAX_PATTERN_LENGTH)],
             current_step: 0,
-            samples_accumulated: 0.0,
-            samples_per_step,
+            last_step: None,
             step_division,
             selcected_step: 0,
+            selection_anchor: None,
+            time_signature: TimeSignature::default(),
+            key_override: None,
+            project_key: Key::default(),
+            quantise_entry: false,
+            random_fill: RandomFill::new(),
+            random_seed: None,
             sequencer_input_window: InputWindow::new(),
         }
     }
 
-    //Check step boundry. Returns true when boundry is hit
-    pub fn process(&mut self, num_samples: usize) -> bool {
-        self.samples_accumulated += num_samples as f32;
+    //Check step boundry at a transport position in quarter notes. Returns true when boundry is hit
+    pub fn process(&mut self, position: f64) -> bool {
+        let step = (position * self.step_division as f64).floor() as i64;
 
-        if self.samples_accumulated >= self.samples_per_step {
-            self.samples_accumulated -= self.samples_per_step;
-            self.advance_step();
-            true
-        } else {
-            false
+        if self.last_step == Some(step) {
+            return false;
         }
-    }
 
-    //goto next step loop around when limit is reached
-    fn advance_step(&mut self) {
-        self.current_step = (self.current_step + 1) % self.events.len();
+        //Loop around when the pattern length is reached
+        self.last_step = Some(step);
+        self.current_step = step.rem_euclid(self.events.len() as i64) as usize;
+        true
     }
 
-    pub fn set_sample_rate(&mut self, sample_rate: f32) {
-        self.sample_rate = sample_rate;
-        let beats_per_second = self.bpm / 60.0;
-        let samples_per_beat = sample_rate / beats_per_second;
-        self.samples_per_step = samples_per_beat / self.step_division as f32;
+    //Gets the current events, empty when the step is a rest
+    pub fn get_current_events(&self) -> &[NoteEvent] {
+        self.get_event(self.current_step)
     }
 
-    //Gets the current events if there are any
-    pub fn get_current_event(&self) -> Option<&NoteEvent> {
-        self.events.get(self.current_step).and_then(|e| e.as_ref())
+    pub fn set_note_at(&mut self, step: usize, frequency: f32, velocity: f32) {
+        if let Some(slot) = self.events.get_mut(step) {
+            *slot = vec![NoteEvent::new(frequency, velocity)];
+        }
     }
 
-    pub fn set_note_at(&mut self, step: usize, frequency: f32, velocity: f32) {
+    //Replaces the notes of a step with a chord
+    pub fn set_chord_at(&mut self, step: usize, notes: Vec<NoteEvent>) {
         if let Some(slot) = self.events.get_mut(step) {
-            *slot = Some(NoteEvent {
-                frequency,
-                velocity,
-            });
+            *slot = notes;
+        }
+    }
+
+    //Overwrites the whole pattern, the rhythm repeats when it is shorter than the pattern
+    pub fn fill_euclidean(&mut self, rhythm: &EuclideanRhythm) {
+        let pattern = rhythm.pattern();
+        let frequency = rhythm.note.freq();
+
+        for (step, slot) in self.events.iter_mut().enumerate() {
+            slot.clear();
+
+            if pattern[step % pattern.len()] {
+                slot.push(NoteEvent::new(frequency, rhythm.velocity));
+            }
+        }
+    }
+
+    //Replaces the pattern with notes generated from the random fill settings
+    pub fn randomise(&mut self) {
+        self.events = self.random_fill.generate(
+            self.key(),
+            self.events.len(),
+            self.time_signature,
+            self.step_division,
+        );
+        self.random_seed = Some(self.random_fill.seed);
+    }
+
+    //Randomises with a fresh seed, the seed is shown so a good result can be recalled
+    pub fn randomise_new_seed(&mut self) {
+        self.random_fill.seed = rand::random_range(0..=MAX_SEED);
+        self.randomise();
+    }
+
+    //Steps between the anchor and the cursor, None when nothing is selected
+    pub fn selection(&self) -> Option<RangeInclusive<usize>> {
+        let anchor = self.selection_anchor?;
+        Some(anchor.min(self.selcected_step)..=anchor.max(self.selcected_step))
+    }
+
+    fn toggle_selection(&mut self) {
+        self.selection_anchor = match self.selection_anchor {
+            Some(_) => None,
+            None => Some(self.selcected_step),
+        };
+    }
+
+    //Applies a transform to the selected steps, or the whole pattern without a selection
+    pub fn apply_transform(&mut self, transform: Transform) {
+        match (self.selection(), transform) {
+            (Some(range), _) => transform.apply(&mut self.events[range]),
+            (None, Transform::Double) => {
+                let doubled = transform::double(&self.events);
+                self.events = doubled;
+                self.set_pattern_len(self.events.len());
+            }
+            (None, Transform::Halve) => {
+                let halved = transform::halve(&self.events);
+                self.events = halved;
+                self.set_pattern_len(self.events.len());
+            }
+            (None, _) => transform.apply(&mut self.events),
         }
     }
 
     pub fn clear_step(&mut self, step: usize) {
         if let Some(slot) = self.events.get_mut(step) {
-            *slot = None;
+            slot.clear();
         }
     }
 
-    pub fn get_event(&self, id: usize) -> &Option<NoteEvent> {
-        if let Some(event) = self.events.get(id) {
-            event
-        } else {
-            &None
-        }
+    pub fn get_event(&self, id: usize) -> &[NoteEvent] {
+        self.events.get(id).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn selected_step(&self) -> usize {
+        self.selcected_step
+    }
+
+    //Moves the cursor by a number of steps, wrapping around the pattern
+    pub fn move_selected_step(&mut self, offset: i64) {
+        let len = self.events.len() as i64;
+        self.selcected_step = (self.selcected_step as i64 + offset).rem_euclid(len) as usize;
     }
 
     fn increment_selected_step(&mut self) {
@@ -114,43 +267,182 @@ impl Sequencer {
         }
     }
 
+    //Moves the cursor without wrapping, starting a selection at the cursor if there is none
+    fn extend_selection(&mut self, forward: bool) {
+        self.selection_anchor.get_or_insert(self.selcected_step);
+
+        self.selcected_step = if forward {
+            (self.selcected_step + 1).min(self.events.len() - 1)
+        } else {
+            self.selcected_step.saturating_sub(1)
+        };
+    }
+
+    //Copies the selected steps, or the whole pattern without a selection
+    pub fn copy_selection(&self) -> Vec<Vec<NoteEvent>> {
+        match self.selection() {
+            Some(range) => self.events[range].to_vec(),
+            None => self.events.clone(),
+        }
+    }
+
+    pub fn cut_selection(&mut self) -> Vec<Vec<NoteEvent>> {
+        let steps = self.copy_selection();
+        let range = self.selection().unwrap_or(0..=self.events.len() - 1);
+
+        for slot in &mut self.events[range] {
+            slot.clear();
+        }
+
+        steps
+    }
+
+    //Overwrites steps from the cursor on, wrapping past the end of the pattern.
+    //Steps that do not fit in the pattern are dropped
+    pub fn paste(&mut self, steps: &[Vec<NoteEvent>]) {
+        let len = self.events.len();
+
+        for (offset, notes) in steps.iter().take(len).enumerate() {
+            self.events[(self.selcected_step + offset) % len] = notes.clone();
+        }
+    }
+
+    //Repeats the selection right after itself and selects the copy.
+    //Without a selection the pattern is appended to itself
+    pub fn duplicate_selection(&mut self) {
+        let Some(range) = self.selection() else {
+            let mut doubled = self.events.clone();
+            doubled.extend(self.events.iter().cloned());
+            doubled.truncate(MAX_PATTERN_LENGTH);
+            self.events = doubled;
+            return;
+        };
+
+        let steps = self.events[range.clone()].to_vec();
+        let start = range.end() + 1;
+        if start >= self.events.len() {
+            return;
+        }
+
+        let end = (start + steps.len()).min(self.events.len());
+        for (slot, notes) in self.events[start..end].iter_mut().zip(steps) {
+            *slot = notes;
+        }
+
+        self.selection_anchor = Some(start);
+        self.selcected_step = end - 1;
+    }
+
     pub fn handle_keyboard_input(&mut self, key_event: KeyEvent) {
         self.sequencer_input_window.handle_keyboard_input(key_event);
 
-        if self.sequencer_input_window.is_editing() {
+        //Control combinations are editing commands handled by the mixer
+        if self.sequencer_input_window.is_editing()
+            || key_event.modifiers.contains(KeyModifiers::CONTROL)
+        {
             return;
         }
 
+        let shift = key_event.modifiers.contains(KeyModifiers::SHIFT);
+
         match key_event.code {
+            KeyCode::Right if shift => self.extend_selection(true),
+            KeyCode::Left if shift => self.extend_selection(false),
             KeyCode::Right => self.increment_selected_step(),
             KeyCode::Left => self.decrement_selected_step(),
+            KeyCode::Esc => self.selection_anchor = None,
+            KeyCode::Char(']') => self.set_pattern_len(self.events.len() + 1),
+            KeyCode::Char('[') => self.set_pattern_len(self.events.len().saturating_sub(1)),
+            KeyCode::Char('}') => self.cycle_step_division(true),
+            KeyCode::Char('{') => self.cycle_step_division(false),
             KeyCode::Char('i') => {
                 let input = self.sequencer_input_window.get_last_string_input();
 
-                match input.parse::<Note>() {
-                    Ok(note) => {
-                        info!("Note {}", note.freq());
-                        self.set_note_at(self.selcected_step, note.freq(), 1.0);
+                match self.parse_note_input(input) {
+                    Some(notes) => {
+                        info!("Notes {:?}", notes);
+                        let notes = notes
+                            .iter()
+                            .map(|note| NoteEvent::new(note.freq(), 1.0))
+                            .collect();
+                        self.set_chord_at(self.selcected_step, notes);
                     }
-                    Err(_) => {
+                    None => {
                         warn!("Unknown note {}", input);
                     }
                 }
             }
+            KeyCode::Char('v') => self.toggle_selection(),
+            KeyCode::Char('t') => self.apply_transform(Transform::Transpose(1)),
+            KeyCode::Char('T') => self.apply_transform(Transform::Transpose(-1)),
+            KeyCode::Char('o') => self.apply_transform(Transform::Transpose(12)),
+            KeyCode::Char('O') => self.apply_transform(Transform::Transpose(-12)),
+            KeyCode::Char(',') => self.apply_transform(Transform::Rotate(-1)),
+            KeyCode::Char('.') => self.apply_transform(Transform::Rotate(1)),
+            KeyCode::Char('x') => {
+                let input = self.sequencer_input_window.get_last_string_input();
+
+                match input.parse::<Transform>() {
+                    Ok(transform) => self.apply_transform(transform),
+                    Err(_) => warn!("Unknown transform {}", input),
+                }
+            }
+            KeyCode::Char('Q') => self.quantise_entry = !self.quantise_entry,
+            KeyCode::Char('E') => {
+                let input = self.sequencer_input_window.get_last_string_input();
+
+                match input.parse::<EuclideanRhythm>() {
+                    Ok(rhythm) => {
+                        info!("Euclidean E({}, {})", rhythm.hits, rhythm.steps);
+                        self.fill_euclidean(&rhythm);
+                    }
+                    Err(_) => warn!("Unknown euclidean rhythm {}", input),
+                }
+            }
+            KeyCode::Char('r') => self.randomise_new_seed(),
+            KeyCode::Char('R') => {
+                let input = self.sequencer_input_window.get_last_string_input();
+
+                match self.random_fill.apply_settings(input) {
+                    Ok(()) => self.randomise(),
+                    Err(_) => warn!("Unknown random fill settings {}", input),
+                }
+            }
+            KeyCode::Char('S') => {
+                let input = self.sequencer_input_window.get_last_string_input();
+
+                //"project" drops the track key and follows the project again
+                match input.parse::<Key>() {
+                    Ok(key) => self.set_key(key),
+                    Err(_) if input.trim() == "project" => self.follow_project_key(),
+                    Err(_) => warn!("Unknown key {}", input),
+                }
+            }
+            KeyCode::Char('m') => {
+                let input = self.sequencer_input_window.get_last_string_input();
+
+                match input.parse::<TimeSignature>() {
+                    Ok(time_signature) => self.set_time_signature(time_signature),
+                    Err(_) => warn!("Unknown time signature {}", input),
+                }
+            }
             _ => {}
         }
     }
 
-    pub fn reset(&mut self) {
-        self.current_step = 0;
-        self.samples_accumulated = 0.0;
+    //Last text submitted in the prompt, read by key commands outside the sequencer
+    pub fn last_input(&self) -> &str {
+        self.sequencer_input_window.get_last_string_input()
     }
 
-    pub fn set_bpm(&mut self, bpm: f32) {
-        self.bpm = bpm;
-        let beats_per_second = bpm / 60.0;
-        let samples_per_beat = self.sample_rate / beats_per_second;
-        self.samples_per_step = samples_per_beat / self.step_division as f32;
+    //True while the note prompt is taking text input
+    pub fn is_editing(&self) -> bool {
+        self.sequencer_input_window.is_editing()
+    }
+
+    pub fn reset(&mut self) {
+        self.current_step = 0;
+        self.last_step = None;
     }
 
     pub fn current_step(&self) -> usize {
@@ -160,6 +452,174 @@ impl Sequencer {
     pub fn pattern_len(&self) -> usize {
         self.events.len()
     }
+
+    //Resizes the pattern, new steps are empty and steps past the end are dropped
+    pub fn set_pattern_len(&mut self, length: usize) {
+        let length = length.clamp(1, MAX_PATTERN_LENGTH);
+        self.events.resize(length, Vec::new());
+        self.selcected_step = self.selcected_step.min(length - 1);
+        self.selection_anchor = self.selection_anchor.map(|anchor| anchor.min(length - 1));
+        self.current_step = self.current_step.min(length - 1);
+    }
+
+    pub fn step_division(&self) -> u8 {
+        self.step_division
+    }
+
+    pub fn set_step_division(&mut self, step_division: u8) {
+        self.step_division = step_division.max(1);
+    }
+
+    //Moves through STEP_DIVISIONS, `forward` picks the next finer division
+    fn cycle_step_division(&mut self, forward: bool) {
+        let idx = STEP_DIVISIONS
+            .iter()
+            .position(|d| *d >= self.step_division)
+            .unwrap_or(STEP_DIVISIONS.len() - 1);
+
+        let idx = if forward {
+            (idx + 1).min(STEP_DIVISIONS.len() - 1)
+        } else {
+            idx.saturating_sub(1)
+        };
+
+        self.set_step_division(STEP_DIVISIONS[idx]);
+    }
+
+    pub fn key(&self) -> Key {
+        self.key_override.unwrap_or(self.project_key)
+    }
+
+    //Gives the track its own key
+    pub fn set_key(&mut self, key: Key) {
+        self.key_override = Some(key);
+    }
+
+    pub fn follow_project_key(&mut self) {
+        self.key_override = None;
+    }
+
+    pub fn has_own_key(&self) -> bool {
+        self.key_override.is_some()
+    }
+
+    pub fn set_project_key(&mut self, key: Key) {
+        self.project_key = key;
+    }
+
+    pub fn quantise_entry(&self) -> bool {
+        self.quantise_entry
+    }
+
+    //Notes separated by spaces are entered as a chord, each is a note name or a scale degree
+    fn parse_note_input(&self, input: &str) -> Option<Vec<Note>> {
+        let key = self.key();
+
+        let notes: Option<Vec<Note>> = input
+            .split_whitespace()
+            .map(|part| {
+                let note = part
+                    .parse::<Note>()
+                    .ok()
+                    .or_else(|| key.parse_degree(part))?;
+
+                Some(self.snap_entry(note))
+            })
+            .collect();
+
+        notes.filter(|notes| !notes.is_empty())
+    }
+
+    //Snaps an entered note to the key when snapping is on
+    pub fn snap_entry(&self, note: Note) -> Note {
+        if !self.quantise_entry {
+            return note;
+        }
+
+        Note::from_midi(self.key().quantise_midi(note.midi())).unwrap_or(note)
+    }
+
+    //Step recording, writes the note at the cursor and moves on to the next step
+    pub fn record_step(&mut self, note: Note, velocity: f32) {
+        let note = self.snap_entry(note);
+        self.set_note_at(self.selcected_step, note.freq(), velocity);
+        self.increment_selected_step();
+    }
+
+    pub fn random_seed(&self) -> Option<u64> {
+        self.random_seed
+    }
+
+    pub fn random_fill(&self) -> &RandomFill {
+        &self.random_fill
+    }
+
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
+    //Only changes how steps are grouped, the pattern length is kept
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        self.time_signature = time_signature;
+    }
+
+    pub fn snapshot(&self) -> PatternSnapshot {
+        PatternSnapshot {
+            events: self.events.clone(),
+            step_division: self.step_division,
+            time_signature: self.time_signature,
+            key_override: self.key_override,
+            random_seed: self.random_seed,
+        }
+    }
+
+    //Copies the pattern into an existing snapshot, its buffers are reused when they are big enough
+    pub fn snapshot_into(&self, snapshot: &mut PatternSnapshot) {
+        snapshot.events.clone_from(&self.events);
+        snapshot.step_division = self.step_division;
+        snapshot.time_signature = self.time_signature;
+        snapshot.key_override = self.key_override;
+        snapshot.random_seed = self.random_seed;
+    }
+
+    //Puts back a snapshot, the cursor and selection are kept inside the pattern
+    pub fn restore(&mut self, snapshot: &PatternSnapshot) {
+        self.events = snapshot.events.clone();
+        self.set_pattern_len(self.events.len());
+        self.step_division = snapshot.step_division;
+        self.time_signature = snapshot.time_signature;
+        self.key_override = snapshot.key_override;
+        self.random_seed = snapshot.random_seed;
+    }
+}
+
+//Note value of a step division, triplet divisions are marked with a T
+pub fn division_label(step_division: u8) -> String {
+    let step_division = step_division.max(1) as u32;
+
+    if step_division.is_multiple_of(3) {
+        format!("1/{}T", step_division / 3 * 8)
+    } else {
+        format!("1/{}", step_division * 4)
+    }
+}
+
+//Reverse of division_label, "1/16" gives 4 and "1/8T" gives 3
+pub fn parse_division_label(label: &str) -> Option<u8> {
+    let (value, triplet) = match label.strip_suffix('T') {
+        Some(value) => (value, true),
+        None => (label, false),
+    };
+
+    let note_value: u32 = value.strip_prefix("1/")?.parse().ok()?;
+
+    let step_division = if triplet {
+        note_value * 3 / 8
+    } else {
+        note_value / 4
+    };
+
+    u8::try_from(step_division).ok().filter(|d| *d > 0)
 }
 
 impl Widget for &Sequencer {
@@ -168,11 +628,22 @@ impl Widget for &Sequencer {
         Self: Sized,
     {
         // Calculate step width by dividing the area width with events length
-        let step_width = area.width / self.events.len() as u16;
+        let selection = self.selection();
+
+        // Long patterns keep a minimum width and scroll by page to the selected step
+        let step_width = (area.width / self.events.len() as u16).max(2);
+        let visible_steps = (area.width / step_width).max(1) as usize;
+        let first_step = self.selcected_step / visible_steps * visible_steps;
 
-        for (step_idx, event) in self.events.iter().enumerate() {
+        for (step_idx, event) in self
+            .events
+            .iter()
+            .enumerate()
+            .skip(first_step)
+            .take(visible_steps)
+        {
             //The step x = the step index multiplied by the x
-            let x = area.x + (step_idx as u16 * step_width);
+            let x = area.x + ((step_idx - first_step) as u16 * step_width);
             let cell_area = Rect {
                 x,
                 y: area.y,
@@ -186,9 +657,22 @@ impl Widget for &Sequencer {
                 Style::default().bg(Color::Yellow).fg(Color::Black)
             } else if step_idx == self.selcected_step {
                 Style::default().bg(Color::Green)
-            } else if event.is_some() {
+            } else if selection.as_ref().is_some_and(|r| r.contains(&step_idx)) {
+                // Inside the selected range
+                if event.is_empty() {
+                    Style::default().bg(Color::Magenta)
+                } else {
+                    Style::default().bg(Color::LightMagenta)
+                }
+            } else if !event.is_empty() {
                 // Step has a note - filled
                 Style::default().bg(Color::Blue)
+            } else if self
+                .time_signature
+                .is_beat_start(step_idx, self.step_division)
+            {
+                // Empty step on a beat - lighter so beats stand out
+                Style::default().bg(Color::Gray)
             } else {
                 // Empty step
                 Style::default().bg(Color::DarkGray)
@@ -199,12 +683,240 @@ impl Widget for &Sequencer {
             block.render(cell_area, buf);
 
             // Optionally show note info
-            if let Some(note) = event {
-                let freq_text = format!("{:.0}Hz", note.frequency);
+            if let Some(note) = event.first() {
+                let freq_text = match event.len() {
+                    1 => format!("{:.0}Hz", note.frequency),
+                    len => format!("{:.0}Hz+{}", note.frequency, len - 1),
+                };
                 buf.set_string(x, area.y, &freq_text, Style::default().fg(Color::White));
             }
+
+            // Bar number on the bottom row of the first step of every bar
+            if self
+                .time_signature
+                .is_bar_start(step_idx, self.step_division)
+                && area.height > 1
+            {
+                let steps_per_bar = self.time_signature.steps_per_bar(self.step_division);
+                let bar_text = format!("{}", step_idx / steps_per_bar + 1);
+                buf.set_string(
+                    x,
+                    area.y + area.height - 1,
+                    &bar_text,
+                    Style::default().fg(Color::White),
+                );
+            }
         }
 
         self.sequencer_input_window.render(area, buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{notes::midi_to_freq, scales::Scale};
+
+    fn sequencer(key: &str) -> Sequencer {
+        let mut sequencer = Sequencer::new(8, 4);
+        sequencer.set_key(key.parse().unwrap());
+        sequencer
+    }
+
+    #[test]
+    fn degrees_enter_a_chord() {
+        let sequencer = sequencer("D minor");
+        assert_eq!(
+            sequencer.parse_note_input("1 3 5"),
+            Some(vec![Note::D4, Note::F4, Note::A4])
+        );
+        assert_eq!(
+            sequencer.parse_note_input("C2 8 b3+"),
+            Some(vec![Note::C2, Note::D5, Note::E5])
+        );
+    }
+
+    #[test]
+    fn invalid_note_input_is_rejected() {
+        let sequencer = sequencer("C major");
+        assert_eq!(sequencer.parse_note_input(""), None);
+        assert_eq!(sequencer.parse_note_input("1 zz"), None);
+        assert_eq!(sequencer.parse_note_input("0"), None);
+    }
+
+    #[test]
+    fn snapped_entry_stays_in_key() {
+        let mut sequencer = sequencer("D minor");
+        sequencer.quantise_entry = true;
+        assert_eq!(
+            sequencer.parse_note_input("C#4 #4"),
+            Some(vec![Note::C4, Note::G4])
+        );
+
+        sequencer.set_key(Key::new(0, Scale::Chromatic));
+        assert_eq!(sequencer.parse_note_input("C#4"), Some(vec![Note::Cs4]));
+    }
+
+    //A pattern with one note per step from MIDI numbers, None is an empty step
+    fn pattern(midi: &[Option<u8>]) -> Sequencer {
+        let mut sequencer = Sequencer::new(midi.len(), 4);
+        for (step, midi) in midi.iter().enumerate() {
+            if let Some(midi) = midi {
+                sequencer.set_note_at(step, midi_to_freq(*midi as f32), 1.0);
+            }
+        }
+        sequencer
+    }
+
+    fn midi(sequencer: &Sequencer) -> Vec<Option<u8>> {
+        (0..sequencer.pattern_len())
+            .map(|step| {
+                sequencer
+                    .get_event(step)
+                    .first()
+                    .map(|note| crate::notes::freq_to_midi(note.frequency).round() as u8)
+            })
+            .collect()
+    }
+
+    fn select(sequencer: &mut Sequencer, anchor: usize, cursor: usize) {
+        sequencer.selection_anchor = Some(anchor);
+        sequencer.selcected_step = cursor;
+    }
+
+    #[test]
+    fn transform_applies_to_selection_only() {
+        let mut sequencer = pattern(&[Some(60), Some(62), Some(64), Some(65)]);
+        select(&mut sequencer, 2, 1);
+
+        sequencer.apply_transform(Transform::Transpose(1));
+        assert_eq!(midi(&sequencer), [Some(60), Some(63), Some(65), Some(65)]);
+
+        sequencer.apply_transform(Transform::Reverse);
+        assert_eq!(midi(&sequencer), [Some(60), Some(65), Some(63), Some(65)]);
+
+        //Doubling a selection stretches it in place
+        sequencer.apply_transform(Transform::Double);
+        assert_eq!(sequencer.pattern_len(), 4);
+        assert_eq!(midi(&sequencer), [Some(60), Some(65), None, Some(65)]);
+    }
+
+    #[test]
+    fn transform_applies_to_whole_pattern_without_selection() {
+        let mut sequencer = pattern(&[Some(60), None, Some(64), Some(65)]);
+
+        sequencer.apply_transform(Transform::Transpose(-12));
+        assert_eq!(midi(&sequencer), [Some(48), None, Some(52), Some(53)]);
+
+        sequencer.apply_transform(Transform::Double);
+        assert_eq!(sequencer.pattern_len(), 8);
+        assert_eq!(
+            midi(&sequencer),
+            [Some(48), None, None, None, Some(52), None, Some(53), None]
+        );
+
+        sequencer.apply_transform(Transform::Halve);
+        assert_eq!(midi(&sequencer), [Some(48), None, Some(52), Some(53)]);
+    }
+
+    #[test]
+    fn copy_takes_selection_or_whole_pattern() {
+        let mut sequencer = pattern(&[Some(60), Some(62), None, Some(65)]);
+        assert_eq!(sequencer.copy_selection().len(), 4);
+
+        select(&mut sequencer, 1, 3);
+        let copied = sequencer.copy_selection();
+        assert_eq!(copied.len(), 3);
+        assert!(copied[1].is_empty());
+        assert_eq!(midi(&sequencer), [Some(60), Some(62), None, Some(65)]);
+    }
+
+    #[test]
+    fn cut_clears_the_selection() {
+        let mut sequencer = pattern(&[Some(60), Some(62), Some(64), Some(65)]);
+        select(&mut sequencer, 1, 2);
+
+        let cut = sequencer.cut_selection();
+        assert_eq!(cut.len(), 2);
+        assert_eq!(midi(&sequencer), [Some(60), None, None, Some(65)]);
+
+        sequencer.selection_anchor = None;
+        sequencer.cut_selection();
+        assert_eq!(midi(&sequencer), [None; 4]);
+    }
+
+    #[test]
+    fn paste_overwrites_from_cursor_and_wraps() {
+        let mut source = pattern(&[Some(70), Some(71), Some(72)]);
+        select(&mut source, 0, 2);
+        let copied = source.copy_selection();
+
+        let mut sequencer = pattern(&[Some(60), Some(62), Some(64), Some(65)]);
+        sequencer.selcected_step = 1;
+        sequencer.paste(&copied);
+        assert_eq!(midi(&sequencer), [Some(60), Some(70), Some(71), Some(72)]);
+
+        //Past the end the paste wraps to the start of the pattern
+        sequencer.selcected_step = 3;
+        sequencer.paste(&copied);
+        assert_eq!(midi(&sequencer), [Some(71), Some(72), Some(71), Some(70)]);
+    }
+
+    #[test]
+    fn paste_longer_than_pattern_is_cut_short() {
+        let long = pattern(&[Some(70), Some(71), Some(72), Some(73), Some(74), Some(75)]);
+        let mut sequencer = pattern(&[None; 4]);
+        sequencer.selcected_step = 2;
+
+        sequencer.paste(&long.copy_selection());
+        assert_eq!(midi(&sequencer), [Some(72), Some(73), Some(70), Some(71)]);
+    }
+
+    #[test]
+    fn duplicate_repeats_selection_after_itself() {
+        let mut sequencer = pattern(&[Some(60), Some(62), None, None, None]);
+        select(&mut sequencer, 0, 1);
+
+        sequencer.duplicate_selection();
+        assert_eq!(
+            midi(&sequencer),
+            [Some(60), Some(62), Some(60), Some(62), None]
+        );
+        assert_eq!(sequencer.selection(), Some(2..=3));
+
+        //The next copy runs into the end of the pattern and is cut short
+        sequencer.duplicate_selection();
+        assert_eq!(
+            midi(&sequencer),
+            [Some(60), Some(62), Some(60), Some(62), Some(60)]
+        );
+        assert_eq!(sequencer.selection(), Some(4..=4));
+
+        //Nothing fits after a selection at the end
+        sequencer.duplicate_selection();
+        assert_eq!(sequencer.pattern_len(), 5);
+        assert_eq!(sequencer.selection(), Some(4..=4));
+    }
+
+    #[test]
+    fn duplicate_without_selection_doubles_pattern() {
+        let mut sequencer = pattern(&[Some(60), None, Some(64)]);
+        sequencer.duplicate_selection();
+        assert_eq!(
+            midi(&sequencer),
+            [Some(60), None, Some(64), Some(60), None, Some(64)]
+        );
+    }
+
+    #[test]
+    fn snapshot_into_reuses_the_buffers() {
+        let mut sequencer = pattern(&[Some(60), None, Some(64)]);
+        let mut snapshot = sequencer.snapshot();
+        let events = snapshot.events.as_ptr();
+
+        sequencer.set_chord_at(1, vec![NoteEvent::new(440.0, 1.0)]);
+        sequencer.snapshot_into(&mut snapshot);
+        assert!(snapshot == sequencer.snapshot());
+        assert_eq!(snapshot.events.as_ptr(), events);
+    }
+}
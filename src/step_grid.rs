@@ -0,0 +1,153 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+use crate::{mixer::Mixer, notes::Note, sequencer::NoteEvent};
+
+//Width of the track names on the left
+const NAME_WIDTH: u16 = 12;
+//Characters drawn per step
+const STEP_WIDTH: u16 = 2;
+
+//Every track as a row of steps, the row cursor is the selected mixer track
+pub struct StepGridView {
+    column: usize,
+}
+
+impl StepGridView {
+    pub fn new() -> Self {
+        StepGridView { column: 0 }
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn handle_keyboard_input(&mut self, key_event: KeyEvent, mixer: &mut Mixer) {
+        if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        match key_event.code {
+            KeyCode::Left => self.column = self.column.saturating_sub(1),
+            KeyCode::Right => self.column += 1,
+            KeyCode::Up => mixer.previous_track(),
+            KeyCode::Down => mixer.next_track(),
+            KeyCode::Enter | KeyCode::Char('x') => self.toggle_step(mixer),
+            KeyCode::Delete | KeyCode::Backspace => {
+                let column = self.column;
+                mixer.edit_selected_pattern(|sequencer| sequencer.clear_step(column));
+            }
+            _ => {}
+        }
+
+        //Rows can have different lengths, keep the cursor on the selected one
+        if let Some(track) = mixer.selected_track() {
+            self.column = self.column.min(track.sequencer().pattern_len() - 1);
+        }
+    }
+
+    //New hits use the first note already in the pattern so drum tracks keep their pitch
+    fn toggle_step(&self, mixer: &mut Mixer) {
+        let column = self.column;
+
+        mixer.edit_selected_pattern(|sequencer| {
+            if !sequencer.get_event(column).is_empty() {
+                sequencer.clear_step(column);
+                return;
+            }
+
+            let note = (0..sequencer.pattern_len())
+                .find_map(|step| sequencer.get_event(step).first().copied())
+                .unwrap_or(NoteEvent::new(Note::C4.freq(), 1.0));
+            sequencer.set_chord_at(column, vec![note]);
+        });
+    }
+}
+
+impl Default for StepGridView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Step grid view paired with the mixer it shows, built for every frame
+pub struct StepGrid<'a> {
+    pub view: &'a StepGridView,
+    pub mixer: &'a Mixer,
+}
+
+impl Widget for StepGrid<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        if area.width <= NAME_WIDTH {
+            return;
+        }
+
+        let columns = ((area.width - NAME_WIDTH) / STEP_WIDTH) as usize;
+        //Page through the steps so the cursor stays visible
+        let first = self.view.column / columns.max(1) * columns.max(1);
+
+        for (row, (idx, track)) in self
+            .mixer
+            .ordered_tracks()
+            .enumerate()
+            .take(area.height as usize)
+            .enumerate()
+        {
+            let y = area.y + row as u16;
+            let sequencer = track.sequencer();
+            let is_selected = idx == self.mixer.selected_index();
+
+            let name_style = if is_selected {
+                Style::default().fg(Color::Black).bg(Color::Green)
+            } else {
+                Style::default().fg(Color::Gray)
+            };
+            let name: String = track
+                .get_name()
+                .chars()
+                .take(NAME_WIDTH as usize - 1)
+                .collect();
+            buf.set_string(
+                area.x,
+                y,
+                format!("{:<width$}", name, width = NAME_WIDTH as usize - 1),
+                name_style,
+            );
+
+            for column in 0..columns {
+                let step = first + column;
+                if step >= sequencer.pattern_len() {
+                    break;
+                }
+
+                let hit = !sequencer.get_event(step).is_empty();
+                let color = if is_selected && step == self.view.column {
+                    Color::Green
+                } else if step == sequencer.current_step() {
+                    Color::Yellow
+                } else if hit {
+                    Color::Blue
+                } else if sequencer
+                    .time_signature()
+                    .is_beat_start(step, sequencer.step_division())
+                {
+                    Color::Gray
+                } else {
+                    Color::DarkGray
+                };
+
+                let x = area.x + NAME_WIDTH + column as u16 * STEP_WIDTH;
+                let cell = if hit { "■" } else { "·" };
+                buf.set_string(x, y, cell, Style::default().fg(color));
+            }
+        }
+    }
+}
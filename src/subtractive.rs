@@ -0,0 +1,308 @@
+use crate::{
+    filter::{FILTER_MODE_NAMES, FILTER_MODES, FilterMode, StateVariableFilter},
+    generators::{
+        ENVELOPE_PARAMETERS, Envelope, Instrument, Processor, WAVE_NAMES, WAVE_TYPES, WaveType,
+    },
+    noise::{NOISE_COLOUR_NAMES, Noise, NoiseColour},
+    notes::Note,
+    oscillator::{Oscillator, OscillatorQuality},
+    parameter::Parameter,
+};
+
+const FILTER_ENVELOPE_NAMES: [&str; ENVELOPE_PARAMETERS] = [
+    "Filter Attack",
+    "Filter Decay",
+    "Filter Sustain",
+    "Filter Release",
+];
+//Parameters before the filter envelope, see parameters
+const VOICE_PARAMETERS: usize = 16;
+
+//One of the two oscillators, tuned relative to the played note
+struct SubtractiveOscillator {
+    oscillator: Oscillator,
+    level: f32,
+    semitones: f32,
+    cents: f32,
+}
+
+impl SubtractiveOscillator {
+    fn new(wave_type: WaveType, level: f32, cents: f32) -> Self {
+        SubtractiveOscillator {
+            oscillator: Oscillator::new(wave_type, OscillatorQuality::Standard),
+            level,
+            semitones: 0.0,
+            cents,
+        }
+    }
+
+    fn process(&mut self, frequency: f32, sample_rate: f32) -> f32 {
+        let ratio = ((self.semitones + self.cents / 100.0) / 12.0).exp2();
+        self.oscillator.process(frequency * ratio, sample_rate) * self.level
+    }
+
+    fn parameters(&self, names: [&'static str; 4]) -> [Parameter; 4] {
+        let wave = WAVE_TYPES
+            .iter()
+            .position(|wave_type| *wave_type == self.oscillator.wave_type())
+            .unwrap_or(0);
+
+        [
+            Parameter::choice(names[0], wave, &WAVE_NAMES),
+            Parameter::new(names[1], self.level, 0.0, 1.0, 0.01),
+            Parameter::new(names[2], self.semitones, -24.0, 24.0, 1.0).unit("st"),
+            Parameter::new(names[3], self.cents, -100.0, 100.0, 1.0).unit("ct"),
+        ]
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => {
+                let wave = (value.round().max(0.0) as usize).min(WAVE_TYPES.len() - 1);
+                self.oscillator.set_wave_type(WAVE_TYPES[wave]);
+            }
+            1 => self.level = value.clamp(0.0, 1.0),
+            2 => self.semitones = value.round().clamp(-24.0, 24.0),
+            3 => self.cents = value.clamp(-100.0, 100.0),
+            _ => {}
+        }
+    }
+}
+
+//Two oscillators and noise through a resonant filter, the filter has its own envelope
+pub struct SubtractiveSynth {
+    sample_rate: f32,
+    oscillators: [SubtractiveOscillator; 2],
+    noise_level: f32,
+    noise: Noise,
+    filter: StateVariableFilter,
+    cutoff: f32,
+    resonance: f32,
+    //Semitones the cutoff moves at full filter envelope
+    envelope_amount: f32,
+    //0 keeps the cutoff fixed, 1 moves it with the note from C4
+    key_tracking: f32,
+    //Seconds to slide to a new note
+    glide: f32,
+    //log2 of the playing frequency, glide moves it towards the target
+    pitch: Option<f32>,
+    target_pitch: f32,
+    //Pitch the slide started from, and its length and samples left
+    glide_from: f32,
+    glide_length: usize,
+    glide_remaining: usize,
+    velocity: f32,
+    filter_envelope: Envelope,
+    envelope: Envelope,
+}
+
+impl SubtractiveSynth {
+    pub fn new(sample_rate: f32, envelope: Envelope) -> Self {
+        SubtractiveSynth {
+            sample_rate,
+            oscillators: [
+                SubtractiveOscillator::new(WaveType::Saw, 0.7, 0.0),
+                SubtractiveOscillator::new(WaveType::Saw, 0.7, 7.0),
+            ],
+            noise_level: 0.0,
+            noise: Noise::new(NoiseColour::White, 0),
+            filter: StateVariableFilter::new(FilterMode::LowPass),
+            cutoff: 800.0,
+            resonance: 0.3,
+            envelope_amount: 36.0,
+            key_tracking: 0.5,
+            glide: 0.0,
+            pitch: None,
+            target_pitch: 440.0f32.log2(),
+            glide_from: 440.0f32.log2(),
+            glide_length: 0,
+            glide_remaining: 0,
+            velocity: 1.0,
+            filter_envelope: Envelope::new(0.005, 0.3, 0.2, 0.2, sample_rate),
+            envelope,
+        }
+    }
+
+    fn next_frequency(&mut self) -> f32 {
+        let pitch = if self.glide_remaining > 0 {
+            self.glide_remaining -= 1;
+            let left = self.glide_remaining as f32 / self.glide_length as f32;
+            self.target_pitch + (self.glide_from - self.target_pitch) * left
+        } else {
+            self.target_pitch
+        };
+
+        self.pitch = Some(pitch);
+        pitch.exp2()
+    }
+}
+
+impl Instrument for SubtractiveSynth {
+    fn get_name(&self) -> &str {
+        "Subtractive"
+    }
+
+    fn process(&mut self) -> f32 {
+        let frequency = self.next_frequency();
+
+        let mut mix = self.noise.process() * self.noise_level;
+        for oscillator in self.oscillators.iter_mut() {
+            mix += oscillator.process(frequency, self.sample_rate);
+        }
+        //Keep the sum of the sources from clipping
+        let total = self.noise_level + self.oscillators.iter().map(|o| o.level).sum::<f32>();
+        mix /= total.max(1.0);
+
+        let tracking = (frequency / Note::C4.freq()).powf(self.key_tracking);
+        let modulation = (self.filter_envelope.process(1.0) * self.envelope_amount / 12.0).exp2();
+        let cutoff = self.cutoff * tracking * modulation;
+        let filtered = self
+            .filter
+            .process(mix, cutoff, self.resonance, self.sample_rate);
+
+        self.envelope.process(filtered) * self.velocity
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        self.target_pitch = frequency.max(1.0).log2();
+        if let Some(pitch) = self.pitch {
+            self.glide_from = pitch;
+            self.glide_length = (self.glide * self.sample_rate).round() as usize;
+            self.glide_remaining = self.glide_length;
+        }
+        self.velocity = velocity;
+        self.filter_envelope.start();
+        self.envelope.start();
+    }
+
+    fn note_off(&mut self) {
+        self.filter_envelope.stop();
+        self.envelope.stop();
+    }
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        self.oscillators[0].oscillator.phase()
+    }
+
+    fn set_quality(&mut self, quality: OscillatorQuality) {
+        for oscillator in self.oscillators.iter_mut() {
+            oscillator.oscillator.set_quality(quality);
+        }
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let mode = FILTER_MODES
+            .iter()
+            .position(|mode| *mode == self.filter.mode())
+            .unwrap_or(0);
+
+        let mut parameters = Vec::new();
+        parameters.extend(self.oscillators[0].parameters([
+            "Osc 1 Wave",
+            "Osc 1 Level",
+            "Osc 1 Semitones",
+            "Osc 1 Detune",
+        ]));
+        parameters.extend(self.oscillators[1].parameters([
+            "Osc 2 Wave",
+            "Osc 2 Level",
+            "Osc 2 Semitones",
+            "Osc 2 Detune",
+        ]));
+        parameters.extend([
+            Parameter::new("Noise Level", self.noise_level, 0.0, 1.0, 0.01),
+            Parameter::choice(
+                "Noise Colour",
+                self.noise.colour().index(),
+                &NOISE_COLOUR_NAMES,
+            ),
+            Parameter::choice("Filter Mode", mode, &FILTER_MODE_NAMES),
+            Parameter::new("Cutoff", self.cutoff, 20.0, 20000.0, 0.05)
+                .unit("Hz")
+                .logarithmic(),
+            Parameter::new("Resonance", self.resonance, 0.0, 1.0, 0.01),
+            Parameter::new("Env Amount", self.envelope_amount, -72.0, 72.0, 1.0).unit("st"),
+            Parameter::new("Key Tracking", self.key_tracking, 0.0, 1.0, 0.05),
+            Parameter::new("Glide", self.glide, 0.0, 5.0, 0.01).unit("s"),
+        ]);
+        parameters.extend(
+            self.filter_envelope
+                .parameters()
+                .into_iter()
+                .zip(FILTER_ENVELOPE_NAMES)
+                .map(|(parameter, name)| Parameter { name, ..parameter }),
+        );
+        parameters.extend(self.envelope.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0..4 => self.oscillators[0].set_parameter(index, value),
+            4..8 => self.oscillators[1].set_parameter(index - 4, value),
+            8 => self.noise_level = value.clamp(0.0, 1.0),
+            9 => self.noise.set_colour(NoiseColour::from_index(value)),
+            10 => {
+                let mode = (value.round().max(0.0) as usize).min(FILTER_MODES.len() - 1);
+                self.filter.set_mode(FILTER_MODES[mode]);
+            }
+            11 => self.cutoff = value.clamp(20.0, 20000.0),
+            12 => self.resonance = value.clamp(0.0, 1.0),
+            13 => self.envelope_amount = value.clamp(-72.0, 72.0),
+            14 => self.key_tracking = value.clamp(0.0, 1.0),
+            15 => self.glide = value.clamp(0.0, 5.0),
+            index if index < VOICE_PARAMETERS + ENVELOPE_PARAMETERS => self
+                .filter_envelope
+                .set_parameter(index - VOICE_PARAMETERS, value),
+            index => self
+                .envelope
+                .set_parameter(index - VOICE_PARAMETERS - ENVELOPE_PARAMETERS, value),
+        }
+    }
+
+    fn reset(&mut self) {
+        for oscillator in self.oscillators.iter_mut() {
+            oscillator.oscillator.reset();
+        }
+        self.pitch = None;
+        self.glide_remaining = 0;
+        self.noise.set_seed(self.noise.seed());
+        self.filter.reset();
+        self.filter_envelope.reset();
+        self.envelope.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 48000.0;
+
+    #[test]
+    fn glide_reaches_the_note_in_the_glide_time() {
+        let mut synth =
+            SubtractiveSynth::new(SAMPLE_RATE, Envelope::new(0.01, 0.1, 1.0, 0.1, SAMPLE_RATE));
+        synth.set_parameter(15, 0.1);
+        synth.note_on(220.0, 1.0);
+        synth.process();
+
+        synth.note_on(440.0, 1.0);
+        let samples = (0.1 * SAMPLE_RATE) as usize;
+        for _ in 0..samples / 2 {
+            synth.process();
+        }
+        //Half an octave up after half the glide time
+        let halfway = synth.pitch.unwrap();
+        assert!((halfway - 220.0f32.log2() - 0.5).abs() < 0.01);
+
+        for _ in samples / 2..samples {
+            synth.process();
+        }
+        assert_eq!(synth.pitch, Some(synth.target_pitch));
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    fmt,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+pub const MIN_BPM: f32 = 20.0;
+pub const MAX_BPM: f32 = 300.0;
+
+//Taps further apart than this start a new measurement
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_TAPS: usize = 8;
+
+//Tempo changes placed on bars, read by the mixer while the transport runs
+#[derive(Default)]
+pub struct TempoMap {
+    events: Vec<TempoEvent>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoEvent {
+    pub bar: u32,
+    pub bpm: f32,
+    //Ramp linearly from the previous event instead of jumping at the bar
+    pub ramp: bool,
+}
+
+impl fmt::Display for TempoEvent {
+    //Bars count from 1 like the transport, a ramp is marked with ~
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ramp = if self.ramp { "~" } else { "" };
+        write!(f, "{}:{}{:.0}", self.bar + 1, ramp, self.bpm)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseTempoEventError;
+
+impl fmt::Display for ParseTempoEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tempo event string")
+    }
+}
+
+impl std::error::Error for ParseTempoEventError {}
+
+impl FromStr for TempoEvent {
+    type Err = ParseTempoEventError;
+
+    //Parses "bar bpm" with an optional "ramp", e.g. "9 140 ramp". Bars count from 1
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+
+        let bar: u32 = words
+            .next()
+            .and_then(|bar| bar.parse().ok())
+            .filter(|bar| *bar >= 1)
+            .ok_or(ParseTempoEventError)?;
+        let bpm: f32 = words
+            .next()
+            .and_then(|bpm| bpm.parse().ok())
+            .filter(|bpm| (MIN_BPM..=MAX_BPM).contains(bpm))
+            .ok_or(ParseTempoEventError)?;
+        let ramp = match words.next() {
+            None => false,
+            Some(word) if word.eq_ignore_ascii_case("ramp") => true,
+            Some(_) => return Err(ParseTempoEventError),
+        };
+
+        if words.next().is_some() {
+            return Err(ParseTempoEventError);
+        }
+
+        Ok(TempoEvent {
+            bar: bar - 1,
+            bpm,
+            ramp,
+        })
+    }
+}
+
+impl TempoMap {
+    pub fn new() -> Self {
+        TempoMap { events: Vec::new() }
+    }
+
+    //Adds an event, replacing any event on the same bar
+    pub fn insert(&mut self, event: TempoEvent) {
+        let event = TempoEvent {
+            bpm: event.bpm.clamp(MIN_BPM, MAX_BPM),
+            ..event
+        };
+
+        match self.events.binary_search_by_key(&event.bar, |e| e.bar) {
+            Ok(idx) => self.events[idx] = event,
+            Err(idx) => self.events.insert(idx, event),
+        }
+    }
+
+    pub fn remove(&mut self, bar: u32) {
+        self.events.retain(|e| e.bar != bar);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn events(&self) -> &[TempoEvent] {
+        &self.events
+    }
+
+    //Returns the tempo at a position in bars, None before the first event
+    pub fn bpm_at(&self, bar_position: f64) -> Option<f32> {
+        let next_idx = self
+            .events
+            .partition_point(|e| e.bar as f64 <= bar_position);
+
+        let previous = self.events.get(next_idx.checked_sub(1)?)?;
+
+        match self.events.get(next_idx) {
+            Some(next) if next.ramp => {
+                let span = (next.bar - previous.bar) as f64;
+                let t = ((bar_position - previous.bar as f64) / span) as f32;
+                Some(previous.bpm + (next.bpm - previous.bpm) * t)
+            }
+            _ => Some(previous.bpm),
+        }
+    }
+}
+
+//Averages the interval between key taps into a tempo
+#[derive(Default)]
+pub struct TapTempo {
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        TapTempo { taps: Vec::new() }
+    }
+
+    //Registers a tap, returns the tempo once there are at least two taps
+    pub fn tap(&mut self, now: Instant) -> Option<f32> {
+        if let Some(last) = self.taps.last()
+            && now.duration_since(*last) > TAP_TIMEOUT
+        {
+            self.taps.clear();
+        }
+
+        self.taps.push(now);
+
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        let intervals = self.taps.len() as f32 - 1.0;
+
+        if intervals < 1.0 {
+            return None;
+        }
+
+        let average = last.duration_since(*first).as_secs_f32() / intervals;
+        Some((60.0 / average).clamp(MIN_BPM, MAX_BPM))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(bar: u32, bpm: f32, ramp: bool) -> TempoEvent {
+        TempoEvent { bar, bpm, ramp }
+    }
+
+    fn ramp_map() -> TempoMap {
+        let mut map = TempoMap::new();
+        map.insert(event(2, 100.0, false));
+        map.insert(event(6, 140.0, true));
+        map
+    }
+
+    #[test]
+    fn no_tempo_before_first_event() {
+        assert_eq!(ramp_map().bpm_at(1.99), None);
+        assert_eq!(TempoMap::new().bpm_at(0.0), None);
+    }
+
+    #[test]
+    fn ramp_interpolates_between_events() {
+        let map = ramp_map();
+        assert_eq!(map.bpm_at(2.0), Some(100.0));
+        assert_eq!(map.bpm_at(4.0), Some(120.0));
+        assert_eq!(map.bpm_at(5.5), Some(135.0));
+    }
+
+    #[test]
+    fn last_event_holds_after_it() {
+        let map = ramp_map();
+        assert_eq!(map.bpm_at(6.0), Some(140.0));
+        assert_eq!(map.bpm_at(100.0), Some(140.0));
+    }
+
+    #[test]
+    fn jump_without_ramp() {
+        let mut map = ramp_map();
+        map.insert(event(6, 140.0, false));
+        assert_eq!(map.bpm_at(5.99), Some(100.0));
+        assert_eq!(map.bpm_at(6.0), Some(140.0));
+    }
+
+    #[test]
+    fn insert_replaces_and_remove_deletes() {
+        let mut map = ramp_map();
+        map.insert(event(2, 90.0, false));
+        assert_eq!(map.events().len(), 2);
+        assert_eq!(map.bpm_at(2.0), Some(90.0));
+
+        map.remove(2);
+        assert_eq!(map.events(), &[event(6, 140.0, true)]);
+    }
+
+    #[test]
+    fn parses_prompt_events() {
+        let parsed: TempoEvent = "9 140 ramp".parse().unwrap();
+        assert_eq!(parsed, event(8, 140.0, true));
+        assert_eq!(parsed.to_string(), "9:~140");
+        assert_eq!("1 90".parse::<TempoEvent>().unwrap(), event(0, 90.0, false));
+
+        for input in [
+            "",
+            "0 120",
+            "9",
+            "9 fast",
+            "9 1000",
+            "9 120 slide",
+            "9 120 ramp x",
+        ] {
+            assert!(input.parse::<TempoEvent>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn tap_tempo_averages_intervals() {
+        let mut tap = TapTempo::new();
+        let start = Instant::now();
+
+        assert_eq!(tap.tap(start), None);
+        assert_eq!(tap.tap(start + Duration::from_millis(500)), Some(120.0));
+        let bpm = tap.tap(start + Duration::from_millis(1100)).unwrap();
+        assert!((bpm - 60.0 / 0.55).abs() < 0.01);
+    }
+
+    #[test]
+    fn tap_tempo_starts_again_after_timeout() {
+        let mut tap = TapTempo::new();
+        let start = Instant::now();
+
+        tap.tap(start);
+        tap.tap(start + Duration::from_millis(500));
+        let late = start + Duration::from_millis(500) + TAP_TIMEOUT + Duration::from_millis(1);
+        assert_eq!(tap.tap(late), None);
+        assert_eq!(tap.tap(late + Duration::from_millis(250)), Some(240.0));
+    }
+
+    #[test]
+    fn tap_tempo_keeps_latest_taps() {
+        let mut tap = TapTempo::new();
+        let start = Instant::now();
+
+        //Slow taps followed by enough fast ones to push them out
+        let mut time = start;
+        for _ in 0..4 {
+            time += Duration::from_millis(1000);
+            tap.tap(time);
+        }
+        let mut bpm = None;
+        for _ in 0..MAX_TAPS {
+            time += Duration::from_millis(500);
+            bpm = tap.tap(time);
+        }
+        assert_eq!(bpm, Some(120.0));
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fmt, str::FromStr};
+
+//Meters cycled through with the time signature key
+const PRESETS: [TimeSignature; 7] = [
+    TimeSignature::new(4, 4),
+    TimeSignature::new(3, 4),
+    TimeSignature::new(5, 4),
+    TimeSignature::new(6, 8),
+    TimeSignature::new(7, 8),
+    TimeSignature::new(9, 8),
+    TimeSignature::new(12, 8),
+];
+
+//Meter of the project or a pattern, step divisions are always counted per quarter note
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl TimeSignature {
+    pub const fn new(numerator: u8, denominator: u8) -> Self {
+        TimeSignature {
+            numerator,
+            denominator,
+        }
+    }
+
+    //Length of one beat of the meter in quarter notes
+    pub fn quarters_per_beat(&self) -> f64 {
+        4.0 / self.denominator as f64
+    }
+
+    pub fn quarters_per_bar(&self) -> f64 {
+        self.numerator as f64 * self.quarters_per_beat()
+    }
+
+    pub fn beats_per_bar(&self) -> u8 {
+        self.numerator
+    }
+
+    //Pattern length that fills exactly one bar, at least one step
+    pub fn steps_per_bar(&self, step_division: u8) -> usize {
+        ((self.quarters_per_bar() * step_division as f64).round() as usize).max(1)
+    }
+
+    //Index of the meter beat a step falls in, counted from the pattern start
+    fn beat_index(&self, step: usize, step_division: u8) -> usize {
+        step * self.denominator as usize / (4 * step_division as usize)
+    }
+
+    pub fn is_beat_start(&self, step: usize, step_division: u8) -> bool {
+        step == 0
+            || self.beat_index(step, step_division) != self.beat_index(step - 1, step_division)
+    }
+
+    pub fn is_bar_start(&self, step: usize, step_division: u8) -> bool {
+        let bar = |s: usize| self.beat_index(s, step_division) / self.numerator as usize;
+        step == 0 || bar(step) != bar(step - 1)
+    }
+
+    //Next meter in the preset list, used for cycling from the keyboard
+    pub fn next_preset(&self) -> Self {
+        let idx = PRESETS.iter().position(|p| p == self);
+        match idx {
+            Some(idx) => PRESETS[(idx + 1) % PRESETS.len()],
+            None => PRESETS[0],
+        }
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        TimeSignature::new(4, 4)
+    }
+}
+
+impl fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseTimeSignatureError;
+
+impl fmt::Display for ParseTimeSignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid time signature string")
+    }
+}
+
+impl FromStr for TimeSignature {
+    type Err = ParseTimeSignatureError;
+
+    //Parses signatures written as "7/8"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (numerator, denominator) = s.trim().split_once('/').ok_or(ParseTimeSignatureError)?;
+
+        let numerator: u8 = numerator
+            .trim()
+            .parse()
+            .map_err(|_| ParseTimeSignatureError)?;
+        let denominator: u8 = denominator
+            .trim()
+            .parse()
+            .map_err(|_| ParseTimeSignatureError)?;
+
+        if !(1..=32).contains(&numerator) || !matches!(denominator, 1 | 2 | 4 | 8 | 16 | 32) {
+            return Err(ParseTimeSignatureError);
+        }
+
+        Ok(TimeSignature::new(numerator, denominator))
+    }
+}
@@ -1,10 +1,24 @@
-use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::layout::{Constraint, Direction, Layout};
 use ratatui::style::{Color, Style};
 use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Widget};
 
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use log::warn;
+use rand::{RngExt, SeedableRng, rngs::SmallRng};
+
 use crate::generators::Instrument;
-use crate::sequencer::Sequencer;
+use crate::instruments::InstrumentKind;
+use crate::keyboard_piano::{KeyboardPiano, PianoAction};
+use crate::note_processors::{
+    Arpeggiator, NoteContext, NoteMessage, NoteProcessor, ScaleQuantiser,
+};
+use crate::notes::Note;
+use crate::oscillator::OscillatorQuality;
+use crate::recorder::{Recorder, Take};
+use crate::sequencer::{Effect, NoteEvent, Sequencer, TICKS_PER_STEP};
+
+//Notes played from the keyboard are released after this long, terminals do not report key releases
+const AUDITION_SECONDS: f32 = 0.3;
 
 //Contains state of the voulume and the sound source, processes all items on the chain
 //endpoint of sound goes to mixer
@@ -15,6 +29,22 @@ pub struct Track {
     sequencer: Sequencer,
     bpm: f32,
     instrument: Option<Box<dyn Instrument + Send>>,
+    instrument_kind: InstrumentKind,
+    note_processors: Vec<Box<dyn NoteProcessor>>,
+    //Reused every sample so the note chain does not allocate on the audio thread
+    note_messages: Vec<NoteMessage>,
+    processed_messages: Vec<NoteMessage>,
+    //Playback of the current step, used for gates and effect commands
+    step_start: f64,
+    last_tick: Option<i64>,
+    gate_end: Option<f64>,
+    rng: SmallRng,
+    piano: KeyboardPiano,
+    //Samples left before an auditioned note is released
+    audition_remaining: usize,
+    recorder: Recorder,
+    //Transport position of the last processed sample, live notes are recorded here
+    position: f64,
 }
 
 impl Track {
@@ -31,13 +61,57 @@ impl Track {
             volume,
             name,
             instrument: None,
-            sequencer: Sequencer::new(bpm, sample_rate, length, step_division),
+            instrument_kind: InstrumentKind::Primitive,
+            sequencer: Sequencer::new(length, step_division),
             bpm,
+            note_processors: Vec::new(),
+            note_messages: Vec::new(),
+            processed_messages: Vec::new(),
+            step_start: 0.0,
+            last_tick: None,
+            gate_end: None,
+            rng: SmallRng::seed_from_u64(0),
+            piano: KeyboardPiano::new(),
+            audition_remaining: 0,
+            recorder: Recorder::new(),
+            position: 0.0,
         }
     }
 
-    pub fn set_instrument(&mut self, instrument: Box<dyn Instrument>) {
+    //Returns the instrument it replaced so it can be dropped somewhere else
+    pub fn set_instrument(
+        &mut self,
+        kind: InstrumentKind,
+        instrument: Box<dyn Instrument>,
+    ) -> Option<Box<dyn Instrument + Send>> {
+        self.instrument_kind = kind;
+        self.instrument.replace(instrument)
+    }
+
+    //Replaces the instrument with a new one of the kind, its settings start from the defaults
+    pub fn set_instrument_kind(&mut self, kind: InstrumentKind, quality: OscillatorQuality) {
+        let mut instrument = kind.build(self.sample_rate);
+        instrument.set_quality(quality);
         self.instrument = Some(instrument);
+        self.instrument_kind = kind;
+    }
+
+    pub fn instrument_kind(&self) -> InstrumentKind {
+        self.instrument_kind
+    }
+
+    pub fn instrument(&self) -> Option<&(dyn Instrument + Send)> {
+        self.instrument.as_deref()
+    }
+
+    pub fn instrument_mut(&mut self) -> Option<&mut (dyn Instrument + Send + 'static)> {
+        self.instrument.as_deref_mut()
+    }
+
+    pub fn set_oscillator_quality(&mut self, quality: OscillatorQuality) {
+        if let Some(instrument) = self.instrument.as_mut() {
+            instrument.set_quality(quality);
+        }
     }
 
     pub fn get_name(&self) -> &str {
@@ -48,18 +122,46 @@ impl Track {
         self.volume
     }
 
-    //Processes a buffer of audio
-    pub fn process_block(&mut self, num_samples: usize) -> Vec<f32> {
+    //Processes a buffer of audio, `start` is the transport position in quarter notes
+    pub fn process_block(
+        &mut self,
+        num_samples: usize,
+        start: f64,
+        quarters_per_sample: f64,
+    ) -> Vec<f32> {
         let mut output = Vec::with_capacity(num_samples);
 
-        for _ in 0..num_samples {
+        for i in 0..num_samples {
+            let position = start + i as f64 * quarters_per_sample;
+
+            self.tick_audition();
+
+            //Sequencer -> note processors -> instrument
+            self.note_messages.clear();
+            self.sequence_notes(position);
+
+            let context = NoteContext {
+                position,
+                bpm: self.bpm,
+                sample_rate: self.sample_rate,
+                key: self.sequencer.key(),
+            };
+
+            for processor in self.note_processors.iter_mut() {
+                self.processed_messages.clear();
+                processor.process(&self.note_messages, &mut self.processed_messages, &context);
+                std::mem::swap(&mut self.note_messages, &mut self.processed_messages);
+            }
+
             if let Some(instrument) = self.instrument.as_mut() {
-                if self.sequencer.process(1) {
-                    if let Some(note) = self.sequencer.get_current_event() {
-                        instrument.note_on(note.frequency);
-                    } else if instrument.get_envelope().is_active() {
-                        //If no note is found trigger the release state
-                        instrument.note_off(); //Note off triggers release state
+                //Monophonic instruments end up on the last note of a chord
+                for message in &self.note_messages {
+                    match message {
+                        NoteMessage::On(note) => instrument.note_on(note.frequency, note.velocity),
+                        NoteMessage::Off if instrument.get_envelope().is_active() => {
+                            instrument.note_off(); //Note off triggers release state
+                        }
+                        NoteMessage::Off => {}
                     }
                 }
 
@@ -73,6 +175,307 @@ impl Track {
         output
     }
 
+    //Runs the instrument without the sequencer while the transport is stopped, so auditioned notes are heard
+    pub fn process_idle(&mut self, num_samples: usize) -> Vec<f32> {
+        let mut output = Vec::with_capacity(num_samples);
+
+        for _ in 0..num_samples {
+            self.tick_audition();
+
+            let sample = match self.instrument.as_mut() {
+                Some(instrument) => instrument.process() * self.volume,
+                None => 0.0,
+            };
+            output.push(sample);
+        }
+
+        output
+    }
+
+    //Plays a note on the instrument right away, it is released after a short time
+    pub fn audition(&mut self, note: Note, velocity: f32) {
+        if let Some(instrument) = self.instrument.as_mut() {
+            instrument.note_on(note.freq(), velocity);
+            self.audition_remaining = (AUDITION_SECONDS * self.sample_rate) as usize;
+        }
+    }
+
+    fn tick_audition(&mut self) {
+        if self.audition_remaining == 0 {
+            return;
+        }
+
+        self.audition_remaining -= 1;
+        if self.audition_remaining == 0 {
+            self.release();
+        }
+    }
+
+    //Lets a sounding note ring out, used when playback stops
+    pub fn release(&mut self) {
+        if let Some(instrument) = self.instrument.as_mut()
+            && instrument.get_envelope().is_active()
+        {
+            instrument.note_off();
+        }
+    }
+
+    //Keeps takes going while live recording, replace mode clears old notes before they play
+    fn record_step_entered(&mut self, position: f64) {
+        if !self.piano.is_live_recording() {
+            self.recorder.stop();
+            return;
+        }
+
+        let len = self.sequencer.pattern_len();
+        let step_count = (position * self.sequencer.step_division() as f64).floor() as i64;
+        self.recorder
+            .enter_step(step_count.div_euclid(len as i64), len, &self.sequencer);
+
+        let step = self.sequencer.current_step();
+        if self.recorder.should_clear(step) {
+            self.sequencer.clear_step(step);
+        }
+    }
+
+    //Writes a note played during live recording at the quantised transport position
+    fn record_live(&mut self, note: Note, velocity: f32) {
+        self.recorder.sync_takes();
+        if !self.recorder.is_taking() {
+            return;
+        }
+
+        let len = self.sequencer.pattern_len() as i64;
+        let (step_count, ticks) = self
+            .recorder
+            .quantise(self.position, self.sequencer.step_division());
+        let step = step_count.rem_euclid(len) as usize;
+
+        let event = NoteEvent {
+            effect: (ticks > 0).then_some(Effect::Wait(ticks)),
+            ..NoteEvent::new(note.freq(), velocity)
+        };
+
+        let mut notes = self.sequencer.get_event(step).to_vec();
+        notes.retain(|other| (other.frequency - event.frequency).abs() > 0.01);
+        notes.push(event);
+        self.sequencer.set_chord_at(step, notes);
+        self.recorder.mark_written(step, step_count.div_euclid(len));
+    }
+
+    //Builds takes from the loop points the audio thread passed, ends the take once recording is off
+    pub fn sync_take(&mut self) {
+        if self.piano.is_live_recording() {
+            self.recorder.sync_takes();
+        } else {
+            self.end_take();
+        }
+    }
+
+    //Stops the running take, called when recording is turned off or playback stops
+    pub fn end_take(&mut self) {
+        self.recorder.sync_takes();
+        if self.recorder.is_taking() {
+            self.recorder.end_take(self.sequencer.snapshot());
+        }
+    }
+
+    //A take that finished since the last call, for the undo history
+    pub fn take_finished(&mut self) -> Option<Take> {
+        self.recorder.take_finished()
+    }
+
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+
+    //True while a live take is running, its notes are undone with the take
+    pub fn is_taking(&self) -> bool {
+        self.recorder.is_taking()
+    }
+
+    pub fn piano(&self) -> &KeyboardPiano {
+        &self.piano
+    }
+
+    //True while the sequencer prompt takes the keys
+    pub fn is_typing(&self) -> bool {
+        self.sequencer.is_editing()
+    }
+
+    //True when the prompt or the keyboard piano uses the key, so it is not a global key
+    pub fn takes_key(&self, key_event: KeyEvent) -> bool {
+        self.is_typing() || self.piano.takes_key(key_event)
+    }
+
+    fn handle_piano_input(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Left => self.sequencer.move_selected_step(-1),
+            KeyCode::Right => self.sequencer.move_selected_step(1),
+            _ => match self.piano.handle_keyboard_input(key_event) {
+                Some(PianoAction::Play(note)) => {
+                    let note = self.sequencer.snap_entry(note);
+                    self.audition(note, 1.0);
+
+                    if self.piano.is_step_recording() {
+                        self.sequencer.record_step(note, 1.0);
+                    } else if self.piano.is_live_recording() {
+                        self.record_live(note, 1.0);
+                    }
+                }
+                Some(PianoAction::Rest) => {
+                    self.sequencer.clear_step(self.sequencer.selected_step());
+                    self.sequencer.move_selected_step(1);
+                }
+                Some(PianoAction::Back) => {
+                    self.sequencer.move_selected_step(-1);
+                    self.sequencer.clear_step(self.sequencer.selected_step());
+                }
+                None => {}
+            },
+        }
+    }
+
+    //Turns the sequencer steps into note messages, applying gates and effect commands
+    fn sequence_notes(&mut self, position: f64) {
+        self.position = position;
+
+        if self.sequencer.process(position) {
+            self.step_start = position;
+            self.last_tick = None;
+            self.record_step_entered(position);
+
+            //If no note is found trigger the release state, a pending gate ends the note itself
+            if self.sequencer.get_current_events().is_empty() && self.gate_end.is_none() {
+                self.note_messages.push(NoteMessage::Off);
+            }
+        }
+
+        if let Some(gate_end) = self.gate_end
+            && position >= gate_end
+        {
+            self.note_messages.push(NoteMessage::Off);
+            self.gate_end = None;
+        }
+
+        let step_length = 1.0 / self.sequencer.step_division() as f64;
+        let tick_length = step_length / TICKS_PER_STEP as f64;
+        let tick = ((position - self.step_start) / tick_length).floor() as i64;
+        if self.last_tick == Some(tick) {
+            return;
+        }
+        self.last_tick = Some(tick);
+
+        for note in self.sequencer.get_current_events() {
+            if !note_plays_on(note, tick, &mut self.rng) {
+                continue;
+            }
+
+            self.note_messages.push(NoteMessage::On(*note));
+
+            if note.gate > 0 {
+                let end = position + note.gate as f64 * tick_length;
+                self.gate_end = Some(self.gate_end.map_or(end, |gate_end| gate_end.max(end)));
+            }
+        }
+    }
+
+    pub fn add_note_processor(&mut self, processor: Box<dyn NoteProcessor>) {
+        self.note_processors.push(processor);
+    }
+
+    //Removes the first processor with the given name, returns false when none was found
+    pub fn remove_note_processor(&mut self, name: &str) -> bool {
+        match self.note_processor_index(name) {
+            Some(idx) => {
+                self.note_processors.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn note_processor_index(&self, name: &str) -> Option<usize> {
+        self.note_processors
+            .iter()
+            .position(|processor| processor.get_name() == name)
+    }
+
+    pub fn note_processors(&self) -> &[Box<dyn NoteProcessor>] {
+        &self.note_processors
+    }
+
+    fn toggle_arpeggiator(&mut self) {
+        if !self.remove_note_processor("Arpeggiator") {
+            self.add_note_processor(Box::new(Arpeggiator::new()));
+        }
+    }
+
+    //The quantiser goes in front so arpeggiated notes stay in key as well
+    fn toggle_scale_quantiser(&mut self) {
+        if !self.remove_note_processor("Scale Quantiser") {
+            self.note_processors
+                .insert(0, Box::new(ScaleQuantiser::new()));
+        }
+    }
+
+    //Quantise strength for live recording from the prompt, in percent or as a fraction
+    fn configure_record_strength(&mut self) {
+        let input = self.sequencer.last_input().trim();
+
+        match input.trim_end_matches('%').parse::<f32>() {
+            Ok(strength) if input.ends_with('%') || strength > 1.0 => {
+                self.recorder.set_strength(strength / 100.0)
+            }
+            Ok(strength) => self.recorder.set_strength(strength),
+            Err(_) => warn!("Unknown quantise strength {}", input),
+        }
+    }
+
+    //Applies the prompt text to the arpeggiator, adding one when the track has none
+    fn configure_arpeggiator(&mut self) {
+        let idx = match self.note_processor_index("Arpeggiator") {
+            Some(idx) => idx,
+            None => {
+                self.add_note_processor(Box::new(Arpeggiator::new()));
+                self.note_processors.len() - 1
+            }
+        };
+
+        let settings = self.sequencer.last_input();
+        if !self.note_processors[idx].configure(settings) {
+            warn!("Unknown arpeggiator settings {}", settings);
+        }
+    }
+
+    //Track level keys for the sequencer window, everything else goes to the sequencer
+    pub fn handle_keyboard_input(&mut self, key_event: KeyEvent) {
+        //Keys the piano does not use are left to the global keys, like play and stop
+        if self.piano.takes_key(key_event) {
+            self.handle_piano_input(key_event);
+            return;
+        }
+        if self.piano.is_enabled() && !key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        self.sequencer.handle_keyboard_input(key_event);
+
+        if self.sequencer.is_editing() || key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        match key_event.code {
+            KeyCode::Char('a') => self.toggle_arpeggiator(),
+            KeyCode::Char('A') => self.configure_arpeggiator(),
+            KeyCode::Char('P') => self.toggle_scale_quantiser(),
+            KeyCode::Char('p') => self.piano.toggle(),
+            KeyCode::Char('l') => self.recorder.toggle_mode(),
+            KeyCode::Char('L') => self.configure_record_strength(),
+            _ => {}
+        }
+    }
+
     pub fn sequencer_mut(&mut self) -> &mut Sequencer {
         &mut self.sequencer
     }
@@ -89,6 +492,10 @@ impl Track {
         self.volume += amount;
     }
 
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.max(0.0);
+    }
+
     pub fn sequencer(&self) -> &Sequencer {
         &self.sequencer
     }
@@ -97,9 +504,26 @@ impl Track {
         self.bpm = bpm;
     }
 
+    //Rewinds the sequencer to the first step and puts the instrument and note chances back
+    //to how they started, so playing from the top sounds the same every time
+    pub fn reset(&mut self) {
+        self.sequencer.reset();
+        self.step_start = 0.0;
+        self.last_tick = None;
+        self.gate_end = None;
+        self.rng = SmallRng::seed_from_u64(0);
+        self.audition_remaining = 0;
+        if let Some(instrument) = self.instrument.as_mut() {
+            instrument.reset();
+        }
+    }
+
+    pub fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
     pub fn set_sample_rate(&mut self, sample_rate: f32) {
         self.sample_rate = sample_rate;
-        self.sequencer.set_sample_rate(sample_rate)
     }
 
     pub fn rename(&mut self, new_name: String) {
@@ -107,7 +531,17 @@ impl Track {
     }
 }
 
-//TODO: implement colums for the sequencer in a tracker style way
+//Whether a note starts on a tick of its step, chances are rolled when the note is due
+fn note_plays_on(note: &NoteEvent, tick: i64, rng: &mut SmallRng) -> bool {
+    match note.effect {
+        None => tick == 0,
+        Some(Effect::Wait(ticks)) => tick == ticks.min(TICKS_PER_STEP - 1) as i64,
+        Some(Effect::Retrigger(0)) => tick == 0,
+        Some(Effect::Retrigger(ticks)) => tick % ticks as i64 == 0,
+        Some(Effect::Chance(chance)) => tick == 0 && rng.random_range(0..255) < chance as u32,
+    }
+}
+
 impl Widget for &Track {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
     where
@@ -152,3 +586,76 @@ impl Widget for &Track {
             .render(layout[1], buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piano_roll::PianoRollView;
+
+    const STEP_DIVISION: u8 = 4;
+
+    fn gated_track(gate: u8) -> Track {
+        let mut track = Track::new(1.0, "Test".to_string(), 48000.0, 120.0, 4, STEP_DIVISION);
+        let note = NoteEvent {
+            gate,
+            ..NoteEvent::new(Note::C4.freq(), 1.0)
+        };
+        track.sequencer.set_chord_at(0, vec![note]);
+        track
+    }
+
+    //Positions in steps where the track sent Off, stepping a quarter of a tick at a time
+    fn off_steps(track: &mut Track, steps: usize) -> Vec<f64> {
+        let resolution = (TICKS_PER_STEP as usize * 4) as f64;
+        let mut offs = Vec::new();
+
+        for i in 0..steps * resolution as usize {
+            let step = i as f64 / resolution;
+            track.note_messages.clear();
+            track.sequence_notes(step / STEP_DIVISION as f64);
+
+            let off = track
+                .note_messages
+                .iter()
+                .any(|message| matches!(message, NoteMessage::Off));
+            if off {
+                offs.push(step);
+            }
+        }
+
+        offs
+    }
+
+    #[test]
+    fn gate_holds_over_empty_steps() {
+        let mut track = gated_track(3 * TICKS_PER_STEP);
+        assert_eq!(off_steps(&mut track, 4), vec![3.0]);
+    }
+
+    #[test]
+    fn empty_step_releases_note_without_gate() {
+        let mut track = gated_track(0);
+        assert_eq!(off_steps(&mut track, 3), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn resized_piano_roll_note_holds_for_drawn_length() {
+        let mut track = Track::new(1.0, "Test".to_string(), 48000.0, 120.0, 8, STEP_DIVISION);
+        let mut piano_roll = PianoRollView::new();
+        let press = |code, modifiers| KeyEvent::new(code, modifiers);
+
+        piano_roll.handle_keyboard_input(
+            press(KeyCode::Enter, KeyModifiers::NONE),
+            &mut track.sequencer,
+        );
+        for _ in 0..3 {
+            piano_roll.handle_keyboard_input(
+                press(KeyCode::Right, KeyModifiers::SHIFT),
+                &mut track.sequencer,
+            );
+        }
+
+        assert_eq!(piano_roll.length(), 4);
+        assert_eq!(off_steps(&mut track, 6).first(), Some(&4.0));
+    }
+}
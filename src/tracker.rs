@@ -0,0 +1,447 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::Widget,
+};
+
+use crate::{
+    notes::{freq_to_midi, midi_to_freq, pitch_class_name},
+    sequencer::{Effect, NoteEvent, Sequencer},
+};
+
+//Velocity of notes typed into an empty row
+const DEFAULT_VELOCITY: u8 = 0xC0;
+//Rows jumped by page up and down
+const PAGE_ROWS: i64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackerColumn {
+    Note,
+    Velocity,
+    Gate,
+    Effect,
+}
+
+impl TrackerColumn {
+    fn next(&self) -> Self {
+        match self {
+            TrackerColumn::Note => TrackerColumn::Velocity,
+            TrackerColumn::Velocity => TrackerColumn::Gate,
+            TrackerColumn::Gate => TrackerColumn::Effect,
+            TrackerColumn::Effect => TrackerColumn::Effect,
+        }
+    }
+
+    fn previous(&self) -> Self {
+        match self {
+            TrackerColumn::Note => TrackerColumn::Note,
+            TrackerColumn::Velocity => TrackerColumn::Note,
+            TrackerColumn::Gate => TrackerColumn::Velocity,
+            TrackerColumn::Effect => TrackerColumn::Gate,
+        }
+    }
+}
+
+//Vertical view of the selected pattern, one row per step.
+//The row cursor is the sequencer cursor, so both windows edit the same step
+pub struct TrackerView {
+    column: TrackerColumn,
+    editing: bool,
+    hex: bool,
+    //Keep the playing step in view instead of the cursor
+    follow: bool,
+}
+
+impl TrackerView {
+    pub fn new() -> Self {
+        TrackerView {
+            column: TrackerColumn::Note,
+            editing: false,
+            hex: true,
+            follow: true,
+        }
+    }
+
+    //While editing every key goes to the tracker, global keys are ignored
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    pub fn is_hex(&self) -> bool {
+        self.hex
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow
+    }
+
+    pub fn handle_keyboard_input(&mut self, key_event: KeyEvent, sequencer: &mut Sequencer) {
+        if key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            return;
+        }
+
+        match key_event.code {
+            KeyCode::Up => sequencer.move_selected_step(-1),
+            KeyCode::Down => sequencer.move_selected_step(1),
+            KeyCode::PageUp => sequencer.move_selected_step(-PAGE_ROWS),
+            KeyCode::PageDown => sequencer.move_selected_step(PAGE_ROWS),
+            KeyCode::Left => self.column = self.column.previous(),
+            KeyCode::Right => self.column = self.column.next(),
+            KeyCode::Delete | KeyCode::Backspace => self.clear_cell(sequencer),
+            KeyCode::Enter => self.editing = !self.editing,
+            KeyCode::Esc => self.editing = false,
+            KeyCode::Char(c) if self.editing => self.edit_cell(c, sequencer),
+            KeyCode::Char('h') => self.hex = !self.hex,
+            KeyCode::Char('f') => self.follow = !self.follow,
+            _ => {}
+        }
+    }
+
+    fn edit_cell(&mut self, c: char, sequencer: &mut Sequencer) {
+        let step = sequencer.selected_step();
+        let mut notes = sequencer.get_event(step).to_vec();
+
+        if let Some(digit) = c.to_digit(if self.hex { 16 } else { 10 }) {
+            let value = self.cell_value(&notes).unwrap_or(0);
+            let value = self.push_digit(value, digit);
+            self.set_cell_value(&mut notes, value);
+        } else {
+            match (c, self.column) {
+                ('+', _) => self.adjust_cell(&mut notes, 1),
+                ('-', _) => self.adjust_cell(&mut notes, -1),
+                ('.', _) => {
+                    self.clear_cell(sequencer);
+                    return;
+                }
+                (command, TrackerColumn::Effect) => {
+                    let parameter = notes
+                        .first()
+                        .and_then(|note| note.effect)
+                        .map(|effect| effect.parameter())
+                        .unwrap_or(0);
+                    let Some(effect) = Effect::from_command(command, parameter) else {
+                        return;
+                    };
+                    notes.iter_mut().for_each(|note| note.effect = Some(effect));
+                }
+                _ => return,
+            }
+        }
+
+        sequencer.set_chord_at(step, notes);
+    }
+
+    //Typed digits shift in from the right like in most trackers
+    fn push_digit(&self, value: u8, digit: u32) -> u8 {
+        let value = if self.hex {
+            (value as u32 * 16 + digit) % 0x100
+        } else {
+            (value as u32 * 10 + digit) % 1000
+        };
+
+        value.min(self.column_max() as u32) as u8
+    }
+
+    fn adjust_cell(&self, notes: &mut Vec<NoteEvent>, amount: i32) {
+        let value = self.cell_value(notes).unwrap_or(0) as i32 + amount;
+        self.set_cell_value(notes, value.clamp(0, self.column_max() as i32) as u8);
+    }
+
+    fn column_max(&self) -> u8 {
+        match self.column {
+            TrackerColumn::Note => 127,
+            _ => u8::MAX,
+        }
+    }
+
+    //Value shown in the column for a row, None when the cell is empty
+    fn cell_value(&self, notes: &[NoteEvent]) -> Option<u8> {
+        let note = notes.first()?;
+
+        match self.column {
+            TrackerColumn::Note => Some(note_midi(note)),
+            TrackerColumn::Velocity => Some(velocity_value(note)),
+            TrackerColumn::Gate => Some(note.gate),
+            TrackerColumn::Effect => note.effect.map(|effect| effect.parameter()),
+        }
+    }
+
+    //Edits apply to every note of a chord, a new note changes the pitch of the whole chord
+    fn set_cell_value(&self, notes: &mut Vec<NoteEvent>, value: u8) {
+        if notes.is_empty() {
+            if self.column != TrackerColumn::Note {
+                return;
+            }
+
+            let velocity = DEFAULT_VELOCITY as f32 / u8::MAX as f32;
+            notes.push(NoteEvent::new(midi_to_freq(value as f32), velocity));
+            return;
+        }
+
+        match self.column {
+            TrackerColumn::Note => {
+                let ratio = midi_to_freq(value as f32) / midi_to_freq(note_midi(&notes[0]) as f32);
+                notes.iter_mut().for_each(|note| note.frequency *= ratio);
+            }
+            TrackerColumn::Velocity => notes
+                .iter_mut()
+                .for_each(|note| note.velocity = value as f32 / u8::MAX as f32),
+            TrackerColumn::Gate => notes.iter_mut().for_each(|note| note.gate = value),
+            TrackerColumn::Effect => notes.iter_mut().for_each(|note| {
+                note.effect = note.effect.map(|effect| effect.with_parameter(value))
+            }),
+        }
+    }
+
+    //Clearing the note column clears the row, other columns go back to their default
+    fn clear_cell(&self, sequencer: &mut Sequencer) {
+        let step = sequencer.selected_step();
+        let mut notes = sequencer.get_event(step).to_vec();
+
+        match self.column {
+            TrackerColumn::Note => notes.clear(),
+            TrackerColumn::Velocity => notes.iter_mut().for_each(|note| note.velocity = 1.0),
+            TrackerColumn::Gate => notes.iter_mut().for_each(|note| note.gate = 0),
+            TrackerColumn::Effect => notes.iter_mut().for_each(|note| note.effect = None),
+        }
+
+        sequencer.set_chord_at(step, notes);
+    }
+
+    fn format_value(&self, value: u8) -> String {
+        if self.hex {
+            format!("{:02X}", value)
+        } else {
+            format!("{:03}", value)
+        }
+    }
+
+    fn empty_value(&self) -> &'static str {
+        if self.hex { ".." } else { "..." }
+    }
+
+    fn format_row(&self, step: usize, notes: &[NoteEvent]) -> [String; 5] {
+        let step = if self.hex {
+            format!("{:02X}", step)
+        } else {
+            format!("{:03}", step)
+        };
+
+        let Some(note) = notes.first() else {
+            return [
+                step,
+                "--- ".to_string(),
+                self.empty_value().to_string(),
+                self.empty_value().to_string(),
+                format!(".{}", self.empty_value()),
+            ];
+        };
+
+        let midi = note_midi(note);
+        //Chords are marked with a + after the lowest note
+        let chord = if notes.len() > 1 { "+" } else { "" };
+        let name = format!(
+            "{:<4}",
+            format!(
+                "{:-<2}{}{}",
+                pitch_class_name(midi % 12),
+                (midi / 12).saturating_sub(1),
+                chord
+            )
+        );
+        let gate = match note.gate {
+            0 => self.empty_value().to_string(),
+            gate => self.format_value(gate),
+        };
+        let effect = match note.effect {
+            Some(effect) => format!(
+                "{}{}",
+                effect.command(),
+                self.format_value(effect.parameter())
+            ),
+            None => format!(".{}", self.empty_value()),
+        };
+
+        [
+            step,
+            name,
+            self.format_value(velocity_value(note)),
+            gate,
+            effect,
+        ]
+    }
+}
+
+impl Default for TrackerView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn note_midi(note: &NoteEvent) -> u8 {
+    freq_to_midi(note.frequency).round().clamp(0.0, 127.0) as u8
+}
+
+fn velocity_value(note: &NoteEvent) -> u8 {
+    (note.velocity * u8::MAX as f32).round().clamp(0.0, 255.0) as u8
+}
+
+//Tracker view paired with the pattern it shows, built for every frame
+pub struct Tracker<'a> {
+    pub view: &'a TrackerView,
+    pub sequencer: &'a Sequencer,
+}
+
+impl Widget for Tracker<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let rows = area.height as usize;
+        let len = self.sequencer.pattern_len();
+        if rows == 0 {
+            return;
+        }
+
+        //Keep the focused row in the middle of the view
+        let focus = if self.view.follow {
+            self.sequencer.current_step()
+        } else {
+            self.sequencer.selected_step()
+        };
+        let first = focus.saturating_sub(rows / 2).min(len.saturating_sub(rows));
+
+        let columns = [
+            TrackerColumn::Note,
+            TrackerColumn::Velocity,
+            TrackerColumn::Gate,
+            TrackerColumn::Effect,
+        ];
+
+        for (row, step) in (first..len.min(first + rows)).enumerate() {
+            let y = area.y + row as u16;
+            let cells = self.view.format_row(step, self.sequencer.get_event(step));
+            let is_cursor = step == self.sequencer.selected_step();
+
+            let row_style = if step == self.sequencer.current_step() {
+                Style::default().fg(Color::Yellow)
+            } else if self
+                .sequencer
+                .time_signature()
+                .is_beat_start(step, self.sequencer.step_division())
+            {
+                Style::default().fg(Color::Gray)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            let row_style = if is_cursor {
+                row_style.bg(Color::DarkGray).fg(Color::White)
+            } else {
+                row_style
+            };
+
+            let mut x = area.x;
+            for (idx, cell) in cells.iter().enumerate() {
+                let width = cell.len() as u16 + 1;
+                if x + width > area.x + area.width {
+                    break;
+                }
+
+                let style = match idx.checked_sub(1).map(|column| columns[column]) {
+                    Some(column) if is_cursor && column == self.view.column => {
+                        if self.view.editing {
+                            Style::default().bg(Color::Green).fg(Color::Black)
+                        } else {
+                            Style::default().fg(Color::Green).bg(Color::DarkGray)
+                        }
+                    }
+                    _ => row_style,
+                };
+
+                buf.set_string(x, y, cell, style);
+                x += width;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    //Tracker editing the given column of a one note pattern
+    fn tracker(column: TrackerColumn, hex: bool) -> (TrackerView, Sequencer) {
+        let mut sequencer = Sequencer::new(4, 4);
+        sequencer.set_note_at(0, midi_to_freq(60.0), 1.0);
+        let view = TrackerView {
+            column,
+            editing: true,
+            hex,
+            ..TrackerView::new()
+        };
+        (view, sequencer)
+    }
+
+    fn type_keys(view: &mut TrackerView, sequencer: &mut Sequencer, keys: &str) {
+        for c in keys.chars() {
+            view.handle_keyboard_input(press(c), sequencer);
+        }
+    }
+
+    fn cell(view: &TrackerView, sequencer: &Sequencer) -> Option<u8> {
+        view.cell_value(sequencer.get_event(0))
+    }
+
+    #[test]
+    fn digits_shift_in_from_the_right() {
+        let (mut view, mut sequencer) = tracker(TrackerColumn::Velocity, true);
+        type_keys(&mut view, &mut sequencer, "12");
+        assert_eq!(cell(&view, &sequencer), Some(0x12));
+        type_keys(&mut view, &mut sequencer, "a");
+        assert_eq!(cell(&view, &sequencer), Some(0x2A));
+
+        let (mut view, mut sequencer) = tracker(TrackerColumn::Gate, false);
+        type_keys(&mut view, &mut sequencer, "123");
+        assert_eq!(cell(&view, &sequencer), Some(123));
+        type_keys(&mut view, &mut sequencer, "4");
+        assert_eq!(cell(&view, &sequencer), Some(234));
+        //Hex digits are not digits in decimal mode
+        type_keys(&mut view, &mut sequencer, "a");
+        assert_eq!(cell(&view, &sequencer), Some(234));
+    }
+
+    #[test]
+    fn note_column_stops_at_127() {
+        let (mut view, mut sequencer) = tracker(TrackerColumn::Note, false);
+        type_keys(&mut view, &mut sequencer, "199");
+        assert_eq!(cell(&view, &sequencer), Some(127));
+        type_keys(&mut view, &mut sequencer, "+");
+        assert_eq!(cell(&view, &sequencer), Some(127));
+
+        let (mut view, mut sequencer) = tracker(TrackerColumn::Note, true);
+        type_keys(&mut view, &mut sequencer, "ff");
+        assert_eq!(cell(&view, &sequencer), Some(127));
+        assert_eq!(view.format_row(0, sequencer.get_event(0))[1], "G-9 ");
+    }
+
+    #[test]
+    fn effect_commands_keep_their_parameter() {
+        let (mut view, mut sequencer) = tracker(TrackerColumn::Effect, true);
+        //A parameter needs a command first
+        type_keys(&mut view, &mut sequencer, "4");
+        assert_eq!(sequencer.get_event(0)[0].effect, None);
+
+        type_keys(&mut view, &mut sequencer, "r4");
+        assert_eq!(sequencer.get_event(0)[0].effect, Some(Effect::Retrigger(4)));
+        type_keys(&mut view, &mut sequencer, "p");
+        assert_eq!(sequencer.get_event(0)[0].effect, Some(Effect::Chance(4)));
+        type_keys(&mut view, &mut sequencer, "x");
+        assert_eq!(sequencer.get_event(0)[0].effect, Some(Effect::Chance(4)));
+    }
+}
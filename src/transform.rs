@@ -0,0 +1,230 @@
+use std::{fmt, str::FromStr};
+
+use rand::RngExt;
+
+use crate::{
+    notes::{Note, freq_to_midi, midi_to_freq},
+    sequencer::NoteEvent,
+};
+
+const MIN_VELOCITY: f32 = 0.05;
+
+//Bulk edits on a range of sequencer steps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    Transpose(i32),
+    Reverse,
+    //Positive moves the steps to the right, wrapping around the range
+    Rotate(i32),
+    //Mirrors every note around a pitch
+    Invert(Note),
+    //Stretches the steps to twice their spacing
+    Double,
+    //Squeezes the steps to half their spacing, odd steps are dropped
+    Halve,
+    //Random velocity offset of at most this amount
+    Humanise(f32),
+}
+
+impl Transform {
+    pub fn apply(&self, steps: &mut [Vec<NoteEvent>]) {
+        match *self {
+            Transform::Transpose(semitones) => {
+                let ratio = 2f32.powf(semitones as f32 / 12.0);
+                for note in steps.iter_mut().flatten() {
+                    note.frequency *= ratio;
+                }
+            }
+            Transform::Reverse => steps.reverse(),
+            Transform::Rotate(amount) => {
+                if !steps.is_empty() {
+                    let amount = amount.rem_euclid(steps.len() as i32) as usize;
+                    steps.rotate_right(amount);
+                }
+            }
+            Transform::Invert(pivot) => {
+                let pivot = pivot.midi() as f32;
+                for note in steps.iter_mut().flatten() {
+                    let midi = freq_to_midi(note.frequency);
+                    note.frequency = midi_to_freq(2.0 * pivot - midi);
+                }
+            }
+            Transform::Double => {
+                let stretched = double(steps);
+                for (slot, notes) in steps.iter_mut().zip(stretched) {
+                    *slot = notes;
+                }
+            }
+            Transform::Halve => {
+                let squeezed = halve(steps);
+                for (idx, slot) in steps.iter_mut().enumerate() {
+                    *slot = squeezed.get(idx).cloned().unwrap_or_default();
+                }
+            }
+            Transform::Humanise(amount) => {
+                let mut rng = rand::rng();
+                for note in steps.iter_mut().flatten() {
+                    let offset = rng.random_range(-amount..=amount);
+                    note.velocity = (note.velocity + offset).clamp(MIN_VELOCITY, 1.0);
+                }
+            }
+        }
+    }
+}
+
+//Steps spread to twice their spacing, the result is twice as long
+pub fn double(steps: &[Vec<NoteEvent>]) -> Vec<Vec<NoteEvent>> {
+    steps
+        .iter()
+        .flat_map(|notes| [notes.clone(), Vec::new()])
+        .collect()
+}
+
+//Every other step, the result is half as long rounded up
+pub fn halve(steps: &[Vec<NoteEvent>]) -> Vec<Vec<NoteEvent>> {
+    steps.iter().step_by(2).cloned().collect()
+}
+
+#[derive(Debug)]
+pub struct ParseTransformError;
+
+impl fmt::Display for ParseTransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid transform string")
+    }
+}
+
+impl FromStr for Transform {
+    type Err = ParseTransformError;
+
+    //Parses commands like "transpose -3", "octave 1", "rotate 2", "invert C4" or "humanise 0.2"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let command = parts.next().ok_or(ParseTransformError)?;
+        let argument = parts.next();
+
+        let number = |default: i32| match argument {
+            Some(argument) => argument.parse::<i32>().map_err(|_| ParseTransformError),
+            None => Ok(default),
+        };
+
+        let transform = match command {
+            "transpose" | "tr" => Transform::Transpose(number(1)?),
+            "octave" | "oct" => Transform::Transpose(number(1)? * 12),
+            "reverse" | "rev" => Transform::Reverse,
+            "rotate" | "rot" => Transform::Rotate(number(1)?),
+            "invert" | "inv" => {
+                let pivot = argument
+                    .ok_or(ParseTransformError)?
+                    .parse::<Note>()
+                    .map_err(|_| ParseTransformError)?;
+                Transform::Invert(pivot)
+            }
+            "double" => Transform::Double,
+            "halve" => Transform::Halve,
+            "humanise" | "humanize" => {
+                let amount = match argument {
+                    Some(argument) => argument.parse::<f32>().map_err(|_| ParseTransformError)?,
+                    None => 0.1,
+                };
+                Transform::Humanise(amount.clamp(0.0, 1.0))
+            }
+            _ => return Err(ParseTransformError),
+        };
+
+        Ok(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //One note per step from MIDI numbers, None is an empty step
+    fn steps(midi: &[Option<u8>]) -> Vec<Vec<NoteEvent>> {
+        midi.iter()
+            .map(|midi| {
+                midi.map(|midi| vec![NoteEvent::new(midi_to_freq(midi as f32), 1.0)])
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    fn midi(steps: &[Vec<NoteEvent>]) -> Vec<Option<u8>> {
+        steps
+            .iter()
+            .map(|notes| {
+                notes
+                    .first()
+                    .map(|note| freq_to_midi(note.frequency).round() as u8)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_commands() {
+        let parse = |s: &str| s.parse::<Transform>().unwrap();
+        assert_eq!(parse("transpose -3"), Transform::Transpose(-3));
+        assert_eq!(parse("tr"), Transform::Transpose(1));
+        assert_eq!(parse("octave -1"), Transform::Transpose(-12));
+        assert_eq!(parse("rev"), Transform::Reverse);
+        assert_eq!(parse("rotate 2"), Transform::Rotate(2));
+        assert_eq!(parse("invert C4"), Transform::Invert(Note::C4));
+        assert_eq!(parse("double"), Transform::Double);
+        assert_eq!(parse("halve"), Transform::Halve);
+        assert_eq!(parse("humanise"), Transform::Humanise(0.1));
+        assert_eq!(parse("humanize 5"), Transform::Humanise(1.0));
+    }
+
+    #[test]
+    fn rejects_invalid_commands() {
+        for input in [
+            "",
+            "wiggle",
+            "transpose x",
+            "invert",
+            "invert H2",
+            "humanise lots",
+        ] {
+            assert!(input.parse::<Transform>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn transpose_invert_and_reorder() {
+        let mut pattern = steps(&[Some(60), None, Some(64), Some(67)]);
+
+        Transform::Transpose(12).apply(&mut pattern);
+        assert_eq!(midi(&pattern), [Some(72), None, Some(76), Some(79)]);
+
+        Transform::Invert(Note::C5).apply(&mut pattern);
+        assert_eq!(midi(&pattern), [Some(72), None, Some(68), Some(65)]);
+
+        Transform::Reverse.apply(&mut pattern);
+        assert_eq!(midi(&pattern), [Some(65), Some(68), None, Some(72)]);
+
+        Transform::Rotate(-1).apply(&mut pattern);
+        assert_eq!(midi(&pattern), [Some(68), None, Some(72), Some(65)]);
+    }
+
+    #[test]
+    fn double_and_halve_in_place_keep_length() {
+        let mut pattern = steps(&[Some(60), Some(62), Some(64), Some(65)]);
+
+        Transform::Double.apply(&mut pattern);
+        assert_eq!(midi(&pattern), [Some(60), None, Some(62), None]);
+
+        Transform::Halve.apply(&mut pattern);
+        assert_eq!(midi(&pattern), [Some(60), Some(62), None, None]);
+    }
+
+    #[test]
+    fn humanise_stays_in_range() {
+        let mut pattern = steps(&[Some(60); 64]);
+        Transform::Humanise(1.0).apply(&mut pattern);
+
+        for note in pattern.iter().flatten() {
+            assert!((MIN_VELOCITY..=1.0).contains(&note.velocity));
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::{error::Error, f32::consts::PI};
+
+use log::warn;
+
+use crate::{fft::fft, sample::Sample};
+
+//Samples in one cycle of a table, also the frame size of wavetable files
+pub const TABLE_SIZE: usize = 2048;
+//Level n holds the first 2^n harmonics, the last level is the full table
+const LEVELS: usize = 11;
+//Most frames read from a file, the same limit as common wavetable synths
+pub const MAX_FRAMES: usize = 256;
+//Frames of the table used before a file is loaded
+const DEFAULT_FRAMES: usize = 8;
+
+//How samples between table entries are read
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Cubic,
+}
+
+pub const INTERPOLATION_NAMES: [&str; 2] = ["Linear", "Cubic"];
+
+//One cycle at several bandwidths, the level played is picked from the note frequency
+//so no harmonic passes Nyquist
+pub struct MipmapTable {
+    levels: Vec<Vec<f32>>,
+}
+
+impl MipmapTable {
+    //Builds a table from the sine and cosine amplitude of every harmonic, starting at 1
+    pub fn from_harmonics(harmonic: impl Fn(usize) -> (f32, f32)) -> Self {
+        let mut re = vec![0.0; TABLE_SIZE];
+        let mut im = vec![0.0; TABLE_SIZE];
+
+        for k in 1..TABLE_SIZE / 2 {
+            let (sine, cosine) = harmonic(k);
+            re[k] = cosine / 2.0;
+            im[k] = -sine / 2.0;
+        }
+
+        Self::from_spectrum(&re, &im)
+    }
+
+    //Builds a table from one cycle of audio, resampled to the table size when needed
+    pub fn from_frame(frame: &[f32]) -> Self {
+        let mut re: Vec<f32> = (0..TABLE_SIZE)
+            .map(|i| sample_linear(frame, i as f32 / TABLE_SIZE as f32))
+            .collect();
+        let mut im = vec![0.0; TABLE_SIZE];
+        fft(&mut re, &mut im, false);
+
+        for (re, im) in re.iter_mut().zip(im.iter_mut()) {
+            *re /= TABLE_SIZE as f32;
+            *im /= TABLE_SIZE as f32;
+        }
+
+        Self::from_spectrum(&re, &im)
+    }
+
+    //Positive frequency bins scaled so the inverse transform gives the signal,
+    //DC is dropped so tables stay centered
+    fn from_spectrum(re: &[f32], im: &[f32]) -> Self {
+        let levels = (0..LEVELS)
+            .map(|level| {
+                let harmonics = (1usize << level).min(TABLE_SIZE / 2 - 1);
+                let mut level_re = vec![0.0; TABLE_SIZE];
+                let mut level_im = vec![0.0; TABLE_SIZE];
+
+                for k in 1..=harmonics {
+                    level_re[k] = re[k];
+                    level_im[k] = im[k];
+                    level_re[TABLE_SIZE - k] = re[k];
+                    level_im[TABLE_SIZE - k] = -im[k];
+                }
+
+                fft(&mut level_re, &mut level_im, true);
+                level_re
+            })
+            .collect();
+
+        MipmapTable { levels }
+    }
+
+    //Reads the table at a phase from 0 to 1 using the level that fits below Nyquist
+    pub fn sample(&self, phase: f32, frequency: f32, sample_rate: f32) -> f32 {
+        sample_linear(&self.levels[self.level(frequency, sample_rate)], phase)
+    }
+
+    pub fn read(
+        &self,
+        phase: f32,
+        frequency: f32,
+        sample_rate: f32,
+        interpolation: Interpolation,
+    ) -> f32 {
+        let cycle = &self.levels[self.level(frequency, sample_rate)];
+        match interpolation {
+            Interpolation::Linear => sample_linear(cycle, phase),
+            Interpolation::Cubic => sample_cubic(cycle, phase),
+        }
+    }
+
+    fn level(&self, frequency: f32, sample_rate: f32) -> usize {
+        let harmonics = (sample_rate / 2.0 / frequency.max(1.0)).max(1.0);
+        (harmonics.log2().floor() as usize).min(LEVELS - 1)
+    }
+}
+
+//Linear interpolation of a single cycle at a phase from 0 to 1
+pub fn sample_linear(cycle: &[f32], phase: f32) -> f32 {
+    let position = phase.rem_euclid(1.0) * cycle.len() as f32;
+    let index = position as usize % cycle.len();
+    let next = (index + 1) % cycle.len();
+    let fraction = position - position.floor();
+
+    cycle[index] + (cycle[next] - cycle[index]) * fraction
+}
+
+//Four point Hermite interpolation of a single cycle at a phase from 0 to 1
+pub fn sample_cubic(cycle: &[f32], phase: f32) -> f32 {
+    let len = cycle.len();
+    let position = phase.rem_euclid(1.0) * len as f32;
+    let index = position as usize % len;
+    let t = position - position.floor();
+
+    let y0 = cycle[(index + len - 1) % len];
+    let y1 = cycle[index];
+    let y2 = cycle[(index + 1) % len];
+    let y3 = cycle[(index + 2) % len];
+
+    let c1 = 0.5 * (y2 - y0);
+    let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+    let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+
+    ((c3 * t + c2) * t + c1) * t + y1
+}
+
+//A sequence of single cycle frames, the position morphs between neighbouring frames
+pub struct Wavetable {
+    frames: Vec<MipmapTable>,
+}
+
+impl Wavetable {
+    //Saw waves with more harmonics in every frame, a sine at the start and a full saw at the end
+    pub fn new() -> Self {
+        let frames = (0..DEFAULT_FRAMES)
+            .map(|frame| {
+                let harmonics = 1 << (frame * 7 / (DEFAULT_FRAMES - 1));
+                MipmapTable::from_harmonics(|k| {
+                    if k <= harmonics {
+                        (2.0 / (PI * k as f32), 0.0)
+                    } else {
+                        (0.0, 0.0)
+                    }
+                })
+            })
+            .collect();
+
+        Wavetable { frames }
+    }
+
+    //Splits audio into frames of TABLE_SIZE samples, audio shorter than a frame is one cycle.
+    //A part frame at the end is padded with silence
+    pub fn from_sample(sample: &Sample) -> Result<Self, Box<dyn Error>> {
+        if sample.is_empty() {
+            return Err("the file has no audio".into());
+        }
+
+        if sample.len() < TABLE_SIZE {
+            return Ok(Wavetable {
+                frames: vec![MipmapTable::from_frame(sample.data())],
+            });
+        }
+
+        let count = sample.len().div_ceil(TABLE_SIZE);
+        let remainder = sample.len() % TABLE_SIZE;
+        if count > MAX_FRAMES {
+            warn!(
+                "Wavetable has {} frames, only the first {} are used",
+                count, MAX_FRAMES
+            );
+        } else if remainder != 0 {
+            warn!(
+                "Wavetable ends {} samples into its last frame, the rest is padded with silence",
+                remainder
+            );
+        }
+
+        let frames = sample
+            .data()
+            .chunks(TABLE_SIZE)
+            .take(MAX_FRAMES)
+            .map(|chunk| {
+                let mut frame = [0.0; TABLE_SIZE];
+                frame[..chunk.len()].copy_from_slice(chunk);
+                MipmapTable::from_frame(&frame)
+            })
+            .collect();
+
+        Ok(Wavetable { frames })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    //Position runs from 0 at the first frame to 1 at the last
+    pub fn read(
+        &self,
+        position: f32,
+        phase: f32,
+        frequency: f32,
+        sample_rate: f32,
+        interpolation: Interpolation,
+    ) -> f32 {
+        let last = self.frames.len().saturating_sub(1);
+        let frame = position.clamp(0.0, 1.0) * last as f32;
+        let index = (frame as usize).min(last);
+        let fraction = frame - index as f32;
+
+        let current = self.frames[index].read(phase, frequency, sample_rate, interpolation);
+        if fraction == 0.0 || index == last {
+            return current;
+        }
+
+        let next = self.frames[index + 1].read(phase, frequency, sample_rate, interpolation);
+        current + (next - current) * fraction
+    }
+}
+
+impl Default for Wavetable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(len: usize) -> Result<Wavetable, Box<dyn Error>> {
+        let data = (0..len)
+            .map(|i| (2.0 * PI * i as f32 / TABLE_SIZE as f32).sin())
+            .collect();
+        Wavetable::from_sample(&Sample::new(data, 48000.0))
+    }
+
+    #[test]
+    fn splits_files_into_frames() {
+        assert_eq!(table(TABLE_SIZE * 3).unwrap().len(), 3);
+        assert_eq!(table(100).unwrap().len(), 1);
+        assert_eq!(
+            table(TABLE_SIZE * (MAX_FRAMES + 2)).unwrap().len(),
+            MAX_FRAMES
+        );
+    }
+
+    #[test]
+    fn pads_the_last_part_frame() {
+        let table = table(TABLE_SIZE * 2 + TABLE_SIZE / 4).unwrap();
+        assert_eq!(table.len(), 3);
+
+        //The tail holds the first quarter of the cycle and silence after it
+        let last = &table.frames[2];
+        assert!((last.sample(0.125, 1.0, 48000.0) - last.sample(0.375, 1.0, 48000.0)).abs() > 0.5);
+        assert!((last.sample(0.5, 1.0, 48000.0) - last.sample(0.75, 1.0, 48000.0)).abs() < 0.05);
+    }
+
+    #[test]
+    fn reports_empty_files() {
+        let error = table(0).err().unwrap();
+        assert_eq!(error.to_string(), "the file has no audio");
+    }
+}
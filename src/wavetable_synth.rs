@@ -0,0 +1,117 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    generators::{Envelope, Instrument, Processor},
+    parameter::Parameter,
+    sample::Sample,
+    wavetable::{INTERPOLATION_NAMES, Interpolation, Wavetable},
+};
+
+//Plays a wavetable, the table position morphs between its frames
+pub struct WavetableSynth {
+    name: String,
+    file: Option<PathBuf>,
+    table: Wavetable,
+    sample_rate: f32,
+    frequency: f32,
+    velocity: f32,
+    phase: f32,
+    position: f32,
+    interpolation: Interpolation,
+    envelope: Envelope,
+}
+
+impl WavetableSynth {
+    pub fn new(sample_rate: f32, envelope: Envelope) -> Self {
+        WavetableSynth {
+            name: "Wavetable".to_string(),
+            file: None,
+            table: Wavetable::new(),
+            sample_rate,
+            frequency: 440.0,
+            velocity: 1.0,
+            phase: 0.0,
+            position: 0.0,
+            interpolation: Interpolation::Linear,
+            envelope,
+        }
+    }
+}
+
+impl Instrument for WavetableSynth {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&mut self) -> f32 {
+        let wave_result = self.table.read(
+            self.position,
+            self.phase,
+            self.frequency,
+            self.sample_rate,
+            self.interpolation,
+        );
+        self.phase = (self.phase + self.frequency / self.sample_rate).rem_euclid(1.0);
+
+        self.envelope.process(wave_result) * self.velocity
+    }
+
+    fn note_on(&mut self, frequency: f32, velocity: f32) {
+        self.frequency = frequency;
+        self.velocity = velocity;
+        self.envelope.start();
+    }
+
+    fn note_off(&mut self) {
+        self.envelope.stop();
+    }
+
+    fn get_envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    fn get_phase(&self) -> f32 {
+        self.phase
+    }
+
+    fn parameters(&self) -> Vec<Parameter> {
+        let interpolation = match self.interpolation {
+            Interpolation::Linear => 0,
+            Interpolation::Cubic => 1,
+        };
+
+        let mut parameters = vec![
+            Parameter::new("Position", self.position, 0.0, 1.0, 0.01),
+            Parameter::choice("Interpolation", interpolation, &INTERPOLATION_NAMES),
+        ];
+        parameters.extend(self.envelope.parameters());
+        parameters
+    }
+
+    fn set_parameter(&mut self, index: usize, value: f32) {
+        match index {
+            0 => self.position = value.clamp(0.0, 1.0),
+            1 if value >= 0.5 => self.interpolation = Interpolation::Cubic,
+            1 => self.interpolation = Interpolation::Linear,
+            index => self.envelope.set_parameter(index - 2, value),
+        }
+    }
+
+    //Frames are TABLE_SIZE samples long, a shorter file is played as a single cycle
+    fn load_file(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let sample = Sample::load(path)?;
+        self.table = Wavetable::from_sample(&sample)?;
+
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        self.name = format!("Wavetable {}", file);
+        self.file = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    fn loaded_file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+}
@@ -0,0 +1,131 @@
+use std::f32::consts::PI;
+
+use terminal_daw::{
+    fft::magnitude_spectrum,
+    generators::WaveType,
+    oscillator::{Oscillator, OscillatorQuality},
+};
+
+const SAMPLE_RATE: f32 = 48000.0;
+const LENGTH: usize = 1 << 16;
+//Bins either side of a harmonic that count as the harmonic, covers the window main lobe
+const LOBE: usize = 6;
+
+//Share of the energy that is not on a harmonic below Nyquist
+fn aliasing_ratio(wave_type: WaveType, quality: OscillatorQuality, frequency: f32) -> f32 {
+    let mut oscillator = Oscillator::new(wave_type, quality);
+    let signal: Vec<f32> = (0..LENGTH)
+        .map(|i| {
+            //Blackman-Harris window keeps leakage well below the thresholds
+            let x = 2.0 * PI * i as f32 / LENGTH as f32;
+            let window =
+                0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos();
+            oscillator.process(frequency, SAMPLE_RATE) * window
+        })
+        .collect();
+
+    let spectrum = magnitude_spectrum(&signal);
+    let bin_width = SAMPLE_RATE / LENGTH as f32;
+    let mut harmonic = vec![false; spectrum.len()];
+    let mut k = 1;
+    while k as f32 * frequency < SAMPLE_RATE / 2.0 {
+        let centre = (k as f32 * frequency / bin_width).round() as usize;
+        let end = (centre + LOBE + 1).min(harmonic.len());
+        harmonic[centre.saturating_sub(LOBE).min(end)..end].fill(true);
+        k += 1;
+    }
+
+    let (mut alias, mut total) = (0.0f64, 0.0f64);
+    //DC and the bins next to it are left out, the triangle and saw have no offset but the window does
+    for (bin, magnitude) in spectrum.iter().enumerate().skip(LOBE) {
+        let energy = (*magnitude as f64).powi(2);
+        total += energy;
+        if !harmonic[bin] {
+            alias += energy;
+        }
+    }
+
+    (alias / total) as f32
+}
+
+//High notes alias the most, 7040 Hz only has three harmonics below Nyquist
+const NOTES: [f32; 3] = [1760.0, 3520.0, 7040.0];
+const BAND_LIMITED: [WaveType; 3] = [WaveType::Square, WaveType::Triangle, WaveType::Saw];
+
+#[test]
+fn sine_does_not_alias_at_any_quality() {
+    for quality in [
+        OscillatorQuality::Draft,
+        OscillatorQuality::Standard,
+        OscillatorQuality::High,
+    ] {
+        for frequency in NOTES {
+            let ratio = aliasing_ratio(WaveType::Sine, quality, frequency);
+            assert!(
+                ratio < 1e-6,
+                "sine {} {} Hz: {:e}",
+                quality,
+                frequency,
+                ratio
+            );
+        }
+    }
+}
+
+#[test]
+fn standard_quality_keeps_aliasing_below_one_percent() {
+    for wave_type in BAND_LIMITED {
+        //The triangle only has corners, its aliases fall off much faster
+        let threshold = match wave_type {
+            WaveType::Triangle => 1e-3,
+            _ => 1e-2,
+        };
+
+        for frequency in NOTES {
+            let ratio = aliasing_ratio(wave_type, OscillatorQuality::Standard, frequency);
+            assert!(
+                ratio < threshold,
+                "{:?} {} Hz: {:e}",
+                wave_type,
+                frequency,
+                ratio
+            );
+        }
+    }
+}
+
+#[test]
+fn high_quality_does_not_alias() {
+    for wave_type in BAND_LIMITED {
+        for frequency in NOTES {
+            let ratio = aliasing_ratio(wave_type, OscillatorQuality::High, frequency);
+            assert!(
+                ratio < 1e-6,
+                "{:?} {} Hz: {:e}",
+                wave_type,
+                frequency,
+                ratio
+            );
+        }
+    }
+}
+
+#[test]
+fn quality_levels_reduce_aliasing() {
+    for wave_type in BAND_LIMITED {
+        for frequency in NOTES {
+            let draft = aliasing_ratio(wave_type, OscillatorQuality::Draft, frequency);
+            let standard = aliasing_ratio(wave_type, OscillatorQuality::Standard, frequency);
+            let high = aliasing_ratio(wave_type, OscillatorQuality::High, frequency);
+            assert!(
+                draft > standard && standard > high,
+                "{:?} {} Hz: {:e} {:e} {:e}",
+                wave_type,
+                frequency,
+                draft,
+                standard,
+                high
+            );
+        }
+    }
+}